@@ -0,0 +1,481 @@
+//! Game actions and the key bindings triggering them.
+//!
+//! Scenes shouldn't care which key does what, they react to `Action`s instead. `Bindings` map
+//! keyboard keys to actions. The default bindings follow the original game (the main menu uses
+//! the Polish initials: **N**owa gra, **W**czytaj grę, **K**oniec), they can be overridden with
+//! a bindings file.
+//!
+//! Every action belongs to a `Context`: the main menu and the screens reachable from it, the
+//! in-game screen or everywhere. Keys are bound per context, so a key can trigger a menu action
+//! and a different in-game action (the language-dependent menu initials don't get in the way of
+//! the in-game keys), while within a scene it triggers at most one action.
+//!
+//! # Bindings file format
+//!
+//! The file is a sequence of `action = KEY[, KEY...]` lines. Empty lines and lines starting with
+//! `#` are ignored. Binding an action replaces all of its default keys, binding it to nothing
+//! (`build =`) disables it. Binding a key takes it away from the actions of the same context (of
+//! all contexts for the actions available everywhere). Action names are the ones returned by
+//! `Action::name()`, key names are the ones returned by `key_name()`. Example:
+//!
+//! ```text
+//! # Scroll with WASD as well
+//! scroll-up = Up, W
+//! scroll-left = Left, A
+//! ```
+
+use crate::input::Input;
+use sdl2::keyboard::Scancode;
+use std::io::{self, BufRead};
+
+/// The name of the bindings file within the [configuration directory](../config/index.html).
+pub const BINDINGS_FILE_NAME: &str = "bindings.cfg";
+
+/// The number of control groups.
+pub const GROUPS: u8 = 10;
+
+/// Where actions can be triggered.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Context {
+    /// The main menu and the screens reachable from it.
+    Menu,
+    InGame,
+    /// Everywhere.
+    Global,
+}
+
+impl Context {
+    /// Can the actions of `self` be triggered in a scene of the `scene` context?
+    pub fn is_active_in(self, scene: Context) -> bool {
+        self == scene || self == Context::Global
+    }
+
+    /// Can the actions of the two contexts be triggered in the same scene (and so can't share
+    /// keys)?
+    fn overlaps(self, other: Context) -> bool {
+        self.is_active_in(other) || other.is_active_in(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    NewGame,
+    LoadGame,
+    Quit,
+    Options,
+    /// Leave the current screen.
+    Back,
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+    /// Select a control group, 0-based.
+    SelectGroup(u8),
+    Build,
+    /// Show or hide the most recent log messages.
+    ToggleLog,
+    /// Show or hide the debug overlay.
+    ToggleDebug,
+}
+
+impl Action {
+    /// All actions, in the order they're listed in the bindings file.
+    pub fn all() -> Vec<Action> {
+        let mut actions = vec![
+            Action::NewGame,
+            Action::LoadGame,
+            Action::Quit,
+            Action::Options,
+            Action::Back,
+            Action::ScrollUp,
+            Action::ScrollDown,
+            Action::ScrollLeft,
+            Action::ScrollRight,
+        ];
+        actions.extend((0..GROUPS).map(Action::SelectGroup));
+        actions.push(Action::Build);
+        actions.push(Action::ToggleLog);
+        actions.push(Action::ToggleDebug);
+        actions
+    }
+
+    /// The name of the action used in the bindings file.
+    pub fn name(self) -> String {
+        match self {
+            Action::NewGame => "new-game".to_string(),
+            Action::LoadGame => "load-game".to_string(),
+            Action::Quit => "quit".to_string(),
+            Action::Options => "options".to_string(),
+            Action::Back => "back".to_string(),
+            Action::ScrollUp => "scroll-up".to_string(),
+            Action::ScrollDown => "scroll-down".to_string(),
+            Action::ScrollLeft => "scroll-left".to_string(),
+            Action::ScrollRight => "scroll-right".to_string(),
+            // Groups are presented to the user as 1-based, the same as the keys on the keyboard.
+            Action::SelectGroup(group) => format!("select-group-{}", group + 1),
+            Action::Build => "build".to_string(),
+            Action::ToggleLog => "toggle-log".to_string(),
+            Action::ToggleDebug => "toggle-debug".to_string(),
+        }
+    }
+
+    /// The inverse of `name()`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::all()
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+
+    pub fn context(self) -> Context {
+        match self {
+            Action::NewGame | Action::LoadGame | Action::Quit | Action::Options => Context::Menu,
+            Action::ScrollUp
+            | Action::ScrollDown
+            | Action::ScrollLeft
+            | Action::ScrollRight
+            | Action::SelectGroup(_)
+            | Action::Build => Context::InGame,
+            Action::Back | Action::ToggleLog | Action::ToggleDebug => Context::Global,
+        }
+    }
+}
+
+/// A mapping from keys to actions. A key triggers at most one action in a context, an action can
+/// be triggered by multiple keys.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bindings {
+    bindings: Vec<(Scancode, Action)>,
+}
+
+impl Bindings {
+    /// The bindings used by the original game.
+    pub fn defaults() -> Bindings {
+        let mut bindings = vec![
+            (Scancode::N, Action::NewGame),
+            (Scancode::W, Action::LoadGame),
+            (Scancode::K, Action::Quit),
+            (Scancode::O, Action::Options),
+            (Scancode::Escape, Action::Back),
+            (Scancode::Up, Action::ScrollUp),
+            (Scancode::Down, Action::ScrollDown),
+            (Scancode::Left, Action::ScrollLeft),
+            (Scancode::Right, Action::ScrollRight),
+            (Scancode::B, Action::Build),
+            (Scancode::F12, Action::ToggleLog),
+            (Scancode::F3, Action::ToggleDebug),
+        ];
+        bindings.extend(
+            (0..GROUPS).map(|group| (digit_scancode(group + 1), Action::SelectGroup(group))),
+        );
+        Bindings { bindings }
+    }
+
+    /// Load bindings from a reader. The default bindings are used for actions the file doesn't
+    /// mention.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read or if the file contains
+    /// unknown actions, unknown keys or lines that can't be parsed.
+    pub fn load<T: io::Read>(reader: T) -> Result<Bindings, String> {
+        Bindings::load_with_defaults(Bindings::defaults(), reader)
+    }
+
+    /// Like `load()`, but with `defaults` used for actions the file doesn't mention (the
+    /// defaults of a language, for example).
+    pub fn load_with_defaults<T: io::Read>(
+        defaults: Bindings,
+        reader: T,
+    ) -> Result<Bindings, String> {
+        let mut bindings = defaults;
+        for (number, line) in io::BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {}", number + 1, message);
+            let (name, keys) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected action = KEY"))?;
+            let action = Action::from_name(name.trim())
+                .ok_or_else(|| error(&format!("unknown action {:?}", name.trim())))?;
+            let mut scancodes = Vec::new();
+            for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                scancodes.push(
+                    key_from_name(key).ok_or_else(|| error(&format!("unknown key {key:?}")))?,
+                );
+            }
+            bindings.rebind(action, &scancodes);
+        }
+        Ok(bindings)
+    }
+
+    /// Save the bindings to a writer, in a form `load()` can read.
+    pub fn save<T: io::Write>(&self, mut writer: T) -> io::Result<()> {
+        for action in Action::all() {
+            let keys = self
+                .keys(action)
+                .into_iter()
+                .map(key_name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(writer, "{} = {}", action.name(), keys)?;
+        }
+        Ok(())
+    }
+
+    /// Make `scancodes` (and only them) trigger `action`. The keys stop triggering the actions
+    /// they were bound to before in the contexts overlapping the action's one.
+    pub fn rebind(&mut self, action: Action, scancodes: &[Scancode]) {
+        let context = action.context();
+        self.bindings.retain(|(scancode, bound)| {
+            *bound != action && !(scancodes.contains(scancode) && bound.context().overlaps(context))
+        });
+        self.bindings
+            .extend(scancodes.iter().map(|scancode| (*scancode, action)));
+    }
+
+    /// The keys bound to an action.
+    pub fn keys(&self, action: Action) -> Vec<Scancode> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(scancode, _)| *scancode)
+            .collect()
+    }
+
+    /// The action triggered by a key in a scene of the `scene` context, if any.
+    pub fn action(&self, scancode: Scancode, scene: Context) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(bound, action)| *bound == scancode && action.context().is_active_in(scene))
+            .map(|(_, action)| *action)
+    }
+
+    /// Actions of a scene of the `scene` context triggered during the frame (their keys were
+    /// pressed), in binding order.
+    pub fn triggered(&self, input: &Input, scene: Context) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for (scancode, action) in &self.bindings {
+            if input.keys.is_pressed(*scancode)
+                && action.context().is_active_in(scene)
+                && !actions.contains(action)
+            {
+                actions.push(*action);
+            }
+        }
+        actions
+    }
+
+    /// Is `action` active during the frame (any of its keys is held)? This is what continuous
+    /// actions, like scrolling, should use.
+    pub fn is_active(&self, input: &Input, action: Action) -> bool {
+        self.bindings
+            .iter()
+            .any(|(scancode, bound)| *bound == action && input.keys.is_held(*scancode))
+    }
+}
+
+/// The scancode of a digit key in the main part of the keyboard.
+fn digit_scancode(digit: u8) -> Scancode {
+    match digit % 10 {
+        0 => Scancode::Num0,
+        digit => Scancode::from_i32(Scancode::Num1 as i32 + digit as i32 - 1).unwrap(),
+    }
+}
+
+/// Named keys other than letters, digits and function keys.
+const NAMED_KEYS: [(&str, Scancode); 15] = [
+    ("Up", Scancode::Up),
+    ("Down", Scancode::Down),
+    ("Left", Scancode::Left),
+    ("Right", Scancode::Right),
+    ("Space", Scancode::Space),
+    ("Return", Scancode::Return),
+    ("Escape", Scancode::Escape),
+    ("Tab", Scancode::Tab),
+    ("Backspace", Scancode::Backspace),
+    ("Delete", Scancode::Delete),
+    ("Insert", Scancode::Insert),
+    ("Home", Scancode::Home),
+    ("End", Scancode::End),
+    ("PageUp", Scancode::PageUp),
+    ("PageDown", Scancode::PageDown),
+];
+
+/// Get a key from its name as used in the bindings file: letters (`A`), digits (`1`), function
+/// keys (`F1`) and the names in `NAMED_KEYS`. The names are case-insensitive.
+pub fn key_from_name(name: &str) -> Option<Scancode> {
+    let upper = name.to_ascii_uppercase();
+    let mut chars = upper.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'A'..='Z'), None) => {
+            return Scancode::from_i32(Scancode::A as i32 + (c as i32 - 'A' as i32));
+        }
+        (Some(c @ '0'..='9'), None) => return Some(digit_scancode(c as u8 - b'0')),
+        _ => (),
+    }
+    if let Some(Ok(number @ 1..=12)) = upper.strip_prefix('F').map(str::parse::<i32>) {
+        return Scancode::from_i32(Scancode::F1 as i32 + number - 1);
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(_, scancode)| *scancode)
+}
+
+/// The inverse of `key_from_name()`. Keys without a name are presented as their scancode
+/// numbers, which `key_from_name()` won't accept.
+pub fn key_name(scancode: Scancode) -> String {
+    let code = scancode as i32;
+    if (Scancode::A as i32..=Scancode::Z as i32).contains(&code) {
+        ((b'A' + (code - Scancode::A as i32) as u8) as char).to_string()
+    } else if scancode == Scancode::Num0 {
+        "0".to_string()
+    } else if (Scancode::Num1 as i32..=Scancode::Num9 as i32).contains(&code) {
+        (code - Scancode::Num1 as i32 + 1).to_string()
+    } else if (Scancode::F1 as i32..=Scancode::F12 as i32).contains(&code) {
+        format!("F{}", code - Scancode::F1 as i32 + 1)
+    } else {
+        NAMED_KEYS
+            .iter()
+            .find(|(_, named)| *named == scancode)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| format!("#{code}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{key_from_name, key_name, Action, Bindings, Context};
+    use crate::input::{Input, MousePosition};
+    use sdl2::keyboard::Scancode;
+
+    #[test]
+    fn test_action_names_round_trip() {
+        for action in Action::all() {
+            assert_eq!(Action::from_name(&action.name()), Some(action));
+        }
+        assert_eq!(
+            Action::from_name("select-group-1"),
+            Some(Action::SelectGroup(0))
+        );
+        assert_eq!(Action::from_name("fly"), None);
+    }
+
+    #[test]
+    fn test_key_names_work() {
+        assert_eq!(key_from_name("n"), Some(Scancode::N));
+        assert_eq!(key_from_name("0"), Some(Scancode::Num0));
+        assert_eq!(key_from_name("5"), Some(Scancode::Num5));
+        assert_eq!(key_from_name("F12"), Some(Scancode::F12));
+        assert_eq!(key_from_name("pageup"), Some(Scancode::PageUp));
+        assert_eq!(key_from_name("F13"), None);
+        assert_eq!(key_from_name("Foo"), None);
+        for key in ["A", "Z", "0", "9", "F1", "Left", "Escape"] {
+            assert_eq!(key_name(key_from_name(key).unwrap()), key);
+        }
+    }
+
+    #[test]
+    fn test_defaults_match_the_original_game() {
+        let bindings = Bindings::defaults();
+        assert_eq!(
+            bindings.action(Scancode::N, Context::Menu),
+            Some(Action::NewGame)
+        );
+        assert_eq!(
+            bindings.action(Scancode::W, Context::Menu),
+            Some(Action::LoadGame)
+        );
+        assert_eq!(
+            bindings.action(Scancode::K, Context::Menu),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            bindings.action(Scancode::Num1, Context::InGame),
+            Some(Action::SelectGroup(0))
+        );
+        assert_eq!(
+            bindings.action(Scancode::Num0, Context::InGame),
+            Some(Action::SelectGroup(9))
+        );
+    }
+
+    #[test]
+    fn test_loading_overrides_defaults() {
+        let file = "# comment\n\nscroll-up = Up, W\nbuild =\n";
+        let bindings = Bindings::load(file.as_bytes()).unwrap();
+        assert_eq!(
+            bindings.keys(Action::ScrollUp),
+            vec![Scancode::Up, Scancode::W]
+        );
+        // Load-game is a menu action, it keeps W.
+        assert_eq!(bindings.keys(Action::LoadGame), vec![Scancode::W]);
+        assert_eq!(bindings.keys(Action::Build), vec![]);
+        assert_eq!(bindings.keys(Action::NewGame), vec![Scancode::N]);
+
+        // Keys are taken away from the actions of the same context and from the global ones.
+        let file = "quit = N\nbuild = Escape\n";
+        let bindings = Bindings::load(file.as_bytes()).unwrap();
+        assert_eq!(bindings.keys(Action::NewGame), vec![]);
+        assert_eq!(bindings.keys(Action::Back), vec![]);
+    }
+
+    #[test]
+    fn test_keys_are_bound_per_context() {
+        let mut bindings = Bindings::defaults();
+        bindings.rebind(Action::Build, &[Scancode::N]);
+        assert_eq!(
+            bindings.action(Scancode::N, Context::Menu),
+            Some(Action::NewGame)
+        );
+        assert_eq!(
+            bindings.action(Scancode::N, Context::InGame),
+            Some(Action::Build)
+        );
+        assert_eq!(
+            bindings.action(Scancode::Escape, Context::InGame),
+            Some(Action::Back)
+        );
+
+        let mut input = Input::idle(MousePosition::new(0, 0));
+        input.keys.pressed.insert(Scancode::N);
+        input.keys.pressed.insert(Scancode::F3);
+        assert_eq!(
+            bindings.triggered(&input, Context::Menu),
+            vec![Action::NewGame, Action::ToggleDebug]
+        );
+        assert_eq!(
+            bindings.triggered(&input, Context::InGame),
+            vec![Action::ToggleDebug, Action::Build]
+        );
+        assert_eq!(
+            bindings.triggered(&input, Context::Global),
+            vec![Action::ToggleDebug]
+        );
+    }
+
+    #[test]
+    fn test_loading_errors_are_reported() {
+        assert!(Bindings::load("fly = F".as_bytes())
+            .unwrap_err()
+            .starts_with("Line 1:"));
+        assert!(Bindings::load("\nquit = Nope".as_bytes())
+            .unwrap_err()
+            .starts_with("Line 2:"));
+        assert!(Bindings::load("quit".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_saving_and_loading_round_trips() {
+        let mut bindings = Bindings::defaults();
+        bindings.rebind(Action::Quit, &[Scancode::Escape, Scancode::Q]);
+        let mut buffer = Vec::new();
+        bindings.save(&mut buffer).unwrap();
+        let loaded = Bindings::load(&buffer[..]).unwrap();
+        for action in Action::all() {
+            assert_eq!(loaded.keys(action), bindings.keys(action));
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Unit animation and sprite drawing.
+//!
+//! An `Animation` picks the frame to show from the unit's action, the direction it faces and
+//! the simulation tick (see `units::TICK_MS`), the frames themselves come from a
+//! `SpriteSource`.
+//!
+//! This is only the runtime, the unit sprites themselves aren't there yet: the mover and shadow
+//! sprites haven't been decoded, so `GrafdatSprites` only has the dying frames (graf.dat `dead`,
+//! shared by all unit kinds and directions) and nothing for idle, walking or attacking units or
+//! for shadows. Units are drawn as placeholders until then. The frame counts and timings of the
+//! actions other than dying are guesses as well (see `Action::timing()`).
+//!
+//! Player colors and highlights aren't part of the frames, a sprite is drawn with a color
+//! translation table from `playercolors::PlayerColors` instead.
+//!
+//! Sprites are collected for a frame and drawn together with `draw_sprites()`: first all the
+//! shadows, then the sprites ordered by depth, so that units lower on the screen are drawn over
+//! the ones behind them.
+
+use crate::grafdat::Grafdat;
+use crate::image13h::{Image13h, Rect, Remap};
+use crate::map::{TILE_HEIGHT, TILE_WIDTH};
+use crate::pathfinding::Position;
+use crate::units::UnitKind;
+
+/// The number of dying frames in graf.dat.
+pub const DEAD_FRAMES: usize = 3;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Direction {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Direction {
+    /// The direction of a step between neighboring cells, `None` if the cells are the same.
+    pub fn of_step(from: Position, to: Position) -> Option<Direction> {
+        let dx = (to.0 as isize - from.0 as isize).signum();
+        let dy = (to.1 as isize - from.1 as isize).signum();
+        Some(match (dx, dy) {
+            (0, -1) => Direction::Up,
+            (1, -1) => Direction::UpRight,
+            (1, 0) => Direction::Right,
+            (1, 1) => Direction::DownRight,
+            (0, 1) => Direction::Down,
+            (-1, 1) => Direction::DownLeft,
+            (-1, 0) => Direction::Left,
+            (-1, -1) => Direction::UpLeft,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    Idle,
+    Walk,
+    Attack,
+    Die,
+}
+
+impl Action {
+    /// How many frames the action has and how many ticks each of them is shown for.
+    // TODO Check the frame counts and timings against the mover sprites once they're decoded.
+    pub fn timing(self) -> (usize, u32) {
+        match self {
+            Action::Idle => (1, 1),
+            Action::Walk => (4, 2),
+            Action::Attack => (3, 3),
+            Action::Die => (DEAD_FRAMES, 4),
+        }
+    }
+
+    /// Does the animation start over after the last frame? Dying stops at the last one.
+    pub fn loops(self) -> bool {
+        self != Action::Die
+    }
+}
+
+/// The animation state of a unit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Animation {
+    pub action: Action,
+    pub direction: Direction,
+    /// The tick the action started in.
+    started: u32,
+}
+
+impl Animation {
+    pub fn new(action: Action, direction: Direction, tick: u32) -> Animation {
+        Animation {
+            action,
+            direction,
+            started: tick,
+        }
+    }
+
+    /// Switch to another action, starting its animation from the first frame. Setting the
+    /// current action again doesn't restart it.
+    pub fn set_action(&mut self, action: Action, tick: u32) {
+        if action != self.action {
+            self.action = action;
+            self.started = tick;
+        }
+    }
+
+    /// The frame to show in `tick`.
+    pub fn frame(&self, tick: u32) -> usize {
+        let (frames, ticks_per_frame) = self.action.timing();
+        let frame = (tick.saturating_sub(self.started) / ticks_per_frame) as usize;
+        if self.action.loops() {
+            frame % frames
+        } else {
+            frame.min(frames - 1)
+        }
+    }
+
+    /// Has a non-looping animation reached its last frame?
+    pub fn is_finished(&self, tick: u32) -> bool {
+        !self.action.loops() && self.frame(tick) == self.action.timing().0 - 1
+    }
+}
+
+/// Where the sprite images come from.
+pub trait SpriteSource {
+    /// The image of a frame, `None` if there's none.
+    fn sprite(
+        &self,
+        kind: UnitKind,
+        action: Action,
+        direction: Direction,
+        frame: usize,
+    ) -> Option<&Image13h>;
+
+    /// The shadow drawn under the unit, `None` if there's none.
+    fn shadow(&self, kind: UnitKind, direction: Direction) -> Option<&Image13h>;
+}
+
+/// The sprites decoded from graf.dat so far.
+pub struct GrafdatSprites<'a> {
+    pub grafdat: &'a Grafdat,
+}
+
+impl<'a> SpriteSource for GrafdatSprites<'a> {
+    fn sprite(
+        &self,
+        _kind: UnitKind,
+        action: Action,
+        _direction: Direction,
+        frame: usize,
+    ) -> Option<&Image13h> {
+        match action {
+            Action::Die => Some(self.grafdat.dead(frame)),
+            // TODO Use the mover sprites once they're decoded.
+            Action::Idle | Action::Walk | Action::Attack => None,
+        }
+    }
+
+    fn shadow(&self, _kind: UnitKind, _direction: Direction) -> Option<&Image13h> {
+        // TODO Use the shadow sprites once they're decoded.
+        None
+    }
+}
+
+/// A sprite to draw, see `draw_sprites()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite<'a> {
+    pub image: &'a Image13h,
+    pub shadow: Option<&'a Image13h>,
+    /// The color translation table to draw the sprite (but not the shadow) with, if any.
+    pub remap: Option<&'a Remap>,
+    /// The screen position of the top left corner.
+    pub x: isize,
+    pub y: isize,
+}
+
+impl<'a> Sprite<'a> {
+    /// A sprite standing in a cell whose top left corner is at the screen position `x`, `y`:
+    /// centered horizontally, with its bottom at the bottom of the cell.
+    pub fn in_cell(image: &'a Image13h, shadow: Option<&'a Image13h>, x: isize, y: isize) -> Self {
+        Sprite {
+            image,
+            shadow,
+            remap: None,
+            x: x + (TILE_WIDTH as isize - image.width() as isize) / 2,
+            y: y + TILE_HEIGHT as isize - image.height() as isize,
+        }
+    }
+
+    /// Sprites with greater depth are drawn later, over the ones with smaller depth.
+    fn depth(&self) -> (isize, isize) {
+        (self.y + self.image.height() as isize, self.x)
+    }
+}
+
+/// Draw the sprites within `clip`: the shadows first, then the sprites in depth order (the
+/// order of `sprites` breaks ties). The shadows are drawn at the same positions as their
+/// sprites.
+pub fn draw_sprites(image: &mut Image13h, sprites: &[Sprite], clip: &Rect) {
+    let mut ordered: Vec<&Sprite> = sprites.iter().collect();
+    // The sort is stable, so the order of sprites with equal depth is kept.
+    ordered.sort_by_key(|sprite| sprite.depth());
+    for sprite in &ordered {
+        if let Some(shadow) = sprite.shadow {
+            image.blit_within(shadow, sprite.x, sprite.y, clip);
+        }
+    }
+    for sprite in &ordered {
+        match sprite.remap {
+            Some(remap) => {
+                image.blit_remapped_within(sprite.image, sprite.x, sprite.y, clip, remap)
+            }
+            None => image.blit_within(sprite.image, sprite.x, sprite.y, clip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::{draw_sprites, Action, Animation, Direction, Sprite};
+    use crate::image13h::{Image13h, Rect};
+
+    #[test]
+    fn test_frames_follow_ticks() {
+        let mut animation = Animation::new(Action::Walk, Direction::Down, 10);
+        let frames: Vec<_> = (10..20).map(|tick| animation.frame(tick)).collect();
+        assert_eq!(frames, vec![0, 0, 1, 1, 2, 2, 3, 3, 0, 0]);
+        animation.set_action(Action::Walk, 13);
+        assert_eq!(animation.frame(14), 2);
+
+        animation.set_action(Action::Die, 20);
+        assert_eq!(animation.frame(20), 0);
+        assert!(!animation.is_finished(27));
+        assert_eq!(animation.frame(100), 2);
+        assert!(animation.is_finished(100));
+    }
+
+    #[test]
+    fn test_directions_work() {
+        assert_eq!(Direction::of_step((3, 3), (4, 2)), Some(Direction::UpRight));
+        assert_eq!(Direction::of_step((3, 3), (2, 3)), Some(Direction::Left));
+        assert_eq!(Direction::of_step((3, 3), (3, 3)), None);
+    }
+
+    #[test]
+    fn test_sprites_are_drawn_in_depth_order() {
+        let front = Image13h::filled_with_color(4, 4, 1);
+        let back = Image13h::filled_with_color(4, 4, 2);
+        let sprite = |image, x, y| Sprite {
+            image,
+            shadow: None,
+            remap: None,
+            x,
+            y,
+        };
+        let mut screen = Image13h::empty(10, 10);
+        let sprites = [sprite(&front, 2, 3), sprite(&back, 0, 0)];
+        draw_sprites(&mut screen, &sprites, &Rect::from_ranges(0..10, 0..5));
+        assert_eq!(screen.line(3)[..7], [2, 2, 1, 1, 1, 1, 0]);
+        assert_eq!(screen.line(0)[..5], [2, 2, 2, 2, 0]);
+        // Sprites are clipped.
+        assert_eq!(screen.line(5)[2], 0);
+
+        // Shadows are beneath all sprites, even the ones further back.
+        let shadow = Image13h::filled_with_color(6, 4, 3);
+        let mut screen = Image13h::empty(10, 10);
+        let sprites = [
+            Sprite {
+                shadow: Some(&shadow),
+                ..sprite(&front, 3, 3)
+            },
+            sprite(&back, 7, 0),
+        ];
+        draw_sprites(&mut screen, &sprites, &Rect::from_ranges(0..10, 0..10));
+        assert_eq!(screen.line(3), [0, 0, 0, 1, 1, 1, 1, 2, 2, 2]);
+        assert_eq!(screen.line(4)[6..], [1, 3, 3, 0]);
+
+        // The remap table only applies to the sprite.
+        let mut remap = [0; 256];
+        remap[1] = 5;
+        let sprites = [Sprite {
+            remap: Some(&remap),
+            ..sprites[0]
+        }];
+        draw_sprites(&mut screen, &sprites, &Rect::from_ranges(0..10, 0..10));
+        assert_eq!(screen.line(4)[6..], [5, 3, 3, 0]);
+    }
+
+    #[test]
+    fn test_sprites_stand_in_cells() {
+        let image = Image13h::empty(10, 20);
+        let sprite = Sprite::in_cell(&image, None, 100, 50);
+        assert_eq!((sprite.x, sprite.y), (103, 44));
+    }
+}
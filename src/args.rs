@@ -0,0 +1,286 @@
+//! Command-line arguments of the `openpol` binary.
+
+use crate::log::Level;
+use crate::settings::{MAX_SCALE, MIN_SCALE};
+use std::path;
+
+/// The environment variable the game directory can be passed in.
+pub const GAME_DIR_VARIABLE: &str = "OPENPOL_GAME_DIR";
+
+pub const USAGE: &str = "Usage: openpol [OPTIONS] [GAMEDIR]
+
+GAMEDIR is the directory with the original game's files. When not given, the OPENPOL_GAME_DIR
+environment variable or the game-dir setting is used.
+
+Options:
+  --skip-intro                Go straight to the main menu
+  --scale N                   Make the window N (1 to 6) times larger than 320x200
+  --fullscreen                Run in fullscreen
+  --mute                      Don't play any audio
+  --palette N                 Use pal.dat palette N for the menus and the game
+  --start-scene SCENE         Start with SCENE: intro, menu or mission:NAME
+  --record FILE               Record the input to FILE
+  --replay FILE               Replay the input recorded in FILE
+  --headless                  Don't open a window (implies --mute), requires --replay or --ticks
+  --ticks N                   Stop after N ticks
+  --screenshot FILE           Save the last frame to FILE (in PPM format) when stopping
+  --log-level LEVEL           Log messages of LEVEL and above: error, warn, info, debug or trace
+  --log-file FILE             Write the log to FILE as well
+  --version                   Print the version and exit
+  --help                      Print this message and exit";
+
+/// Where does the game start.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StartScene {
+    Intro,
+    Menu,
+    Mission(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Options {
+    pub game_dir: Option<path::PathBuf>,
+    pub skip_intro: bool,
+    pub scale: Option<u32>,
+    pub fullscreen: bool,
+    pub mute: bool,
+    pub palette: Option<usize>,
+    pub start_scene: Option<StartScene>,
+    pub record: Option<path::PathBuf>,
+    pub replay: Option<path::PathBuf>,
+    pub headless: bool,
+    pub ticks: Option<u64>,
+    pub screenshot: Option<path::PathBuf>,
+    pub log_level: Option<Level>,
+    pub log_file: Option<path::PathBuf>,
+}
+
+impl Options {
+    /// Are the user's settings, key bindings, message catalogs and saved games used? Not when
+    /// recording or replaying, the game starts from the built-in defaults then (see `replay`).
+    pub fn uses_config(&self) -> bool {
+        self.record.is_none() && self.replay.is_none()
+    }
+
+    /// The scene to start with, taking `--skip-intro` into account.
+    pub fn start_scene(&self) -> StartScene {
+        match &self.start_scene {
+            Some(scene) => scene.clone(),
+            None if self.skip_intro => StartScene::Menu,
+            None => StartScene::Intro,
+        }
+    }
+
+    /// The game directory: the one passed on the command line, the one from the environment
+    /// variable or the one from the settings, in this order.
+    pub fn resolve_game_dir<F: Fn(&str) -> Option<std::ffi::OsString>>(
+        &self,
+        var: F,
+        from_settings: Option<&str>,
+    ) -> Result<path::PathBuf, String> {
+        self.game_dir
+            .clone()
+            .or_else(|| var(GAME_DIR_VARIABLE).map(path::PathBuf::from))
+            .or_else(|| from_settings.map(path::PathBuf::from))
+            .ok_or_else(|| format!("No game directory given\n\n{USAGE}"))
+    }
+}
+
+/// What the user asked for.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Version,
+    Help,
+}
+
+/// Parse the arguments (without the program name).
+pub fn parse<T: IntoIterator<Item = String>>(args: T) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut options = Options {
+        game_dir: None,
+        skip_intro: false,
+        scale: None,
+        fullscreen: false,
+        mute: false,
+        palette: None,
+        start_scene: None,
+        record: None,
+        replay: None,
+        headless: false,
+        ticks: None,
+        screenshot: None,
+        log_level: None,
+        log_file: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{arg} requires a value\n\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "--version" => return Ok(Command::Version),
+            "--help" | "-h" => return Ok(Command::Help),
+            "--skip-intro" => options.skip_intro = true,
+            "--scale" => options.scale = Some(parse_number(&arg, &value()?)?),
+            "--fullscreen" => options.fullscreen = true,
+            "--mute" => options.mute = true,
+            "--palette" => options.palette = Some(parse_number(&arg, &value()?)?),
+            "--start-scene" => options.start_scene = Some(parse_scene(&value()?)?),
+            "--record" => options.record = Some(value()?.into()),
+            "--replay" => options.replay = Some(value()?.into()),
+            "--headless" => options.headless = true,
+            "--ticks" => options.ticks = Some(parse_number(&arg, &value()?)?),
+            "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--log-level" => {
+                let level = value()?;
+                options.log_level = Some(
+                    Level::from_name(&level)
+                        .ok_or_else(|| format!("Unknown log level {level:?}"))?,
+                );
+            }
+            "--log-file" => options.log_file = Some(value()?.into()),
+            _ if options.game_dir.is_none() && !arg.starts_with('-') => {
+                options.game_dir = Some(arg.into())
+            }
+            _ => return Err(format!("Unexpected argument {arg:?}\n\n{USAGE}")),
+        }
+    }
+
+    if options.record.is_some() && options.replay.is_some() {
+        return Err("--record and --replay can't be used together".to_string());
+    }
+    if options.palette.is_some() && options.replay.is_some() {
+        return Err(
+            "--palette can't be used with --replay, the recorded palette is used".to_string(),
+        );
+    }
+    if options.headless {
+        if options.replay.is_none() && options.ticks.is_none() {
+            return Err("--headless requires --replay or --ticks".to_string());
+        }
+        options.mute = true;
+    }
+    if let Some(scale) = options.scale {
+        if !(MIN_SCALE..=MAX_SCALE).contains(&scale) {
+            return Err(format!(
+                "--scale has to be between {MIN_SCALE} and {MAX_SCALE}"
+            ));
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{name} requires a number, got {value:?}"))
+}
+
+fn parse_scene(value: &str) -> Result<StartScene, String> {
+    match value {
+        "intro" => Ok(StartScene::Intro),
+        "menu" => Ok(StartScene::Menu),
+        _ => match value.strip_prefix("mission:") {
+            Some(name) if !name.is_empty() => Ok(StartScene::Mission(name.to_string())),
+            _ => Err(format!("Unknown scene {value:?}")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::{parse, Command, Options, StartScene, GAME_DIR_VARIABLE};
+    use crate::log::Level;
+    use std::path;
+
+    fn parse_options(args: &[&str]) -> Result<Options, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(options) => Ok(options),
+            command => panic!("Unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_parsing_works() {
+        let options = parse_options(&[
+            "--scale",
+            "3",
+            "game",
+            "--mute",
+            "--start-scene",
+            "mission:m01",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        assert_eq!(options.game_dir, Some(path::PathBuf::from("game")));
+        assert_eq!(options.scale, Some(3));
+        assert!(options.mute);
+        assert!(!options.fullscreen);
+        assert_eq!(
+            options.start_scene(),
+            StartScene::Mission("m01".to_string())
+        );
+        assert_eq!(options.log_level, Some(Level::Debug));
+
+        let options = parse_options(&["--skip-intro"]).unwrap();
+        assert_eq!(options.start_scene(), StartScene::Menu);
+        assert_eq!(parse_options(&[]).unwrap().start_scene(), StartScene::Intro);
+
+        assert_eq!(
+            parse(vec!["game".to_string(), "--version".to_string()]),
+            Ok(Command::Version)
+        );
+    }
+
+    #[test]
+    fn test_headless_works() {
+        let options = parse_options(&["--headless", "--ticks", "10", "--screenshot", "a.ppm"]);
+        let options = options.unwrap();
+        assert!(options.mute);
+        assert_eq!(options.ticks, Some(10));
+        assert_eq!(options.screenshot, Some(path::PathBuf::from("a.ppm")));
+        assert!(parse_options(&["--headless"]).is_err());
+        assert!(parse_options(&["--headless", "--replay", "file"]).is_ok());
+    }
+
+    #[test]
+    fn test_errors_are_reported() {
+        assert!(parse_options(&["--scale"]).is_err());
+        assert!(parse_options(&["--scale", "x"]).is_err());
+        assert!(parse_options(&["--scale", "0"]).is_err());
+        assert!(parse_options(&["--scale", "7"]).is_err());
+        assert!(parse_options(&["--start-scene", "credits"]).is_err());
+        assert!(parse_options(&["--start-scene", "mission:"]).is_err());
+        assert!(parse_options(&["--log-level", "loud"]).is_err());
+        assert!(parse_options(&["--record", "a", "--replay", "b"]).is_err());
+        assert!(parse_options(&["--palette", "1", "--replay", "b"]).is_err());
+        assert!(parse_options(&["a", "b"]).is_err());
+        assert!(parse_options(&["--frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn test_game_dir_resolution_works() {
+        let options = parse_options(&[]).unwrap();
+        let env = |name: &str| {
+            assert_eq!(name, GAME_DIR_VARIABLE);
+            Some("from-env".into())
+        };
+        assert_eq!(
+            options.resolve_game_dir(env, Some("from-settings")),
+            Ok(path::PathBuf::from("from-env"))
+        );
+        assert_eq!(
+            options.resolve_game_dir(|_| None, Some("from-settings")),
+            Ok(path::PathBuf::from("from-settings"))
+        );
+        assert!(options.resolve_game_dir(|_| None, None).is_err());
+
+        let options = parse_options(&["from-args"]).unwrap();
+        assert_eq!(
+            options.resolve_game_dir(env, None),
+            Ok(path::PathBuf::from("from-args"))
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Auto-tiling: picking the terrain variants that fit the neighboring cells.
+//!
+//! Water, roads, bridges and palisades have variants for the different shapes their neighborhood
+//! can take (a shoreline bending around a corner, a road junction, the end of a bridge and so
+//! on). Use `place()` to put terrain on the map, it picks the right variant for the cell and
+//! updates the neighbors, or `refresh_all()` to fix up a whole map after generating it. Other
+//! terrains aren't neighbor-dependent and keep their variants.
+//!
+//! Which variant has which shape is a working hypothesis (based on how many variants there are),
+//! the tables below are what needs changing once the pictures are examined more closely:
+//!
+//! * Water (8-neighborhood): with land on any side the variant is the mask of water sides
+//!   (`UP`, `RIGHT`, `DOWN`, `LEFT`, 0..15). Surrounded by water on all sides it's 15 (open
+//!   water), or 15 + the mask of land corners (`UP_LEFT`, `UP_RIGHT`, `DOWN_RIGHT`, `DOWN_LEFT`,
+//!   16..31). Variants 31..39 aren't picked automatically. Bridges count as water, so do the
+//!   cells beyond the map edge.
+//! * Roads (4-neighborhood): the mask of the sides with a road or a bridge (0..16). Variants
+//!   16..21 aren't picked automatically.
+//! * Bridges: horizontal (0..4) and vertical (4..8) ones, a single-cell bridge, the start, the
+//!   middle and the end of a longer bridge. A road placed on water becomes a bridge.
+//! * Palisades (4-neighborhood): a post, horizontal and vertical segments, four corners, four
+//!   T-junctions and a cross, see `PALISADE_VARIANTS`.
+
+use crate::map::{Map, Terrain, Tile};
+
+pub const UP: u8 = 1;
+pub const RIGHT: u8 = 1 << 1;
+pub const DOWN: u8 = 1 << 2;
+pub const LEFT: u8 = 1 << 3;
+
+pub const UP_LEFT: u8 = 1;
+pub const UP_RIGHT: u8 = 1 << 1;
+pub const DOWN_RIGHT: u8 = 1 << 2;
+pub const DOWN_LEFT: u8 = 1 << 3;
+
+/// The variant of water surrounded by water.
+const OPEN_WATER: u8 = 15;
+
+/// The palisade variants indexed with the mask of the sides with palisades. Segments ending at a
+/// side are drawn as if they went on.
+const PALISADE_VARIANTS: [u8; 16] = [
+    0,  // a post
+    2,  // UP: vertical
+    1,  // RIGHT: horizontal
+    3,  // UP, RIGHT: a corner
+    2,  // DOWN: vertical
+    2,  // UP, DOWN: vertical
+    4,  // RIGHT, DOWN: a corner
+    7,  // UP, RIGHT, DOWN: a T-junction
+    1,  // LEFT: horizontal
+    6,  // UP, LEFT: a corner
+    1,  // RIGHT, LEFT: horizontal
+    10, // UP, RIGHT, LEFT: a T-junction
+    5,  // DOWN, LEFT: a corner
+    9,  // UP, DOWN, LEFT: a T-junction
+    8,  // RIGHT, DOWN, LEFT: a T-junction
+    11, // a cross
+];
+
+/// The first vertical bridge variant.
+const VERTICAL_BRIDGE: u8 = 4;
+
+/// The offsets of the 4-neighbors with their masks.
+const SIDES: [(isize, isize, u8); 4] = [(0, -1, UP), (1, 0, RIGHT), (0, 1, DOWN), (-1, 0, LEFT)];
+
+/// The offsets of the diagonal neighbors with their masks.
+const CORNERS: [(isize, isize, u8); 4] = [
+    (-1, -1, UP_LEFT),
+    (1, -1, UP_RIGHT),
+    (1, 1, DOWN_RIGHT),
+    (-1, 1, DOWN_LEFT),
+];
+
+/// Put `terrain` at `x`, `y` (see `Map::set_tile()`) and pick the variants of the cell and of
+/// its neighbors. A road placed on water (or on a bridge) becomes a bridge. Positions outside of
+/// the map are ignored.
+pub fn place(map: &mut Map, x: usize, y: usize, terrain: Terrain) {
+    let current = match map.get(x, y) {
+        Some(cell) => cell.tile.terrain,
+        None => return,
+    };
+    let terrain = match (terrain, current) {
+        (Terrain::Road, Terrain::Water) | (Terrain::Road, Terrain::Bridge) => Terrain::Bridge,
+        _ => terrain,
+    };
+    if current != terrain {
+        map.set_tile(x, y, Tile::new(terrain, 0));
+    }
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if let Some((x, y)) = offset(map, x, y, dx, dy) {
+                refresh(map, x, y);
+            }
+        }
+    }
+}
+
+/// Pick the variant of the cell at `x`, `y` to fit its neighbors. Only the variant changes, the
+/// other properties of the cell stay as they are.
+pub fn refresh(map: &mut Map, x: usize, y: usize) {
+    if let Some(variant) = variant(map, x, y) {
+        map.get_mut(x, y).unwrap().tile.variant = variant;
+    }
+}
+
+/// Pick the variants of all cells.
+pub fn refresh_all(map: &mut Map) {
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            refresh(map, x, y);
+        }
+    }
+}
+
+/// The variant that fits the neighbors of the cell at `x`, `y`, `None` if the cell's terrain
+/// isn't neighbor-dependent (or the position is outside of the map).
+pub fn variant(map: &Map, x: usize, y: usize) -> Option<u8> {
+    let terrain = map.get(x, y)?.tile.terrain;
+    let is = |dx: isize, dy: isize, terrains: &[Terrain], beyond_map: bool| {
+        offset(map, x, y, dx, dy)
+            .map(|(x, y)| terrains.contains(&map.get(x, y).unwrap().tile.terrain))
+            .unwrap_or(beyond_map)
+    };
+    let sides = |terrains: &[Terrain], beyond_map: bool| {
+        SIDES
+            .iter()
+            .filter(|(dx, dy, _)| is(*dx, *dy, terrains, beyond_map))
+            .fold(0, |mask, (_, _, bit)| mask | bit)
+    };
+    let watery = [Terrain::Water, Terrain::Bridge];
+    let roads = [Terrain::Road, Terrain::Bridge];
+    match terrain {
+        Terrain::Water => Some(match sides(&watery, true) {
+            mask if mask != UP | RIGHT | DOWN | LEFT => mask,
+            _ => {
+                let land = CORNERS
+                    .iter()
+                    .filter(|(dx, dy, _)| !is(*dx, *dy, &watery, true))
+                    .fold(0, |mask, (_, _, bit)| mask | bit);
+                OPEN_WATER + land
+            }
+        }),
+        Terrain::Road => Some(sides(&roads, false)),
+        Terrain::Palisade => Some(PALISADE_VARIANTS[sides(&[Terrain::Palisade], false) as usize]),
+        Terrain::Bridge => {
+            let connected = sides(&roads, false);
+            let bridges = sides(&[Terrain::Bridge], false);
+            let horizontal = connected & (LEFT | RIGHT) != 0 || connected & (UP | DOWN) == 0;
+            let (base, start, end) = if horizontal {
+                (0, LEFT, RIGHT)
+            } else {
+                (VERTICAL_BRIDGE, UP, DOWN)
+            };
+            Some(
+                base + match (bridges & start != 0, bridges & end != 0) {
+                    (false, false) => 0,
+                    (false, true) => 1,
+                    (true, true) => 2,
+                    (true, false) => 3,
+                },
+            )
+        }
+        _ => None,
+    }
+}
+
+/// The position `dx`, `dy` away from `x`, `y`, `None` if it's outside of the map.
+fn offset(map: &Map, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+    let (x, y) = (x as isize + dx, y as isize + dy);
+    if x < 0 || y < 0 || !map.contains(x as usize, y as usize) {
+        return None;
+    }
+    Some((x as usize, y as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::autotile::{place, refresh_all, variant, DOWN, LEFT, RIGHT, UP, UP_LEFT};
+    use crate::map::{Map, Terrain, Tile};
+
+    fn grass(width: usize, height: usize) -> Map {
+        Map::new(width, height, Tile::new(Terrain::Grass, 0))
+    }
+
+    fn terrain(map: &Map, x: usize, y: usize) -> (Terrain, u8) {
+        let tile = map.get(x, y).unwrap().tile;
+        (tile.terrain, tile.variant)
+    }
+
+    #[test]
+    fn test_shorelines_work() {
+        let mut map = grass(4, 4);
+        for (x, y) in [(1, 1), (2, 1), (1, 2), (2, 2)].iter().copied() {
+            place(&mut map, x, y, Terrain::Water);
+        }
+        // The top left corner of the lake has water on the right and below.
+        assert_eq!(terrain(&map, 1, 1), (Terrain::Water, RIGHT | DOWN));
+        assert_eq!(terrain(&map, 2, 2), (Terrain::Water, UP | LEFT));
+        assert_eq!(variant(&map, 0, 0), None);
+
+        // Water beyond the map edge doesn't make a shore, land in a corner does.
+        let mut map = Map::new(3, 3, Tile::new(Terrain::Water, 0));
+        map.set_tile(0, 0, Tile::new(Terrain::Grass, 0));
+        refresh_all(&mut map);
+        assert_eq!(terrain(&map, 2, 2).1, 15);
+        assert_eq!(terrain(&map, 1, 1).1, 15 + UP_LEFT);
+        assert_eq!(terrain(&map, 1, 0).1, UP | RIGHT | DOWN);
+    }
+
+    #[test]
+    fn test_roads_work() {
+        let mut map = grass(3, 3);
+        place(&mut map, 1, 0, Terrain::Road);
+        place(&mut map, 1, 1, Terrain::Road);
+        place(&mut map, 2, 1, Terrain::Road);
+        assert_eq!(terrain(&map, 1, 0), (Terrain::Road, DOWN));
+        assert_eq!(terrain(&map, 1, 1), (Terrain::Road, UP | RIGHT));
+        assert_eq!(terrain(&map, 2, 1), (Terrain::Road, LEFT));
+    }
+
+    #[test]
+    fn test_bridges_are_placed_across_water() {
+        let mut map = Map::new(4, 3, Tile::new(Terrain::Water, 0));
+        for x in 0..4 {
+            place(&mut map, x, 1, Terrain::Road);
+        }
+        assert_eq!(terrain(&map, 0, 1), (Terrain::Bridge, 1));
+        assert_eq!(terrain(&map, 1, 1), (Terrain::Bridge, 2));
+        assert_eq!(terrain(&map, 3, 1), (Terrain::Bridge, 3));
+        // The bridge doesn't make a shore.
+        assert_eq!(terrain(&map, 1, 0), (Terrain::Water, 15));
+
+        let mut map = grass(1, 3);
+        place(&mut map, 0, 1, Terrain::Water);
+        place(&mut map, 0, 0, Terrain::Road);
+        place(&mut map, 0, 1, Terrain::Road);
+        assert_eq!(terrain(&map, 0, 1), (Terrain::Bridge, 4));
+        assert_eq!(terrain(&map, 0, 0), (Terrain::Road, DOWN));
+    }
+
+    #[test]
+    fn test_palisades_work() {
+        let mut map = grass(3, 3);
+        place(&mut map, 1, 1, Terrain::Palisade);
+        assert_eq!(terrain(&map, 1, 1), (Terrain::Palisade, 0));
+        place(&mut map, 2, 1, Terrain::Palisade);
+        place(&mut map, 1, 2, Terrain::Palisade);
+        assert_eq!(terrain(&map, 1, 1), (Terrain::Palisade, 4));
+        assert_eq!(terrain(&map, 2, 1), (Terrain::Palisade, 1));
+        assert_eq!(terrain(&map, 1, 2), (Terrain::Palisade, 2));
+    }
+}
@@ -1,4 +1,4 @@
-use openpol::sounddat;
+use openpol::{sfx, sounddat};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
@@ -38,7 +38,12 @@ fn main() {
         None => {
             println!("Sounds in {path}:");
             for i in 0..sounddat.sounds() {
-                println!("{}: {} bytes", i, sounddat.sound_data(i).len());
+                println!(
+                    "{}: {} bytes ({})",
+                    i,
+                    sounddat.sound_data(i).len(),
+                    sfx::name(i).unwrap_or_else(|| "unknown".to_string()),
+                );
             }
         }
     }
@@ -0,0 +1,124 @@
+use openpol::fontdat;
+use openpol::fontimport::{self, Grid};
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [OPTIONS] INPUT FONTDAT_FILE [EXTRA_GLYPHS_FILE]
+
+Import a font from a BDF file or a PNG glyph sheet (INPUT ending with .bdf or .png) and save it
+as font.dat. Glyphs are cropped or padded to the widths font.dat requires. When EXTRA_GLYPHS_FILE
+is given, the glyphs that don't fit font.dat as they are (and the glyphs of characters font.dat
+doesn't have, like Polish letters) are saved there as well.
+
+Options:
+  --color N            The palette color of the glyphs (default: {DEFAULT_COLOR})
+  --cell WxH           The size of the PNG sheet cells
+  --baseline N         The cell row right above the baseline (default: the cell height - 4)
+  --characters TEXT    The characters of the PNG sheet cells, left to right and top to bottom",
+    );
+    process::exit(1);
+}
+
+const DEFAULT_COLOR: u8 = 15;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+    let mut color = DEFAULT_COLOR;
+    let mut cell = None;
+    let mut baseline = None;
+    let mut characters = None;
+    let mut paths = Vec::new();
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage(program));
+        match arg.as_str() {
+            "--color" => color = value().parse().unwrap_or_else(|_| usage(program)),
+            "--cell" => {
+                cell = value()
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .or_else(|| usage(program))
+            }
+            "--baseline" => baseline = Some(value().parse().unwrap_or_else(|_| usage(program))),
+            "--characters" => characters = Some(value().chars().collect::<Vec<_>>()),
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => usage(program),
+        }
+    }
+    if !(2..=3).contains(&paths.len()) {
+        usage(program);
+    }
+
+    let input = fs::File::open(paths[0]).unwrap_or_else(|e| {
+        eprintln!("Cannot open {}: {e}", paths[0]);
+        process::exit(1);
+    });
+    let glyphs = if paths[0].to_lowercase().ends_with(".bdf") {
+        fontimport::load_bdf(io::BufReader::new(input), color)
+    } else if paths[0].to_lowercase().ends_with(".png") {
+        let ((cell_width, cell_height), characters) = match (cell, characters) {
+            (Some(cell), Some(characters)) => (cell, characters),
+            _ => {
+                eprintln!("PNG sheets require --cell and --characters");
+                process::exit(1);
+            }
+        };
+        let grid = Grid {
+            cell_width,
+            cell_height,
+            baseline: baseline.unwrap_or_else(|| cell_height.saturating_sub(4)),
+            characters,
+        };
+        fontimport::load_png_grid(io::BufReader::new(input), &grid, color)
+    } else {
+        usage(program)
+    };
+    let glyphs = glyphs.unwrap_or_else(|e| {
+        eprintln!("Cannot import {}: {e}", paths[0]);
+        process::exit(1);
+    });
+
+    let extended = paths.len() == 3;
+    let built = fontimport::build(&glyphs, extended);
+    if !built.fitted.is_empty() {
+        eprintln!(
+            "Glyphs fitted to the font.dat widths: {}",
+            built.fitted.iter().collect::<String>()
+        );
+    }
+    if !built.outside.is_empty() && !extended {
+        eprintln!(
+            "Glyphs skipped, font.dat doesn't have them: {}",
+            built.outside.iter().collect::<String>()
+        );
+    }
+    let missing: String = (0..fontdat::CHARACTERS)
+        .map(|index| (fontdat::FIRST_CHARACTER as u8 + index as u8) as char)
+        .filter(|c| !glyphs.iter().any(|glyph| glyph.character == *c))
+        .collect();
+    if !missing.is_empty() {
+        eprintln!("Characters without glyphs, left empty: {missing}");
+    }
+
+    let create = |path: &str| {
+        io::BufWriter::new(fs::File::create(path).unwrap_or_else(|e| {
+            eprintln!("Cannot create {path}: {e}");
+            process::exit(1);
+        }))
+    };
+    built.fontdat.save(create(paths[1]));
+    if extended {
+        built
+            .fontdat
+            .save_extra_glyphs(create(paths[2]))
+            .unwrap_or_else(|e| {
+                eprintln!("Cannot save the extra glyphs: {e}");
+                process::exit(1);
+            });
+    }
+}
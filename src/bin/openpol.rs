@@ -1,27 +1,91 @@
 use flic::{FlicFile, RasterMut};
+use openpol::actions::{self, Action, Bindings, Context};
+use openpol::animation::{self, Animation, Direction, GrafdatSprites, Sprite, SpriteSource};
+use openpol::args::{self, Command, Options, StartScene};
 use openpol::audio::Sound;
-use openpol::image13h::Rect;
-use openpol::input::{Input, InputProcessor, InputProcessorResult};
-use openpol::{grafdat, image13h, paldat, sounddat};
+use openpol::combat::{Combat, Event as CombatEvent};
+use openpol::commands::{self, CommandKind};
+use openpol::cursor::{Cursor, CursorKind, Target};
+use openpol::debug::{self, FrameStats};
+use openpol::hud::{self, Hud};
+use openpol::image13h::{Image13h, Rect};
+use openpol::input::{Input, InputProcessor, InputProcessorResult, MousePosition};
+use openpol::l10n::Localization;
+use openpol::log::{self, Level, Target as LogTarget};
+use openpol::map::{self, Map, Terrain, Tile};
+use openpol::movement::Movement;
+use openpol::pathfinding::Position;
+use openpol::playercolors::{Highlight, PlayerColors};
+use openpol::replay::{self, Player, Recorder, Tick};
+use openpol::selection::Selection;
+use openpol::settings::{self, Settings};
+use openpol::sfx::{self, SoundGroup};
+use openpol::ui::{
+    ImageButton, Label, ListBox, Menu, Panel, Response, Skin, Slider, TextField, Toggle, Ui,
+    WidgetId,
+};
+use openpol::units::{self, UnitKind, UnitTable, Units};
+use openpol::viewport::Viewport;
+use openpol::{autotile, config, fontdat, grafdat, image13h, paldat, ppm, sounddat};
+use openpol::{error, info, warn};
 use rodio::Source;
 
+use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::render::{Texture, WindowCanvas};
 use sdl2::{EventPump, TimerSubsystem};
 
-use std::cmp;
-
 use std::env;
 use std::fs::{self, File};
-use std::io::{prelude::*, BufReader};
+use std::io::{prelude::*, BufReader, BufWriter};
 use std::path;
+use std::time::{Duration, Instant};
 
 const VERSION: &str = env!("GIT_DESCRIPTION");
 
+/// How much time passes between ticks in headless mode without a replay, in milliseconds.
+const HEADLESS_TICK_MS: u32 = 16;
+
+/// How many log messages the log overlay shows.
+const LOG_OVERLAY_MESSAGES: usize = 8;
+
 fn main() -> Result<(), String> {
-    let game = Game::new()?;
-    game.run()
+    let options = match args::parse(env::args().skip(1))? {
+        Command::Run(options) => options,
+        Command::Version => {
+            println!("openpol {VERSION}");
+            return Ok(());
+        }
+        Command::Help => {
+            println!("{}", args::USAGE);
+            return Ok(());
+        }
+    };
+    let log_file = match &options.log_file {
+        Some(path) => Some(Box::new(
+            File::create(path).map_err(|e| format!("Cannot create {path:?}: {e}"))?,
+        ) as Box<dyn Write + Send>),
+        None => None,
+    };
+    log::init(options.log_level.unwrap_or(Level::Info), log_file);
+    let mut settings = Settings::load_from_config_dir()?;
+    let game_dir =
+        options.resolve_game_dir(|name| env::var_os(name), settings.game_dir.as_deref())?;
+    if !options.uses_config() {
+        settings = Settings::default();
+    }
+    let game = Game::new(&game_dir, settings, &options)?;
+    game.run(&options)
+}
+
+/// The audio output, see `Game::audio`.
+struct Audio {
+    // We need to keep the OutputStream alive for the audio to work.
+    #[allow(dead_code)]
+    stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
 }
 
 struct Game {
@@ -30,11 +94,36 @@ struct Game {
     grafdat: grafdat::Grafdat,
     paldat: paldat::Paldat,
     music: Option<rodio::Sink>,
-    // We need to keep the OutputStream alive for the audio to work.
-    #[allow(dead_code)]
-    audio_stream: rodio::OutputStream,
-    audio_stream_handle: rodio::OutputStreamHandle,
+    /// `None` when muted.
+    audio: Option<Audio>,
     sounds: Vec<Sound>,
+    bindings: Bindings,
+    l10n: Localization,
+    /// The fingerprint of the game data, see `replay::fingerprint()`.
+    fingerprint: u64,
+    fontdat: fontdat::Fontdat,
+    /// The music track requested most recently (the track may not be playing if its file is
+    /// missing).
+    music_track: Option<usize>,
+    /// Set by behaviors to end the game after the current tick.
+    quit_requested: bool,
+    settings: Settings,
+    /// The window scale chosen on the command line. It's used instead of the setting until the
+    /// scale is changed on the options screen, and it isn't saved.
+    scale: Option<u32>,
+    /// Are the settings, key bindings, message catalogs and saved games of the configuration
+    /// directory used? See `Options::uses_config()`.
+    uses_config: bool,
+    /// The palette to use instead of the default ones, see `palette()`.
+    palette: Option<usize>,
+    start_scene: StartScene,
+    /// Should the most recent log messages be shown on top of the game?
+    show_log: bool,
+    show_debug: bool,
+    /// The simulation ticks the behaviors ran in the current frame and how long the simulation
+    /// alone took, for the debug overlay.
+    simulated_ticks: u32,
+    simulation_time: Duration,
 }
 
 impl Game {
@@ -46,78 +135,261 @@ impl Game {
     /// Track numbers are 2-based (technically 1-based, but the data is the first track on the
     /// disc, so...) to keep the same numbering scheme as the original game.
     pub fn play_music_maybe(&mut self, track: usize) {
+        self.music_track = Some(track);
         let file_path = self
             .root_dir
             .join("music")
             .join(format!("track{track}.ogg"));
 
+        let audio = match &self.audio {
+            Some(audio) if self.settings.music => audio,
+            _ => {
+                self.music = None;
+                return;
+            }
+        };
         if file_path.is_file() {
             // TODO: Get rid of these unwrap()s
             let file = BufReader::new(File::open(file_path).unwrap());
             let source = rodio::Decoder::new(file).unwrap();
-            let sink = rodio::Sink::try_new(&self.audio_stream_handle).unwrap();
+            let sink = rodio::Sink::try_new(&audio.handle).unwrap();
+            sink.set_volume(self.settings.music_gain());
             sink.append(source);
             self.music = Some(sink)
         } else {
             self.music = None;
-            eprintln!("Music file {file_path:?} not found");
+            warn!(LogTarget::Audio, "Music file {file_path:?} not found");
+        }
+    }
+
+    /// Play the first sound of a sound group. The audio device refusing the sound is logged,
+    /// the game goes on without it.
+    pub fn play_sound(&self, group: SoundGroup) {
+        let audio = match &self.audio {
+            Some(audio) => audio,
+            None => return,
+        };
+        let index = group.index(0);
+        let result = audio.handle.play_raw(
+            self.sounds[index]
+                .as_source()
+                .convert_samples()
+                .amplify(self.settings.sound_gain()),
+        );
+        if let Err(e) = result {
+            warn!(LogTarget::Audio, "Can't play sound {index}: {e}");
+        }
+    }
+
+    /// How many times the window is larger than the screen, see `scale`.
+    pub fn scale(&self) -> u32 {
+        self.scale.unwrap_or(self.settings.scale)
+    }
+
+    /// The data of the palette to draw with, `default` unless a palette was chosen on the
+    /// command line.
+    pub fn palette(&self, default: usize) -> &[u8] {
+        self.paldat.palette_data(self.palette.unwrap_or(default))
+    }
+
+    /// The names of the saved games in the configuration directory, sorted.
+    pub fn saved_games(&self) -> Vec<String> {
+        if !self.uses_config {
+            return Vec::new();
+        }
+        let mut saves = config::config_dir()
+            .and_then(|dir| fs::read_dir(dir.join("saves")).ok())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        saves.sort();
+        saves
+    }
+
+    /// Switch to the language chosen in the settings. The language's keyboard shortcuts are
+    /// applied to the key bindings.
+    pub fn apply_language(&mut self) {
+        match load_language(&self.settings.language, &self.fontdat, self.uses_config) {
+            Ok((l10n, bindings)) => {
+                self.bindings = bindings;
+                self.l10n = l10n;
+            }
+            Err(e) => error!(LogTarget::Data, "{e}"),
+        }
+    }
+
+    /// Apply the settings that can be changed while the game is running (the window scale is
+    /// applied by the event loop).
+    pub fn apply_settings(&mut self) {
+        match (&self.music, self.settings.music, self.music_track) {
+            (Some(_), false, _) => self.music = None,
+            (None, true, Some(track)) => self.play_music_maybe(track),
+            (Some(sink), true, _) => sink.set_volume(self.settings.music_gain()),
+            _ => (),
         }
     }
 }
 
 impl Game {
-    pub fn new() -> Result<Game, String> {
-        let args: Vec<String> = env::args().skip(1).collect();
-        if args.len() != 1 {
-            return Err("Usage: openpol GAMEDIR".to_string());
-        }
-        let root_dir = path::Path::new(&args[0]);
+    pub fn new(
+        root_dir: &path::Path,
+        settings: Settings,
+        options: &Options,
+    ) -> Result<Game, String> {
+        info!(LogTarget::Data, "Loading the game data from {root_dir:?}");
         let data_dir = root_dir.join("data");
 
-        let (audio_stream, audio_stream_handle) =
-            rodio::OutputStream::try_default().expect("Cannot open an audio output stream");
+        let read = |path: path::PathBuf| {
+            fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+        };
+        let paldat_data = read(root_dir.join("pal.dat"))?;
+        let grafdat_data = read(root_dir.join("graf.dat"))?;
+        let sounddat_data = read(data_dir.join("sound.dat"))?;
+        let fingerprint = replay::fingerprint(&[&paldat_data, &grafdat_data, &sounddat_data]);
+
+        let sounddat = sounddat::Sounddat::load(&sounddat_data[..]).unwrap();
+        // The font isn't required to get to the menu, so we can live without it.
+        let fontdat_path = root_dir.join("font.dat");
+        let mut fontdat = match fs::File::open(&fontdat_path)
+            .ok()
+            .and_then(fontdat::Fontdat::load)
+        {
+            Some(fontdat) => fontdat,
+            None => {
+                warn!(
+                    LogTarget::Data,
+                    "Cannot load {fontdat_path:?}, text won't be displayed"
+                );
+                fontdat::Fontdat::empty()
+            }
+        };
+        let extra_glyphs_path = root_dir.join(fontdat::EXTRA_GLYPHS_FILE_NAME);
+        if extra_glyphs_path.is_file() {
+            let file = fs::File::open(&extra_glyphs_path).map_err(|e| e.to_string())?;
+            fontdat
+                .load_extra_glyphs(BufReader::new(file))
+                .map_err(|e| format!("Cannot load {extra_glyphs_path:?}: {e}"))?;
+        }
+        sfx::check_sound_count(sounddat.sounds())?;
+
+        let (l10n, bindings) = load_language(&settings.language, &fontdat, options.uses_config())?;
+
+        let paldat = paldat::Paldat::load(&paldat_data[..]).unwrap();
+        if let Some(palette) = options.palette {
+            if palette >= paldat.palettes() {
+                return Err(format!(
+                    "There's no palette {palette}, pal.dat has {} palettes",
+                    paldat.palettes()
+                ));
+            }
+        }
+
+        let audio = if options.mute {
+            None
+        } else {
+            let (stream, handle) =
+                rodio::OutputStream::try_default().expect("Cannot open an audio output stream");
+            Some(Audio { stream, handle })
+        };
 
         Ok(Game {
             root_dir: root_dir.to_path_buf(),
             data_dir,
-            audio_stream,
-            audio_stream_handle,
+            audio,
             music: None,
-            paldat: paldat::Paldat::load(fs::File::open(root_dir.join("pal.dat")).unwrap())
-                .unwrap(),
-            grafdat: grafdat::Grafdat::load(fs::File::open(root_dir.join("graf.dat")).unwrap())
-                .unwrap(),
-            sounds: sounddat::Sounddat::load(
-                fs::File::open(root_dir.join("data").join("sound.dat")).unwrap(),
-            )
-            .unwrap()
-            .into_vecs()
-            .into_iter()
-            .map(Sound::new)
-            .collect(),
+            paldat,
+            grafdat: grafdat::Grafdat::load(&grafdat_data[..]).unwrap(),
+            sounds: sounddat.into_vecs().into_iter().map(Sound::new).collect(),
+            bindings,
+            l10n,
+            fingerprint,
+            fontdat,
+            music_track: None,
+            quit_requested: false,
+            settings,
+            scale: options.scale,
+            uses_config: options.uses_config(),
+            palette: options.palette,
+            start_scene: options.start_scene(),
+            show_log: false,
+            show_debug: false,
+            simulated_ticks: 0,
+            simulation_time: Duration::ZERO,
         })
     }
 
-    pub fn run(self) -> Result<(), String> {
+    pub fn run(mut self, options: &Options) -> Result<(), String> {
+        let driver = match &options.replay {
+            Some(path) => {
+                let file = File::open(path).map_err(|e| format!("Cannot open {path:?}: {e}"))?;
+                let player = Player::new(BufReader::new(file))?;
+                if player.fingerprint() != self.fingerprint {
+                    return Err(format!(
+                        "{path:?} was recorded with different game data than the data in {:?}",
+                        self.root_dir,
+                    ));
+                }
+                if let Some(palette) = player.palette() {
+                    if palette >= self.paldat.palettes() {
+                        return Err(format!(
+                            "{path:?} uses palette {palette} pal.dat doesn't have"
+                        ));
+                    }
+                }
+                self.palette = player.palette();
+                Driver::Replay(player)
+            }
+            None => Driver::Live {
+                processor: InputProcessor::new(),
+                recorder: match &options.record {
+                    Some(path) => {
+                        let file = File::create(path)
+                            .map_err(|e| format!("Cannot create {path:?}: {e}"))?;
+                        Some(
+                            Recorder::new(BufWriter::new(file), self.fingerprint, self.palette)
+                                .map_err(|e| e.to_string())?,
+                        )
+                    }
+                    None => None,
+                },
+            },
+        };
+        if options.headless {
+            return self.headless_loop(driver, options);
+        }
+
         let sdl = sdl2::init()?;
         let video = sdl.video()?;
         // This show_cursor() call needs to happen *after* the video subsystem is initialized,
         // otherwise it'll silently do nothing.
         sdl.mouse().show_cursor(false);
-        let window = video
-            .window(
-                &format!("openpol {VERSION}"),
-                image13h::SCREEN_WIDTH as u32 * 2,
-                image13h::SCREEN_HEIGHT as u32 * 2,
-            )
-            .build()
-            .map_err(|e| e.to_string())?;
+        let mut window = video.window(
+            &format!("openpol {VERSION}"),
+            image13h::SCREEN_WIDTH as u32 * self.scale(),
+            image13h::SCREEN_HEIGHT as u32 * self.scale(),
+        );
+        if options.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().map_err(|e| e.to_string())?;
         let mut canvas = window
             .into_canvas()
             .target_texture()
             .present_vsync()
             .build()
             .map_err(|e| e.to_string())?;
+        if options.fullscreen {
+            canvas
+                .set_logical_size(
+                    image13h::SCREEN_WIDTH as u32,
+                    image13h::SCREEN_HEIGHT as u32,
+                )
+                .map_err(|e| e.to_string())?;
+        }
 
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         let mut event_pump = sdl.event_pump()?;
@@ -133,39 +405,273 @@ impl Game {
 
         let mut timer = sdl.timer()?;
 
-        self.event_loop(&mut event_pump, &mut timer, &mut canvas, &mut texture)
+        self.event_loop(
+            driver,
+            options,
+            &mut event_pump,
+            &mut timer,
+            &mut canvas,
+            &mut texture,
+        )
     }
 
     fn event_loop(
         mut self,
+        mut driver: Driver,
+        options: &Options,
         event_pump: &mut EventPump,
         timer: &mut TimerSubsystem,
         canvas: &mut WindowCanvas,
         texture: &mut Texture,
     ) -> Result<(), String> {
         let mut last_render = timer.ticks();
-        let mut behavior: Box<dyn Behavior> = Box::new(Intro::new(self.data_dir.clone()).unwrap());
-        let mut input_processor = InputProcessor::new();
+        let mut behavior = self.initial_behavior();
+        // We render into our own buffer instead of directly into the texture, because the
+        // texture's contents are undefined between locks and behaviors don't necessarily redraw
+        // everything in every frame.
+        let mut frame = vec![0; image13h::SCREEN_PIXELS * 3];
+        // The frame with overlays drawn on top.
+        let mut overlaid = Vec::new();
+        let mut stats = FrameStats::new();
+        let mut ticks = 0;
         loop {
-            let input = match input_processor.process_frame_events(event_pump.poll_iter()) {
-                InputProcessorResult::Quit => return Ok(()),
-                InputProcessorResult::Input(input) => input,
-            };
             let now = timer.ticks();
             let dt = now - last_render;
             last_render = now;
+            let mouse = match &mut driver {
+                Driver::Live {
+                    processor,
+                    recorder,
+                } => {
+                    // The scale has to be up to date before the frame's events are processed.
+                    // In fullscreen SDL converts the mouse coordinates to logical ones for us.
+                    processor.set_scale(if options.fullscreen { 1 } else { self.scale() });
+                    let input = match processor.process_frame_events(event_pump.poll_iter()) {
+                        InputProcessorResult::Quit => break,
+                        InputProcessorResult::Input(input) => input,
+                    };
+                    self.step(&mut behavior, dt, &input, &mut frame);
+                    if let Some(recorder) = recorder {
+                        recorder
+                            .record(dt, &input, replay::frame_checksum(&frame))
+                            .map_err(|e| e.to_string())?;
+                    }
+                    input.mouse_position
+                }
+                Driver::Replay(player) => {
+                    if event_pump
+                        .poll_iter()
+                        .any(|event| matches!(event, Event::Quit { .. }))
+                    {
+                        break;
+                    }
+                    match player.next_tick()? {
+                        Some(tick) => {
+                            self.replay_tick(&mut behavior, &tick, &mut frame)?;
+                            tick.input.mouse_position
+                        }
+                        None => break,
+                    }
+                }
+            };
+            stats.record(dt, self.simulated_ticks, self.simulation_time);
+            ticks += 1;
+            if self.quit_requested || options.ticks == Some(ticks) {
+                break;
+            }
+            if !options.fullscreen {
+                let scale = self.scale();
+                let window_size = (
+                    image13h::SCREEN_WIDTH as u32 * scale,
+                    image13h::SCREEN_HEIGHT as u32 * scale,
+                );
+                if canvas.window().size() != window_size {
+                    canvas
+                        .window_mut()
+                        .set_size(window_size.0, window_size.1)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            // The overlays are drawn on a copy, so that they don't end up in the frame checksums:
+            // what they show depends on more than the input.
+            let shown = if self.show_log || self.show_debug {
+                overlaid.clone_from(&frame);
+                if self.show_debug {
+                    let info = self.debug_info(behavior.as_ref(), mouse);
+                    debug::draw_overlay(&self.fontdat, &stats, &info, &mut overlaid);
+                }
+                if self.show_log {
+                    log::draw_overlay(
+                        &self.fontdat,
+                        &log::recent(LOG_OVERLAY_MESSAGES),
+                        &mut overlaid,
+                    );
+                }
+                &overlaid
+            } else {
+                &frame
+            };
             // NOTE: pitch is assumed to be equal to video width * 3 bytes (RGB), eg. there are no
             // holes between rows in the buffer.
-            texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| {
-                if let Some(new_behavior) = behavior.update(&mut self, dt, &input, buffer) {
-                    behavior = new_behavior;
-                }
-            })?;
+            texture
+                .update(None, shown, image13h::SCREEN_WIDTH * 3)
+                .map_err(|e| e.to_string())?;
             canvas.clear();
             canvas.copy(texture, None, None)?;
             canvas.present();
         }
+        finish(driver, options, &frame)
+    }
+
+    /// Run the game without opening a window, as fast as possible. Without a replay the game
+    /// gets no input at all.
+    fn headless_loop(mut self, mut driver: Driver, options: &Options) -> Result<(), String> {
+        let mut behavior = self.initial_behavior();
+        let mut frame = vec![0; image13h::SCREEN_PIXELS * 3];
+        let mut ticks = 0;
+        loop {
+            match &mut driver {
+                Driver::Live { recorder, .. } => {
+                    let input = Input::idle(MousePosition::new(0, 0));
+                    self.step(&mut behavior, HEADLESS_TICK_MS, &input, &mut frame);
+                    if let Some(recorder) = recorder {
+                        recorder
+                            .record(HEADLESS_TICK_MS, &input, replay::frame_checksum(&frame))
+                            .map_err(|e| e.to_string())?;
+                    }
+                }
+                Driver::Replay(player) => match player.next_tick()? {
+                    Some(tick) => self.replay_tick(&mut behavior, &tick, &mut frame)?,
+                    None => break,
+                },
+            }
+            ticks += 1;
+            if self.quit_requested || options.ticks == Some(ticks) {
+                break;
+            }
+        }
+        finish(driver, options, &frame)
     }
+
+    fn initial_behavior(&self) -> Box<dyn Behavior> {
+        match &self.start_scene {
+            StartScene::Intro if !self.settings.skip_intro => {
+                Box::new(Intro::new(self.data_dir.clone()).unwrap())
+            }
+            StartScene::Intro | StartScene::Menu => Box::new(MainMenu::new()),
+            StartScene::Mission(name) => Box::new(InGame::new(self, name)),
+        }
+    }
+
+    /// The state of the game shown in the debug overlay.
+    fn debug_info(&self, behavior: &dyn Behavior, mouse: MousePosition) -> debug::Info {
+        debug::Info {
+            scene: behavior.name().to_string(),
+            mouse,
+            palette: behavior
+                .palette()
+                .map(|palette| self.palette.unwrap_or(palette)),
+            hovered: behavior.hovered(mouse),
+        }
+    }
+
+    /// Advance the game by one tick.
+    fn step(&mut self, behavior: &mut Box<dyn Behavior>, dt: u32, input: &Input, frame: &mut [u8]) {
+        self.simulated_ticks = 0;
+        self.simulation_time = Duration::ZERO;
+        let triggered = self.bindings.triggered(input, Context::Global);
+        if triggered.contains(&Action::ToggleLog) {
+            self.show_log = !self.show_log;
+        }
+        if triggered.contains(&Action::ToggleDebug) {
+            self.show_debug = !self.show_debug;
+        }
+        if let Some(new_behavior) = behavior.update(self, dt, input, frame) {
+            *behavior = new_behavior;
+        }
+    }
+
+    /// Advance the game by one recorded tick and make sure we rendered the same frame as the
+    /// one rendered when recording.
+    fn replay_tick(
+        &mut self,
+        behavior: &mut Box<dyn Behavior>,
+        tick: &Tick,
+        frame: &mut [u8],
+    ) -> Result<(), String> {
+        self.step(behavior, tick.dt, &tick.input, frame);
+        if replay::frame_checksum(frame) != tick.frame_checksum {
+            return Err(format!(
+                "Replay diverged: tick {} rendered a different frame than when recorded",
+                tick.tick
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Load the messages of `language` and the key bindings. Without `uses_config` (see
+/// `Options::uses_config()`) these are the built-in messages and the language's default bindings.
+fn load_language(
+    language: &str,
+    fontdat: &fontdat::Fontdat,
+    uses_config: bool,
+) -> Result<(Localization, Bindings), String> {
+    if !uses_config {
+        let l10n = Localization::builtin(language);
+        let bindings = l10n.default_bindings();
+        return Ok((l10n, bindings));
+    }
+    let l10n = Localization::load_from_config_dir(language, fontdat)?;
+    let bindings = load_bindings(&l10n)?;
+    Ok((l10n, bindings))
+}
+
+/// Load the key bindings file from the configuration directory, with the language's defaults for
+/// the actions the file doesn't mention.
+fn load_bindings(l10n: &Localization) -> Result<Bindings, String> {
+    match config::config_dir()
+        .map(|dir| dir.join(actions::BINDINGS_FILE_NAME))
+        .filter(|path| path.is_file())
+    {
+        Some(path) => Bindings::load_with_defaults(
+            l10n.default_bindings(),
+            fs::File::open(&path).map_err(|e| e.to_string())?,
+        )
+        .map_err(|e| format!("Cannot load key bindings from {path:?}: {e}")),
+        None => Ok(l10n.default_bindings()),
+    }
+}
+
+/// Where does the input come from.
+enum Driver {
+    Live {
+        processor: InputProcessor,
+        recorder: Option<Recorder<BufWriter<File>>>,
+    },
+    Replay(Player<BufReader<File>>),
+}
+
+/// Stop the game: finish the recording and save the screenshot, if requested.
+fn finish(driver: Driver, options: &Options, frame: &[u8]) -> Result<(), String> {
+    if let Driver::Live {
+        recorder: Some(recorder),
+        ..
+    } = driver
+    {
+        recorder.finish().map_err(|e| e.to_string())?;
+    }
+    if let Some(path) = &options.screenshot {
+        let file = File::create(path).map_err(|e| format!("Cannot create {path:?}: {e}"))?;
+        ppm::write_ppm(
+            image13h::SCREEN_WIDTH,
+            image13h::SCREEN_HEIGHT,
+            frame,
+            BufWriter::new(file),
+        )
+        .map_err(|e| format!("Cannot save the screenshot to {path:?}: {e}"))?;
+    }
+    Ok(())
 }
 
 trait Behavior {
@@ -176,6 +682,20 @@ trait Behavior {
         input: &Input,
         buffer: &mut [u8],
     ) -> Option<Box<dyn Behavior>>;
+
+    /// The name of the scene, for debugging.
+    fn name(&self) -> &'static str;
+
+    /// The pal.dat palette the scene is drawn with by default, `None` if the scene uses a
+    /// palette of its own.
+    fn palette(&self) -> Option<usize> {
+        None
+    }
+
+    /// A description of what's at `position`, for debugging.
+    fn hovered(&self, _position: MousePosition) -> Option<String> {
+        None
+    }
 }
 
 struct Intro {
@@ -210,6 +730,10 @@ impl Intro {
 }
 
 impl Behavior for Intro {
+    fn name(&self) -> &'static str {
+        "intro"
+    }
+
     fn update(
         &mut self,
         game: &mut Game,
@@ -217,7 +741,7 @@ impl Behavior for Intro {
         input: &Input,
         buffer: &mut [u8],
     ) -> Option<Box<dyn Behavior>> {
-        if input.key_pressed.is_some() {
+        if input.any_key_pressed() {
             self.next();
         }
 
@@ -229,9 +753,12 @@ impl Behavior for Intro {
                     assert_eq!(flic.height() as usize, image13h::SCREEN_HEIGHT);
                     self.flic = Some(flic);
 
-                    match fs::File::open(&self.data_dir.join(format!("I00{i}.DAT"))) {
-                        Err(_) => (),
-                        Ok(mut audio_file) => {
+                    match (
+                        fs::File::open(&self.data_dir.join(format!("I00{i}.DAT"))),
+                        &game.audio,
+                    ) {
+                        (Err(_), _) | (_, None) => (),
+                        (Ok(mut audio_file), Some(audio)) => {
                             // The IXXX.DAT files have a 4-byte little-endian integer header that
                             // contains the audio data size (the size of the whole file should be
                             // audio data size + 4 bytes for the header).
@@ -244,7 +771,7 @@ impl Behavior for Intro {
                             assert_eq!(audio_data.len(), expected_len);
 
                             let sound = Sound::new(audio_data);
-                            let sink = rodio::Sink::try_new(&game.audio_stream_handle).unwrap();
+                            let sink = rodio::Sink::try_new(&audio.handle).unwrap();
                             sink.append(sound.as_source());
                             self.audio_sink = Some(sink);
                         }
@@ -283,19 +810,62 @@ impl Behavior for Intro {
     }
 }
 
+/// The palette used by the menus.
+const MENU_PALETTE: usize = 2;
+
+/// The color used to outline the menu item under the mouse cursor.
+const MENU_HIGHLIGHT_COLOR: u8 = 15;
+
+/// The areas of the main menu image occupied by its buttons.
+fn main_menu_items() -> Vec<(Rect, Action)> {
+    vec![
+        (Rect::from_ranges(20..131, 45..71), Action::NewGame),
+        (Rect::from_ranges(20..131, 90..116), Action::LoadGame),
+        (Rect::from_ranges(20..131, 130..152), Action::Quit),
+    ]
+}
+
+/// Draw the menu mouse cursor.
+fn draw_menu_cursor(game: &Game, screen: &mut image13h::Image13h, input: &Input) {
+    CursorKind::Menu.draw(&game.grafdat, screen, input.mouse_position);
+}
+
 struct MainMenu {
-    music_playing: bool,
+    menu: Menu<Action>,
 }
 
 impl MainMenu {
     pub fn new() -> MainMenu {
         MainMenu {
-            music_playing: false,
+            menu: Menu::new(&main_menu_items()),
+        }
+    }
+
+    /// Perform a main menu action, no matter if it was chosen with the mouse or the keyboard.
+    fn activate(&self, game: &mut Game, action: Action) -> Option<Box<dyn Behavior>> {
+        game.play_sound(SoundGroup::Click);
+        match action {
+            Action::NewGame => Some(Box::new(NewGameSetup::new())),
+            Action::LoadGame => Some(Box::new(LoadGameScreen::new(game))),
+            Action::Options => Some(Box::new(OptionsScreen::new(game))),
+            Action::Quit => {
+                game.quit_requested = true;
+                None
+            }
+            _ => None,
         }
     }
 }
 
 impl Behavior for MainMenu {
+    fn name(&self) -> &'static str {
+        "main menu"
+    }
+
+    fn palette(&self) -> Option<usize> {
+        Some(MENU_PALETTE)
+    }
+
     fn update(
         &mut self,
         game: &mut Game,
@@ -303,65 +873,760 @@ impl Behavior for MainMenu {
         input: &Input,
         buffer: &mut [u8],
     ) -> Option<Box<dyn Behavior>> {
-        if !self.music_playing {
+        if game.music_track != Some(2) {
             game.play_music_maybe(2);
-            self.music_playing = true;
         }
         // TODO stop copying every frame
         let mut screen = image13h::Image13h::empty_screen_sized();
         screen.blit(game.grafdat.main_menu(), 0, 0);
-        // Yes, the main menu cursor image comes from the buttons image array.
-        let cursor = game.grafdat.button(6);
-        screen.blit_with_transparency(
-            cursor,
-            // TODO Implement blitting that handles the source image crossing the destination image
-            // boundary gracefully. We need this to display the cursor correctly near the right and
-            // bottom borders. Clipping the blitting coordinates for now but it's a hack.
-            cmp::min(
-                image13h::SCREEN_WIDTH - cursor.width(),
-                input.mouse_position.x,
-            ),
-            cmp::min(
-                image13h::SCREEN_HEIGHT - cursor.height(),
-                input.mouse_position.y,
-            ),
+
+        let clicked = self.menu.update(input);
+        self.menu.draw(&mut screen, MENU_HIGHLIGHT_COLOR);
+        draw_menu_cursor(game, &mut screen, input);
+        // TODO Stop converting and copying data every frame unnecessarily
+        image13h::indices_to_rgb(screen.data(), game.palette(MENU_PALETTE), buffer);
+
+        let pressed = game
+            .bindings
+            .triggered(input, Context::Menu)
+            .into_iter()
+            .find(|action| {
+                *action == Action::Options
+                    || main_menu_items().iter().any(|(_, item)| item == action)
+            });
+        match clicked.or(pressed) {
+            Some(action) => self.activate(game, action),
+            None => {
+                if input.any_key_pressed() {
+                    game.play_sound(SoundGroup::Click);
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Draw a screen with a title (a message ID), used by the screens reachable from the main menu.
+fn draw_menu_screen(game: &Game, title: &str) -> image13h::Image13h {
+    let mut screen = image13h::Image13h::empty_screen_sized();
+    let title = game.l10n.text(title);
+    let width = game.fontdat.text_width(title);
+    game.fontdat.draw_text(
+        &mut screen,
+        title,
+        ((image13h::SCREEN_WIDTH - width) / 2) as isize,
+        20,
+    );
+    screen
+}
+
+/// Should we go back to the main menu?
+fn menu_back_requested(game: &Game, input: &Input) -> bool {
+    game.bindings
+        .triggered(input, Context::Menu)
+        .contains(&Action::Back)
+        || input.mouse_button_pressed(MouseButton::Right)
+}
+
+/// Setting up a new game: choosing the player's name.
+struct NewGameSetup {
+    player_name: TextField,
+}
+
+impl NewGameSetup {
+    pub fn new() -> NewGameSetup {
+        let mut player_name = TextField::new(100, 60, 120);
+        player_name.set_focused(true);
+        NewGameSetup { player_name }
+    }
+}
+
+impl Behavior for NewGameSetup {
+    fn name(&self) -> &'static str {
+        "new game"
+    }
+
+    fn palette(&self) -> Option<usize> {
+        Some(MENU_PALETTE)
+    }
+
+    fn update(
+        &mut self,
+        game: &mut Game,
+        ticks: u32,
+        input: &Input,
+        buffer: &mut [u8],
+    ) -> Option<Box<dyn Behavior>> {
+        self.player_name.update(&game.fontdat, input, ticks);
+        let mut screen = draw_menu_screen(game, "new-game.title");
+        game.fontdat
+            .draw_text(&mut screen, game.l10n.text("new-game.player"), 40, 60);
+        self.player_name.draw(&game.fontdat, &mut screen);
+        draw_menu_cursor(game, &mut screen, input);
+        image13h::indices_to_rgb(screen.data(), game.palette(MENU_PALETTE), buffer);
+
+        if input.keys.is_pressed(Scancode::Return) {
+            game.play_sound(SoundGroup::Click);
+            return Some(Box::new(InGame::new(game, &self.player_name.text())));
+        }
+        if menu_back_requested(game, input) {
+            game.play_sound(SoundGroup::Click);
+            return Some(Box::new(MainMenu::new()));
+        }
+        None
+    }
+}
+
+/// The settings screen. Changes are applied immediately and saved when leaving the screen.
+struct OptionsScreen {
+    ui: Ui,
+    scale: WidgetId,
+    music_volume: WidgetId,
+    sound_volume: WidgetId,
+    music: WidgetId,
+    skip_intro: WidgetId,
+    language: WidgetId,
+    back: WidgetId,
+}
+
+impl OptionsScreen {
+    pub fn new(game: &Game) -> OptionsScreen {
+        let (settings, l10n) = (&game.settings, &game.l10n);
+        let mut ui = Ui::new();
+        ui.add(Panel::new(Rect::from_ranges(30..290, 40..182)));
+        let slider = |ui: &mut Ui, y: usize, label: &str, min: u32, max: u32, value: u32| {
+            ui.add(Label::new(&game.fontdat, 40, y, label));
+            ui.add(Slider::new(
+                Rect::from_ranges(170..280, y + 2..y + 10),
+                min as i32,
+                max as i32,
+                value as i32,
+            ))
+        };
+        let scale = slider(
+            &mut ui,
+            48,
+            l10n.text("options.scale"),
+            settings::MIN_SCALE,
+            settings::MAX_SCALE,
+            settings.scale,
+        );
+        let music_volume = slider(
+            &mut ui,
+            64,
+            l10n.text("options.music-volume"),
+            0,
+            settings::MAX_VOLUME,
+            settings.music_volume,
         );
+        let sound_volume = slider(
+            &mut ui,
+            80,
+            l10n.text("options.sound-volume"),
+            0,
+            settings::MAX_VOLUME,
+            settings.sound_volume,
+        );
+        let toggle = |ui: &mut Ui, y: usize, label: &str, checked: bool| {
+            let mut toggle = Toggle::new(Rect::from_ranges(40..200, y..y + 16), label);
+            toggle.set_checked(checked);
+            ui.add(toggle)
+        };
+        let music = toggle(&mut ui, 98, l10n.text("options.music"), settings.music);
+        let skip_intro = toggle(
+            &mut ui,
+            118,
+            l10n.text("options.skip-intro"),
+            settings.skip_intro,
+        );
+        let language = ui.add(ImageButton::new(
+            Rect::from_ranges(40..151, 156..174),
+            0,
+            &l10n.format(
+                "options.language",
+                &[&l10n.language_name(&settings.language)],
+            ),
+        ));
+        let back = ui.add(ImageButton::new(
+            Rect::from_ranges(170..281, 156..174),
+            0,
+            l10n.text("back"),
+        ));
+        ui.set_focus(Some(scale));
+        OptionsScreen {
+            ui,
+            scale,
+            music_volume,
+            sound_volume,
+            music,
+            skip_intro,
+            language,
+            back,
+        }
+    }
+
+    /// Update the settings after a widget has been changed.
+    fn apply(&mut self, settings: &mut Settings, id: WidgetId) {
+        let slider = |id| self.ui.get::<Slider>(id).unwrap().value() as u32;
+        let toggle = |id| self.ui.get::<Toggle>(id).unwrap().is_checked();
+        if id == self.scale {
+            settings.scale = slider(id);
+        } else if id == self.music_volume {
+            settings.music_volume = slider(id);
+        } else if id == self.sound_volume {
+            settings.sound_volume = slider(id);
+        } else if id == self.music {
+            settings.music = toggle(id);
+        } else if id == self.skip_intro {
+            settings.skip_intro = toggle(id);
+        } else if id == self.language {
+            let current = settings::LANGUAGES
+                .iter()
+                .position(|language| *language == settings.language)
+                .unwrap_or(0);
+            settings.language =
+                settings::LANGUAGES[(current + 1) % settings::LANGUAGES.len()].to_string();
+        }
+    }
+}
 
-        match input.key_pressed {
-            Some(Scancode::N) => {
-                println!("New game (keyboard)");
+impl Behavior for OptionsScreen {
+    fn name(&self) -> &'static str {
+        "options"
+    }
+
+    fn palette(&self) -> Option<usize> {
+        Some(MENU_PALETTE)
+    }
+
+    fn update(
+        &mut self,
+        game: &mut Game,
+        ticks: u32,
+        input: &Input,
+        buffer: &mut [u8],
+    ) -> Option<Box<dyn Behavior>> {
+        let mut back = menu_back_requested(game, input);
+        for (id, response) in self.ui.update(&game.fontdat, input, ticks) {
+            if id == self.back {
+                back = true;
+                continue;
             }
-            Some(Scancode::W) => {
-                println!("Load game (keyboard)");
+            if response == Response::Clicked {
+                game.play_sound(SoundGroup::Click);
             }
-            Some(Scancode::K) => {
-                println!("Quit (keyboard)");
+            self.apply(&mut game.settings, id);
+            if id == self.scale {
+                game.scale = None;
             }
-            Some(_) => {
-                game.audio_stream_handle
-                    .play_raw(game.sounds[0].as_source().convert_samples())
-                    .unwrap();
+            game.apply_settings();
+            if id == self.language {
+                // All the texts change, it's easiest to start over.
+                game.apply_language();
+                *self = OptionsScreen::new(game);
+                self.ui.set_focus(Some(self.language));
+                break;
             }
-            None => {}
         }
-        if input.mouse_button_pressed.is_some() {
-            if Rect::from_ranges(20..131, 130..152)
-                .contains(input.mouse_position.x, input.mouse_position.y)
-            {
-                println!("Quit (mouse)");
-            } else if Rect::from_ranges(20..131, 45..71)
-                .contains(input.mouse_position.x, input.mouse_position.y)
-            {
-                println!("New game (mouse)");
-            } else if Rect::from_ranges(20..131, 90..116)
-                .contains(input.mouse_position.x, input.mouse_position.y)
+
+        let mut screen = draw_menu_screen(game, "options.title");
+        self.ui
+            .draw(&Skin::new(&game.grafdat, &game.fontdat), &mut screen);
+        draw_menu_cursor(game, &mut screen, input);
+        image13h::indices_to_rgb(screen.data(), game.palette(MENU_PALETTE), buffer);
+
+        if back {
+            game.play_sound(SoundGroup::Click);
+            if game.uses_config {
+                if let Err(error) = game.settings.save_to_config_dir() {
+                    error!(LogTarget::Data, "{error}");
+                }
+            }
+            return Some(Box::new(MainMenu::new()));
+        }
+        None
+    }
+}
+
+/// The palette used in-game.
+// TODO Confirm which pal.dat palette the original uses for the in-game screen.
+const GAME_PALETTE: usize = 0;
+
+/// The size of the map the game starts with, in cells.
+const DEMO_MAP_SIZE: (usize, usize) = (48, 40);
+
+/// The player sitting at this computer.
+const LOCAL_PLAYER: units::Player = units::Player(0);
+
+/// The building the build command puts up.
+// TODO Let the player pick the building once there are building kinds.
+const DEMO_BUILDING: u8 = 0;
+
+/// The colors units are drawn with (by owner) until their sprites are decoded.
+// TODO Check the colors against the game palette, draw the sprites once decoded.
+const UNIT_PLACEHOLDER_COLORS: [u8; 2] = [15, 4];
+
+/// The color of the selected units' outlines and of the selection rectangle.
+// TODO Check the color against the game palette.
+const SELECTION_COLOR: u8 = 15;
+
+/// The color missiles are drawn with until their sprites are decoded.
+// TODO Check the color against the game palette, draw the sprites once decoded.
+const MISSILE_PLACEHOLDER_COLOR: u8 = 15;
+
+/// For how many ticks each of the hit splash frames is shown.
+const HIT_FRAME_TICKS: u32 = 2;
+
+/// The number of hit splash frames in graf.dat.
+const HIT_FRAMES: u32 = 2;
+
+/// The game itself: the map with the players' units. Units walk where they're sent and fight the
+/// enemies they're ordered to attack, gathering and building aren't carried out yet.
+struct InGame {
+    hud: Hud,
+    cursor: Cursor,
+    map: Map,
+    viewport: Viewport,
+    table: UnitTable,
+    units: Units,
+    movement: Movement,
+    combat: Combat,
+    selection: Selection,
+    commands: commands::Queue,
+    /// The cells units were hit in, with the ticks they were hit in.
+    hits: Vec<(Position, u32)>,
+    /// The current simulation tick.
+    tick: u32,
+    /// The time towards the next tick, in milliseconds.
+    tick_remainder: u32,
+    /// The command waiting for its target to be picked on the map.
+    targeting: Option<CommandKind>,
+    /// The players' color tables for the game's palette.
+    player_colors: PlayerColors,
+}
+
+impl InGame {
+    /// Start the game with `status` shown in the status line.
+    pub fn new(game: &Game, status: &str) -> InGame {
+        let mut hud = Hud::new();
+        hud.status = status.to_string();
+        let map = demo_map();
+        let table = UnitTable::builtin();
+        InGame {
+            hud,
+            cursor: Cursor::default(),
+            viewport: Viewport::new(&map),
+            units: demo_units(&table),
+            table,
+            map,
+            movement: Movement::new(),
+            combat: Combat::new(),
+            selection: Selection::new(),
+            commands: commands::Queue::new(),
+            hits: Vec::new(),
+            tick: 0,
+            tick_remainder: 0,
+            targeting: None,
+            player_colors: PlayerColors::new(&game.paldat, game.palette.unwrap_or(GAME_PALETTE)),
+        }
+    }
+
+    /// Give the selected units a command of `kind` with the target in `cell` (ignored by the
+    /// commands without targets). The command takes effect in the next tick.
+    fn issue(&mut self, kind: CommandKind, cell: Position) {
+        let units = self.selection.units().to_vec();
+        let command = match kind {
+            CommandKind::Move => commands::Command::Move {
+                units,
+                target: cell,
+            },
+            CommandKind::Attack => match self.enemy_at(cell) {
+                Some(target) => commands::Command::Attack { units, target },
+                None => return,
+            },
+            CommandKind::Stop => commands::Command::Stop { units },
+            CommandKind::Gather => commands::Command::Gather {
+                units,
+                target: cell,
+            },
+            CommandKind::Build => commands::Command::Build {
+                units,
+                building: DEMO_BUILDING,
+                target: cell,
+            },
+        };
+        self.commands.push(commands::Timed {
+            tick: self.tick + 1,
+            player: LOCAL_PLAYER,
+            command,
+        });
+    }
+
+    fn enemy_at(&self, cell: Position) -> Option<units::UnitId> {
+        self.units
+            .iter()
+            .find(|unit| unit.position == cell && unit.owner != LOCAL_PLAYER)
+            .map(|unit| unit.id)
+    }
+
+    /// What a right click on `cell` tells the selected units to do.
+    fn default_command(&self, cell: Position) -> CommandKind {
+        let available = commands::available(&self.units, self.selection.units());
+        let has_resource = self
+            .map
+            .get(cell.0, cell.1)
+            .is_some_and(|cell| cell.resource.is_some());
+        if self.enemy_at(cell).is_some() {
+            CommandKind::Attack
+        } else if has_resource && available.contains(&CommandKind::Gather) {
+            CommandKind::Gather
+        } else {
+            CommandKind::Move
+        }
+    }
+
+    /// What's under the mouse cursor, for picking the cursor image.
+    fn target_at(&self, position: MousePosition) -> Target {
+        let cell = match self.viewport.screen_to_cell(position.x, position.y) {
+            Some(cell) => cell,
+            None => return Target::Nothing,
+        };
+        if let Some(unit) = self.units.at(cell) {
+            return if unit.owner == LOCAL_PLAYER {
+                Target::OwnUnit
+            } else {
+                Target::Enemy
+            };
+        }
+        match self.map.get(cell.0, cell.1) {
+            Some(cell) if cell.resource.is_some() && !self.selection.is_empty() => Target::Resource,
+            _ => Target::Nothing,
+        }
+    }
+
+    /// Draw the corpses, the units and missiles (as placeholders for now), the hit splashes and
+    /// the selection. Corpses are drawn in their owner's colors but otherwise all look the same:
+    /// units don't keep the direction they face, so corpses face down, and the dying frames are
+    /// shared by all unit kinds anyway.
+    fn draw_units(&self, grafdat: &grafdat::Grafdat, input: &Input, screen: &mut Image13h) {
+        let (width, height) = (map::TILE_WIDTH, map::TILE_HEIGHT);
+        let cell_position = |(x, y): Position| self.viewport.world_to_screen(x * width, y * height);
+        let sprites = GrafdatSprites { grafdat };
+        let corpses: Vec<Sprite> = self
+            .combat
+            .corpses()
+            .iter()
+            .filter_map(|corpse| {
+                let direction = Direction::Down;
+                let animation = Animation::new(animation::Action::Die, direction, corpse.died);
+                let image = sprites.sprite(
+                    corpse.kind,
+                    animation.action,
+                    direction,
+                    animation.frame(self.tick),
+                )?;
+                let (x, y) = cell_position(corpse.position);
+                Some(Sprite {
+                    remap: self.player_colors.remap(corpse.owner, Highlight::None),
+                    ..Sprite::in_cell(image, None, x, y)
+                })
+            })
+            .collect();
+        animation::draw_sprites(screen, &corpses, &hud::VIEWPORT);
+
+        let outline = {
+            let mut outline = Image13h::empty(width, height);
+            outline.draw_rect(&Rect::from_ranges(0..width, 0..height), SELECTION_COLOR);
+            outline
+        };
+        for unit in self.units.iter() {
+            let color =
+                UNIT_PLACEHOLDER_COLORS[unit.owner.0 as usize % UNIT_PLACEHOLDER_COLORS.len()];
+            let placeholder = Image13h::filled_with_color(width / 2, height / 2, color);
+            let (x, y) = cell_position(unit.position);
+            screen.blit_within(
+                &placeholder,
+                x + (width / 4) as isize,
+                y + (height / 4) as isize,
+                &hud::VIEWPORT,
+            );
+            if self.selection.contains(unit.id) {
+                screen.blit_within(&outline, x, y, &hud::VIEWPORT);
+            }
+        }
+
+        let missile = Image13h::filled_with_color(2, 2, MISSILE_PLACEHOLDER_COLOR);
+        for flying in self.combat.missiles() {
+            let ((from_x, from_y), (to_x, to_y)) =
+                (cell_position(flying.from), cell_position(flying.to));
+            let (flown, distance) = (flying.flown as isize, flying.distance().max(1) as isize);
+            let x = from_x + (to_x - from_x) * flown / distance + (width / 2) as isize;
+            let y = from_y + (to_y - from_y) * flown / distance + (height / 2) as isize;
+            screen.blit_within(&missile, x, y, &hud::VIEWPORT);
+        }
+
+        for (position, hit) in &self.hits {
+            let frame = (self.tick - hit) / HIT_FRAME_TICKS;
+            let splash = grafdat.hit(frame.min(HIT_FRAMES - 1) as usize);
+            let (x, y) = cell_position(*position);
+            let sprite = Sprite::in_cell(splash, None, x, y);
+            screen.blit_within(splash, sprite.x, sprite.y, &hud::VIEWPORT);
+        }
+        if let Some(drag) = input.drag {
+            if drag.button == MouseButton::Left
+                && hud::VIEWPORT.contains(drag.start.x, drag.start.y)
+                && self.targeting.is_none()
             {
-                println!("Load game (mouse)");
+                let rect = drag.rect();
+                let mut band = Image13h::empty(rect.width, rect.height);
+                band.draw_rect(
+                    &Rect::from_ranges(0..rect.width, 0..rect.height),
+                    SELECTION_COLOR,
+                );
+                screen.blit_within(&band, rect.left as isize, rect.top as isize, &hud::VIEWPORT);
             }
         }
+    }
+}
+
+/// A grass map with some rocks, a lake and a road crossing it, to have something to scroll around
+/// until maps can be loaded.
+// TODO Replace with loading missions.
+fn demo_map() -> Map {
+    let (width, height) = DEMO_MAP_SIZE;
+    let grass = Terrain::Grass.variants();
+    let rocks = Terrain::Rocks.variants();
+    let mut map = Map::new(width, height, Tile::new(Terrain::Grass, 0));
+    for y in 0..height {
+        for x in 0..width {
+            let hash = x * 7 + y * 13 + x * y;
+            let tile = if hash % 17 == 0 {
+                Tile::new(Terrain::Rocks, (hash % rocks) as u8)
+            } else {
+                Tile::new(Terrain::Grass, (hash % grass) as u8)
+            };
+            map.set_tile(x, y, tile);
+        }
+    }
+    for y in 8..16 {
+        for x in 10..22 {
+            autotile::place(&mut map, x, y, Terrain::Water);
+        }
+    }
+    for x in 4..30 {
+        autotile::place(&mut map, x, 12, Terrain::Road);
+    }
+    map
+}
 
-        // TODO Stop converting and copying data every frame unnecessarily
-        image13h::indices_to_rgb(screen.data(), game.paldat.palette_data(2), buffer);
+/// A few units of two players to command until missions can be loaded.
+// TODO Replace with loading missions.
+fn demo_units(table: &UnitTable) -> Units {
+    let mut units = Units::new();
+    let positions = [
+        (UnitKind::Peasant, LOCAL_PLAYER, (5, 4)),
+        (UnitKind::Peasant, LOCAL_PLAYER, (6, 4)),
+        (UnitKind::Warrior, LOCAL_PLAYER, (5, 6)),
+        (UnitKind::Warrior, LOCAL_PLAYER, (6, 6)),
+        (UnitKind::Archer, LOCAL_PLAYER, (7, 5)),
+        (UnitKind::Horseman, LOCAL_PLAYER, (4, 5)),
+        (UnitKind::Warrior, units::Player(1), (8, 6)),
+        (UnitKind::Archer, units::Player(1), (27, 21)),
+    ];
+    for (kind, owner, position) in positions.iter().copied() {
+        units.spawn(table, kind, owner, position);
+    }
+    units
+}
+
+impl Behavior for InGame {
+    fn name(&self) -> &'static str {
+        "game"
+    }
+
+    fn palette(&self) -> Option<usize> {
+        Some(GAME_PALETTE)
+    }
+
+    fn hovered(&self, position: MousePosition) -> Option<String> {
+        let region = hud::region_at(position.x, position.y);
+        match self.viewport.screen_to_cell(position.x, position.y) {
+            Some((x, y)) => {
+                let terrain = self.map.get(x, y).unwrap().tile.terrain;
+                let unit = match self.units.at((x, y)) {
+                    Some(unit) => format!(" {:?} {:?}", unit.kind, unit.order),
+                    None => String::new(),
+                };
+                Some(format!("{:?} {} {} {:?}{}", region, x, y, terrain, unit))
+            }
+            None => Some(format!("{:?}", region)),
+        }
+    }
+
+    fn update(
+        &mut self,
+        game: &mut Game,
+        ticks: u32,
+        input: &Input,
+        buffer: &mut [u8],
+    ) -> Option<Box<dyn Behavior>> {
+        let available = commands::available(&self.units, self.selection.units());
+        if let Some(index) = self.hud.update(input) {
+            game.play_sound(SoundGroup::Click);
+            match available.get(index) {
+                Some(kind) if kind.needs_target() => self.targeting = Some(*kind),
+                Some(kind) => self.issue(*kind, (0, 0)),
+                None => (),
+            }
+        }
+        self.viewport
+            .update(&self.map, input, &game.bindings, ticks);
+
+        let position = input.mouse_position;
+        let cell = self.viewport.screen_to_cell(position.x, position.y);
+        let back = game
+            .bindings
+            .triggered(input, Context::InGame)
+            .contains(&Action::Back);
+        if let Some(kind) = self.targeting {
+            if input.mouse_buttons.is_released(MouseButton::Left) && input.drag.is_none() {
+                if let Some(cell) = cell {
+                    self.issue(kind, cell);
+                    self.targeting = None;
+                }
+            } else if input.mouse_buttons.is_released(MouseButton::Right) || back {
+                self.targeting = None;
+            }
+        } else {
+            self.selection.update(
+                input,
+                &game.bindings,
+                &self.viewport,
+                &self.units,
+                LOCAL_PLAYER,
+                ticks,
+            );
+            if let Some(cell) = cell {
+                if input.mouse_buttons.is_released(MouseButton::Right) && !self.selection.is_empty()
+                {
+                    self.issue(self.default_command(cell), cell);
+                }
+            }
+            if back {
+                return Some(Box::new(MainMenu::new()));
+            }
+        }
+
+        self.tick_remainder += ticks;
+        let simulation_started = Instant::now();
+        while self.tick_remainder >= units::TICK_MS {
+            self.tick_remainder -= units::TICK_MS;
+            self.tick += 1;
+            game.simulated_ticks += 1;
+            for command in self.commands.due(self.tick) {
+                commands::apply(&self.map, &mut self.units, &command);
+            }
+            self.movement
+                .update(&self.map, &self.table, &mut self.units);
+            for event in self.combat.update(&self.table, &mut self.units, self.tick) {
+                if let Some(sound) = event.sound() {
+                    game.play_sound(sound);
+                }
+                if let CombatEvent::Hit { position, .. } = event {
+                    self.hits.push((position, self.tick));
+                }
+            }
+            let tick = self.tick;
+            self.hits
+                .retain(|(_, hit)| tick - hit < HIT_FRAME_TICKS * HIT_FRAMES);
+        }
+        game.simulation_time += simulation_started.elapsed();
+
+        let available = commands::available(&self.units, self.selection.units());
+        self.hud.commands = available
+            .iter()
+            .map(|kind| {
+                game.l10n
+                    .text(&format!("command.{}", kind.name()))
+                    .to_string()
+            })
+            .collect();
+        let mut screen = Image13h::empty_screen_sized();
+        self.viewport.draw(&game.grafdat, &self.map, &mut screen);
+        self.draw_units(&game.grafdat, input, &mut screen);
+        self.hud
+            .draw(&Skin::new(&game.grafdat, &game.fontdat), &mut screen);
+        self.viewport
+            .draw_minimap(&game.grafdat, &self.map, &mut screen);
+        self.cursor
+            .update(position, self.target_at(position), false);
+        self.cursor.draw(&game.grafdat, &mut screen, position);
+        image13h::indices_to_rgb(screen.data(), game.palette(GAME_PALETTE), buffer);
+        None
+    }
+}
+
+/// The list of saved games.
+struct LoadGameScreen {
+    ui: Ui,
+    back: WidgetId,
+}
+
+impl LoadGameScreen {
+    pub fn new(game: &Game) -> LoadGameScreen {
+        let (l10n, saves) = (&game.l10n, game.saved_games());
+        let mut ui = Ui::new();
+        ui.add(Panel::new(Rect::from_ranges(30..290, 50..170)));
+        if saves.is_empty() {
+            ui.add(Label::new(
+                &game.fontdat,
+                40,
+                60,
+                l10n.text("load-game.empty"),
+            ));
+        } else {
+            let list = ui.add(ListBox::new(Rect::from_ranges(40..280, 60..138), saves));
+            ui.set_focus(Some(list));
+        }
+        let back = ui.add(ImageButton::new(
+            Rect::from_ranges(104..215, 145..163),
+            0,
+            l10n.text("back"),
+        ));
+        LoadGameScreen { ui, back }
+    }
+}
+
+impl Behavior for LoadGameScreen {
+    fn name(&self) -> &'static str {
+        "load game"
+    }
+
+    fn palette(&self) -> Option<usize> {
+        Some(MENU_PALETTE)
+    }
+
+    fn update(
+        &mut self,
+        game: &mut Game,
+        ticks: u32,
+        input: &Input,
+        buffer: &mut [u8],
+    ) -> Option<Box<dyn Behavior>> {
+        let responses = self.ui.update(&game.fontdat, input, ticks);
+        let mut screen = draw_menu_screen(game, "load-game.title");
+        self.ui
+            .draw(&Skin::new(&game.grafdat, &game.fontdat), &mut screen);
+        draw_menu_cursor(game, &mut screen, input);
+        image13h::indices_to_rgb(screen.data(), game.palette(MENU_PALETTE), buffer);
+
+        let back_clicked = responses
+            .iter()
+            .any(|(id, response)| *id == self.back && *response == Response::Clicked);
+        if back_clicked || menu_back_requested(game, input) {
+            game.play_sound(SoundGroup::Click);
+            return Some(Box::new(MainMenu::new()));
+        }
         None
     }
 }
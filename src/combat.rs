@@ -0,0 +1,421 @@
+//! Combat: units attacking each other.
+//!
+//! `Combat::update()` runs once per simulation tick. A unit ordered to attack strikes whenever its
+//! target is within its range (in cells, counting diagonal steps as one) and it's done with its
+//! previous attack. A range of 1 means melee: the target has to be adjacent and the hit lands right
+//! away. Ranged attackers fire missiles instead, at the cell the target is in. A missile takes a
+//! few ticks to get there and doesn't follow the target: it hits whatever unit is in the cell when
+//! it lands, unless the unit is the shooter's own, and misses otherwise. Attackers out of range
+//! wait here, `movement` walks them into range.
+//!
+//! Every hit takes the attacker's damage minus the defender's armor (at least 1 hit point). A
+//! player's armor grows with defense upgrades, one level per shield picture in graf.dat. Units
+//! running out of hit points die and leave a corpse for `CORPSE_TICKS` ticks.
+//!
+//! The simulation doesn't play sounds or draw anything, it reports what happened as `Event`s
+//! instead, for the renderer and the audio to react to. The audio plays `Event::sound()`, which
+//! is `None` for every event until the combat sounds of sound.dat are identified (see `sfx`), so
+//! combat is silent for now.
+
+use crate::pathfinding::{self, Position, STRAIGHT_COST};
+use crate::sfx::SoundGroup;
+use crate::units::{Order, Player, UnitId, UnitKind, UnitTable, Units};
+use std::collections::BTreeMap;
+use std::mem;
+
+/// How many ticks a unit needs between two attacks.
+pub const ATTACK_TICKS: u32 = 10;
+
+/// How far a missile flies in a tick, in `pathfinding::distance()` units (`STRAIGHT_COST` per
+/// cell).
+pub const MISSILE_SPEED: u32 = STRAIGHT_COST / 2;
+
+/// The number of defense upgrades (the shield pictures).
+pub const ARMOR_LEVELS: u8 = 4;
+
+/// How many hit points every armor level protects from.
+pub const ARMOR_PER_LEVEL: u32 = 1;
+
+/// How long corpses stay on the map, in ticks.
+pub const CORPSE_TICKS: u32 = 200;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// A ranged attacker fired a missile.
+    Fired { attacker: UnitId, target: UnitId },
+    Hit {
+        target: UnitId,
+        position: Position,
+        damage: u32,
+        /// Was the unit hit by a missile?
+        ranged: bool,
+    },
+    /// A missile landed with no enemy in its cell.
+    Missed { position: Position },
+    Died {
+        unit: UnitId,
+        kind: UnitKind,
+        owner: Player,
+        position: Position,
+    },
+}
+
+impl Event {
+    /// The sound to play for the event. None of the combat sounds are in the `sfx` catalog yet.
+    pub fn sound(&self) -> Option<SoundGroup> {
+        match self {
+            Event::Fired { .. } | Event::Hit { .. } | Event::Missed { .. } | Event::Died { .. } => {
+                None
+            }
+        }
+    }
+}
+
+/// A missile on its way to a cell.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Missile {
+    /// The player who fired the missile, their units aren't hit by it.
+    pub owner: Player,
+    pub from: Position,
+    pub to: Position,
+    pub damage: u32,
+    /// How far the missile has flown, see `MISSILE_SPEED`.
+    pub flown: u32,
+}
+
+impl Missile {
+    /// How far the missile has to fly in total, see `MISSILE_SPEED`.
+    pub fn distance(&self) -> u32 {
+        pathfinding::distance(self.from, self.to)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Corpse {
+    pub kind: UnitKind,
+    pub owner: Player,
+    pub position: Position,
+    /// The tick the unit died in.
+    pub died: u32,
+}
+
+/// The state of the fighting in a game.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Combat {
+    /// The ticks left until the units can attack again, units missing are ready.
+    cooldowns: BTreeMap<UnitId, u32>,
+    armor: BTreeMap<Player, u8>,
+    missiles: Vec<Missile>,
+    corpses: Vec<Corpse>,
+}
+
+impl Combat {
+    pub fn new() -> Combat {
+        Combat::default()
+    }
+
+    /// The number of defense upgrades a player has.
+    pub fn armor(&self, player: Player) -> u8 {
+        self.armor.get(&player).copied().unwrap_or(0)
+    }
+
+    /// Give a player another defense upgrade. Returns `false` if all of them are done already.
+    pub fn upgrade_armor(&mut self, player: Player) -> bool {
+        let armor = self.armor.entry(player).or_insert(0);
+        if *armor >= ARMOR_LEVELS {
+            return false;
+        }
+        *armor += 1;
+        true
+    }
+
+    pub fn missiles(&self) -> &[Missile] {
+        &self.missiles
+    }
+
+    pub fn corpses(&self) -> &[Corpse] {
+        &self.corpses
+    }
+
+    /// Run the `tick`-th tick of combat. Returns what happened, in order.
+    pub fn update(&mut self, table: &UnitTable, units: &mut Units, tick: u32) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.corpses
+            .retain(|corpse| tick.saturating_sub(corpse.died) < CORPSE_TICKS);
+        for cooldown in self.cooldowns.values_mut() {
+            *cooldown = cooldown.saturating_sub(1);
+        }
+        self.cooldowns.retain(|_, cooldown| *cooldown > 0);
+
+        for mut missile in mem::take(&mut self.missiles) {
+            missile.flown += MISSILE_SPEED;
+            if missile.flown < missile.distance() {
+                self.missiles.push(missile);
+            } else {
+                match units.at(missile.to) {
+                    Some(unit) if unit.owner != missile.owner => {
+                        let id = unit.id;
+                        self.hit(units, id, missile.damage, true, tick, &mut events);
+                    }
+                    _ => events.push(Event::Missed {
+                        position: missile.to,
+                    }),
+                }
+            }
+        }
+
+        let attackers: Vec<(UnitId, UnitId)> = units
+            .iter()
+            .filter_map(|unit| match unit.order {
+                Order::Attack { target } => Some((unit.id, target)),
+                _ => None,
+            })
+            .collect();
+        for (id, target) in attackers {
+            // The attacker may have been killed earlier in the tick.
+            let attacker = match units.get(id) {
+                Some(attacker) => attacker.clone(),
+                None => continue,
+            };
+            let target_position = match units.get(target) {
+                Some(target) => target.position,
+                None => {
+                    units.get_mut(id).unwrap().order = Order::Idle;
+                    continue;
+                }
+            };
+            let stats = table.stats(attacker.kind);
+            if self.cooldowns.contains_key(&id)
+                || cells_between(attacker.position, target_position) > stats.range
+            {
+                continue;
+            }
+            self.cooldowns.insert(id, ATTACK_TICKS);
+            if stats.range <= 1 {
+                self.hit(units, target, stats.damage, false, tick, &mut events);
+            } else {
+                self.missiles.push(Missile {
+                    owner: attacker.owner,
+                    from: attacker.position,
+                    to: target_position,
+                    damage: stats.damage,
+                    flown: 0,
+                });
+                events.push(Event::Fired {
+                    attacker: id,
+                    target,
+                });
+            }
+        }
+        events
+    }
+
+    /// Deal `damage` (less the armor) to a unit, killing it if it runs out of hit points.
+    fn hit(
+        &mut self,
+        units: &mut Units,
+        id: UnitId,
+        damage: u32,
+        ranged: bool,
+        tick: u32,
+        events: &mut Vec<Event>,
+    ) {
+        let armor = match units.get(id) {
+            Some(unit) => self.armor(unit.owner) as u32 * ARMOR_PER_LEVEL,
+            None => return,
+        };
+        let damage = damage.saturating_sub(armor).max(1);
+        let unit = units.get_mut(id).unwrap();
+        unit.hit_points = unit.hit_points.saturating_sub(damage);
+        events.push(Event::Hit {
+            target: id,
+            position: unit.position,
+            damage,
+            ranged,
+        });
+        if unit.hit_points == 0 {
+            let unit = units.remove(id).unwrap();
+            self.cooldowns.remove(&id);
+            self.corpses.push(Corpse {
+                kind: unit.kind,
+                owner: unit.owner,
+                position: unit.position,
+                died: tick,
+            });
+            events.push(Event::Died {
+                unit: id,
+                kind: unit.kind,
+                owner: unit.owner,
+                position: unit.position,
+            });
+        }
+    }
+}
+
+/// The number of steps between two cells, diagonal steps included.
+pub fn cells_between(a: Position, b: Position) -> u32 {
+    let dx = (a.0 as isize - b.0 as isize).unsigned_abs();
+    let dy = (a.1 as isize - b.1 as isize).unsigned_abs();
+    dx.max(dy) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::combat::{Combat, Event, ARMOR_LEVELS, ATTACK_TICKS, CORPSE_TICKS};
+    use crate::units::{Order, Player, UnitId, UnitKind, UnitTable, Units};
+
+    fn attack(units: &mut Units, attacker: UnitId, target: UnitId) {
+        units.get_mut(attacker).unwrap().order = Order::Attack { target };
+    }
+
+    #[test]
+    fn test_melee_works() {
+        let table = UnitTable::load(
+            "peasant 5 1 1 1 1 1\nwarrior 10 1 3 1 1 1\narcher 5 1 1 4 1 1\nhorseman 5 1 1 1 1 1"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut units = Units::new();
+        let warrior = units.spawn(&table, UnitKind::Warrior, Player(0), (1, 1));
+        let peasant = units.spawn(&table, UnitKind::Peasant, Player(1), (3, 1));
+        let mut combat = Combat::new();
+        attack(&mut units, warrior, peasant);
+        // Out of range.
+        assert!(combat.update(&table, &mut units, 0).is_empty());
+
+        units.get_mut(peasant).unwrap().position = (2, 2);
+        let events = combat.update(&table, &mut units, 1);
+        assert_eq!(
+            events,
+            vec![Event::Hit {
+                target: peasant,
+                position: (2, 2),
+                damage: 3,
+                ranged: false
+            }]
+        );
+        // The warrior has to wait before the next blow.
+        for tick in 2..1 + ATTACK_TICKS {
+            assert!(combat.update(&table, &mut units, tick).is_empty());
+        }
+        let events = combat.update(&table, &mut units, 1 + ATTACK_TICKS);
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            Event::Died {
+                unit: peasant,
+                kind: UnitKind::Peasant,
+                owner: Player(1),
+                position: (2, 2)
+            }
+        );
+        assert!(units.get(peasant).is_none());
+        assert_eq!(combat.corpses().len(), 1);
+
+        // With the target gone the attacker stops.
+        combat.update(&table, &mut units, 12);
+        assert_eq!(units.get(warrior).unwrap().order, Order::Idle);
+        combat.update(&table, &mut units, 11 + CORPSE_TICKS);
+        assert!(combat.corpses().is_empty());
+    }
+
+    #[test]
+    fn test_missiles_fly() {
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let archer = units.spawn(&table, UnitKind::Archer, Player(0), (0, 0));
+        let range = table.stats(UnitKind::Archer).range as usize;
+        let target = units.spawn(&table, UnitKind::Warrior, Player(1), (range, 0));
+        let mut combat = Combat::new();
+        attack(&mut units, archer, target);
+        let events = combat.update(&table, &mut units, 0);
+        assert_eq!(
+            events,
+            vec![Event::Fired {
+                attacker: archer,
+                target
+            }]
+        );
+        assert_eq!(combat.missiles().len(), 1);
+
+        // Two ticks per cell.
+        let mut tick = 1;
+        loop {
+            let events = combat.update(&table, &mut units, tick);
+            if let Some(Event::Hit { ranged, .. }) = events.first() {
+                assert!(ranged);
+                break;
+            }
+            tick += 1;
+        }
+        assert_eq!(tick as usize, range * 2);
+        let hit_points = units.get(target).unwrap().hit_points;
+        assert_eq!(
+            hit_points,
+            table.stats(UnitKind::Warrior).hit_points - table.stats(UnitKind::Archer).damage
+        );
+    }
+
+    #[test]
+    fn test_missiles_hit_the_cell_they_were_fired_at() {
+        let table = UnitTable::builtin();
+        // Fire at the target in (2, 0), move it out of the way and put peasants of the
+        // `players` in its place. Returns what happened when the missile landed.
+        let land = |players: &[Player]| {
+            let mut units = Units::new();
+            let archer = units.spawn(&table, UnitKind::Archer, Player(0), (0, 0));
+            let target = units.spawn(&table, UnitKind::Warrior, Player(1), (2, 0));
+            let mut combat = Combat::new();
+            attack(&mut units, archer, target);
+            combat.update(&table, &mut units, 0);
+            assert_eq!(combat.missiles().len(), 1);
+            units.get_mut(archer).unwrap().order = Order::Idle;
+            units.get_mut(target).unwrap().position = (2, 1);
+            for &player in players {
+                units.spawn(&table, UnitKind::Peasant, player, (2, 0));
+            }
+            for tick in 1..ATTACK_TICKS {
+                let events = combat.update(&table, &mut units, tick);
+                if !events.is_empty() {
+                    return (events, units);
+                }
+            }
+            panic!("The missile didn't land");
+        };
+
+        // The target stepped out of the way, the missile doesn't follow it.
+        let (events, _) = land(&[]);
+        assert_eq!(events, vec![Event::Missed { position: (2, 0) }]);
+
+        // Another enemy stepped in.
+        let (events, units) = land(&[Player(2)]);
+        let other = units.at((2, 0)).unwrap().id;
+        assert!(matches!(events[..], [Event::Hit { target, ranged: true, .. }] if target == other));
+
+        // The shooter's own units aren't hit.
+        let (events, _) = land(&[Player(0)]);
+        assert_eq!(events, vec![Event::Missed { position: (2, 0) }]);
+    }
+
+    #[test]
+    fn test_armor_works() {
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let warrior = units.spawn(&table, UnitKind::Warrior, Player(0), (0, 0));
+        let target = units.spawn(&table, UnitKind::Warrior, Player(1), (1, 0));
+        let mut combat = Combat::new();
+        for _ in 0..ARMOR_LEVELS {
+            assert!(combat.upgrade_armor(Player(1)));
+        }
+        assert!(!combat.upgrade_armor(Player(1)));
+        assert_eq!(combat.armor(Player(0)), 0);
+
+        attack(&mut units, warrior, target);
+        let damage = match combat.update(&table, &mut units, 0)[..] {
+            [Event::Hit { damage, .. }] => damage,
+            ref events => panic!("{:?}", events),
+        };
+        let full = table.stats(UnitKind::Warrior).damage;
+        assert_eq!(damage, full.saturating_sub(ARMOR_LEVELS as u32).max(1));
+    }
+}
@@ -0,0 +1,459 @@
+//! Commands: the orders players give to their units.
+//!
+//! The user interface doesn't change the simulation directly, it issues `Command`s instead. A
+//! command is stamped with the tick it takes effect in and the player who gave it (`Timed`),
+//! kept in a `Queue` and applied to the units (see `apply()`) when the simulation reaches its
+//! tick. As commands are the only way players' decisions get into the simulation, a game can be
+//! reproduced (or shared over the network) as the list of its commands.
+//!
+//! # Serialized form
+//!
+//! `varint` is an unsigned LEB128-encoded integer (the same as in replays). A timed command is
+//! the `varint` tick, a player byte, a kind byte (see `CommandKind::to_byte()`), the `varint`
+//! number of units followed by their `varint` IDs and then, depending on the kind:
+//!
+//! * move and gather: `varint` x and y of the target cell
+//! * attack: `varint` ID of the target unit
+//! * stop: nothing
+//! * build: a building byte and `varint` x and y of the target cell
+
+use crate::map::Map;
+use crate::pathfinding::{self, Position};
+use crate::replay::{invalid_data, read_u8, read_varint, write_varint};
+use crate::units::{Order, Player, UnitId, UnitKind, Units};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Move {
+        units: Vec<UnitId>,
+        target: Position,
+    },
+    Attack {
+        units: Vec<UnitId>,
+        target: UnitId,
+    },
+    /// Drop whatever the units are doing.
+    Stop {
+        units: Vec<UnitId>,
+    },
+    Gather {
+        units: Vec<UnitId>,
+        target: Position,
+    },
+    /// See `Order::Build`.
+    Build {
+        units: Vec<UnitId>,
+        building: u8,
+        target: Position,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CommandKind {
+    Move,
+    Attack,
+    Stop,
+    Gather,
+    Build,
+}
+
+pub const COMMAND_KINDS: [CommandKind; 5] = [
+    CommandKind::Move,
+    CommandKind::Attack,
+    CommandKind::Stop,
+    CommandKind::Gather,
+    CommandKind::Build,
+];
+
+impl CommandKind {
+    /// The name used for the command's messages (`command.NAME`).
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandKind::Move => "move",
+            CommandKind::Attack => "attack",
+            CommandKind::Stop => "stop",
+            CommandKind::Gather => "gather",
+            CommandKind::Build => "build",
+        }
+    }
+
+    /// Does the command need a target picked on the map?
+    pub fn needs_target(self) -> bool {
+        self != CommandKind::Stop
+    }
+
+    /// Can a unit of `kind` carry the command out?
+    pub fn is_available_to(self, kind: UnitKind) -> bool {
+        match self {
+            CommandKind::Move | CommandKind::Attack | CommandKind::Stop => true,
+            CommandKind::Gather | CommandKind::Build => kind == UnitKind::Peasant,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CommandKind::Move => 0,
+            CommandKind::Attack => 1,
+            CommandKind::Stop => 2,
+            CommandKind::Gather => 3,
+            CommandKind::Build => 4,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<CommandKind> {
+        COMMAND_KINDS
+            .iter()
+            .copied()
+            .find(|kind| kind.to_byte() == byte)
+    }
+}
+
+/// The commands at least one of the `selected` units can carry out, in `COMMAND_KINDS` order.
+pub fn available(units: &Units, selected: &[UnitId]) -> Vec<CommandKind> {
+    COMMAND_KINDS
+        .iter()
+        .copied()
+        .filter(|command| {
+            selected
+                .iter()
+                .filter_map(|id| units.get(*id))
+                .any(|unit| command.is_available_to(unit.kind))
+        })
+        .collect()
+}
+
+impl Command {
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            Command::Move { .. } => CommandKind::Move,
+            Command::Attack { .. } => CommandKind::Attack,
+            Command::Stop { .. } => CommandKind::Stop,
+            Command::Gather { .. } => CommandKind::Gather,
+            Command::Build { .. } => CommandKind::Build,
+        }
+    }
+
+    /// The units the command is given to.
+    pub fn units(&self) -> &[UnitId] {
+        match self {
+            Command::Move { units, .. }
+            | Command::Attack { units, .. }
+            | Command::Stop { units }
+            | Command::Gather { units, .. }
+            | Command::Build { units, .. } => units,
+        }
+    }
+}
+
+/// A command together with when it takes effect and who gave it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Timed {
+    pub tick: u32,
+    pub player: Player,
+    pub command: Command,
+}
+
+impl Timed {
+    /// Write the command in its serialized form.
+    pub fn write<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        write_varint(writer, self.tick)?;
+        writer.write_all(&[self.player.0, self.command.kind().to_byte()])?;
+        let units = self.command.units();
+        write_varint(writer, units.len() as u32)?;
+        for id in units {
+            write_varint(writer, id.0)?;
+        }
+        match &self.command {
+            Command::Move { target, .. } | Command::Gather { target, .. } => {
+                write_cell(writer, *target)
+            }
+            Command::Attack { target, .. } => write_varint(writer, target.0),
+            Command::Stop { .. } => Ok(()),
+            Command::Build {
+                building, target, ..
+            } => {
+                writer.write_all(&[*building])?;
+                write_cell(writer, *target)
+            }
+        }
+    }
+
+    /// Read a command written with `write()`.
+    pub fn read<T: Read>(reader: &mut T) -> io::Result<Timed> {
+        let tick = read_varint(reader)?;
+        let player = Player(read_u8(reader)?);
+        let kind = read_u8(reader)?;
+        let kind = CommandKind::from_byte(kind)
+            .ok_or_else(|| invalid_data(&format!("unknown command {}", kind)))?;
+        let mut units = Vec::new();
+        for _ in 0..read_varint(reader)? {
+            units.push(UnitId(read_varint(reader)?));
+        }
+        let command = match kind {
+            CommandKind::Move => Command::Move {
+                units,
+                target: read_cell(reader)?,
+            },
+            CommandKind::Attack => Command::Attack {
+                units,
+                target: UnitId(read_varint(reader)?),
+            },
+            CommandKind::Stop => Command::Stop { units },
+            CommandKind::Gather => Command::Gather {
+                units,
+                target: read_cell(reader)?,
+            },
+            CommandKind::Build => Command::Build {
+                units,
+                building: read_u8(reader)?,
+                target: read_cell(reader)?,
+            },
+        };
+        Ok(Timed {
+            tick,
+            player,
+            command,
+        })
+    }
+}
+
+fn write_cell<T: Write>(writer: &mut T, (x, y): Position) -> io::Result<()> {
+    write_varint(writer, x as u32)?;
+    write_varint(writer, y as u32)
+}
+
+fn read_cell<T: Read>(reader: &mut T) -> io::Result<Position> {
+    Ok((read_varint(reader)? as usize, read_varint(reader)? as usize))
+}
+
+/// The commands waiting for their ticks, ordered by tick and then by the order they were pushed
+/// in.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Queue {
+    commands: VecDeque<Timed>,
+}
+
+impl Queue {
+    pub fn new() -> Queue {
+        Queue::default()
+    }
+
+    pub fn push(&mut self, command: Timed) {
+        let index = self
+            .commands
+            .iter()
+            .position(|queued| queued.tick > command.tick)
+            .unwrap_or(self.commands.len());
+        self.commands.insert(index, command);
+    }
+
+    /// Take the commands due in `tick` (or earlier), in order.
+    pub fn due(&mut self, tick: u32) -> Vec<Timed> {
+        let count = self
+            .commands
+            .iter()
+            .take_while(|command| command.tick <= tick)
+            .count();
+        self.commands.drain(..count).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// Give the units the orders a command stands for. Players only command their own units, other
+/// units (and units that don't exist anymore) are ignored, and so are units that can't carry the
+/// command out. Units moving together keep their formation (see `pathfinding::formation()`).
+/// The orders are carried out by `movement` and `combat`.
+pub fn apply(map: &Map, units: &mut Units, timed: &Timed) {
+    let kind = timed.command.kind();
+    let ids: Vec<UnitId> = timed
+        .command
+        .units()
+        .iter()
+        .copied()
+        .filter(|id| {
+            units
+                .get(*id)
+                .is_some_and(|unit| unit.owner == timed.player && kind.is_available_to(unit.kind))
+        })
+        .collect();
+    let orders: Vec<Order> = match timed.command {
+        Command::Move { target, .. } => {
+            let positions: Vec<Position> = ids
+                .iter()
+                .map(|id| units.get(*id).unwrap().position)
+                .collect();
+            pathfinding::formation(map, &positions, target)
+                .into_iter()
+                .map(|target| Order::Move { target })
+                .collect()
+        }
+        Command::Attack { target, .. } => {
+            match units.get(target) {
+                Some(enemy) if enemy.owner != timed.player => (),
+                _ => return,
+            }
+            vec![Order::Attack { target }; ids.len()]
+        }
+        Command::Stop { .. } => vec![Order::Idle; ids.len()],
+        Command::Gather { target, .. } => vec![Order::Gather { target }; ids.len()],
+        Command::Build {
+            building, target, ..
+        } => vec![Order::Build { building, target }; ids.len()],
+    };
+    for (id, order) in ids.iter().zip(orders) {
+        units.get_mut(*id).unwrap().order = order;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{apply, available, Command, CommandKind, Queue, Timed};
+    use crate::map::{Map, Terrain, Tile};
+    use crate::units::{Order, Player, UnitId, UnitKind, UnitTable, Units};
+
+    fn timed(tick: u32, command: Command) -> Timed {
+        Timed {
+            tick,
+            player: Player(0),
+            command,
+        }
+    }
+
+    #[test]
+    fn test_commands_survive_serialization() {
+        let commands = vec![
+            timed(
+                3,
+                Command::Move {
+                    units: vec![UnitId(1), UnitId(300)],
+                    target: (5, 200),
+                },
+            ),
+            timed(
+                4,
+                Command::Attack {
+                    units: vec![UnitId(2)],
+                    target: UnitId(7),
+                },
+            ),
+            timed(4, Command::Stop { units: vec![] }),
+            timed(
+                9,
+                Command::Gather {
+                    units: vec![UnitId(2)],
+                    target: (1, 1),
+                },
+            ),
+            Timed {
+                player: Player(3),
+                ..timed(
+                    1000,
+                    Command::Build {
+                        units: vec![UnitId(4)],
+                        building: 6,
+                        target: (2, 3),
+                    },
+                )
+            },
+        ];
+        let mut data = Vec::new();
+        for command in &commands {
+            command.write(&mut data).unwrap();
+        }
+        let mut reader = &data[..];
+        for command in &commands {
+            assert_eq!(&Timed::read(&mut reader).unwrap(), command);
+        }
+        assert!(reader.is_empty());
+
+        assert!(Timed::read(&mut &[0, 0, 9, 0][..]).is_err());
+        let mut data = Vec::new();
+        commands[4].write(&mut data).unwrap();
+        assert!(Timed::read(&mut &data[..data.len() - 1][..]).is_err());
+    }
+
+    #[test]
+    fn test_queue_keeps_order() {
+        let stop = |id| Command::Stop {
+            units: vec![UnitId(id)],
+        };
+        let mut queue = Queue::new();
+        queue.push(timed(5, stop(0)));
+        queue.push(timed(2, stop(1)));
+        queue.push(timed(5, stop(2)));
+        queue.push(timed(3, stop(3)));
+        assert!(queue.due(1).is_empty());
+        let due: Vec<_> = queue.due(5).into_iter().map(|c| c.command).collect();
+        assert_eq!(due, vec![stop(1), stop(3), stop(0), stop(2)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_commands_give_orders() {
+        let map = Map::new(8, 8, Tile::new(Terrain::Grass, 0));
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let peasant = units.spawn(&table, UnitKind::Peasant, Player(0), (1, 1));
+        let warrior = units.spawn(&table, UnitKind::Warrior, Player(0), (2, 1));
+        let enemy = units.spawn(&table, UnitKind::Archer, Player(1), (6, 6));
+        let order = |units: &Units, id| units.get(id).unwrap().order;
+
+        assert_eq!(
+            available(&units, &[warrior]),
+            vec![CommandKind::Move, CommandKind::Attack, CommandKind::Stop]
+        );
+        assert_eq!(available(&units, &[warrior, peasant]).len(), 5);
+        assert!(available(&units, &[]).is_empty());
+
+        let all = vec![peasant, warrior, enemy];
+        let command = |command| timed(0, command);
+        apply(
+            &map,
+            &mut units,
+            &command(Command::Move {
+                units: all.clone(),
+                target: (4, 4),
+            }),
+        );
+        assert_eq!(order(&units, peasant), Order::Move { target: (4, 4) });
+        assert_eq!(order(&units, warrior), Order::Move { target: (5, 4) });
+        // Other players' units can't be commanded.
+        assert_eq!(order(&units, enemy), Order::Idle);
+
+        apply(
+            &map,
+            &mut units,
+            &command(Command::Gather {
+                units: all.clone(),
+                target: (0, 0),
+            }),
+        );
+        assert_eq!(order(&units, peasant), Order::Gather { target: (0, 0) });
+        assert_eq!(order(&units, warrior), Order::Move { target: (5, 4) });
+
+        // Units don't attack their own side.
+        let attack = |target| {
+            command(Command::Attack {
+                units: vec![warrior],
+                target,
+            })
+        };
+        apply(&map, &mut units, &attack(peasant));
+        assert_eq!(order(&units, warrior), Order::Move { target: (5, 4) });
+        apply(&map, &mut units, &attack(enemy));
+        assert_eq!(order(&units, warrior), Order::Attack { target: enemy });
+
+        apply(&map, &mut units, &command(Command::Stop { units: all }));
+        assert_eq!(order(&units, warrior), Order::Idle);
+        assert_eq!(order(&units, peasant), Order::Idle);
+    }
+}
@@ -0,0 +1,57 @@
+//! Locations of user configuration files.
+//!
+//! On Linux (and other Unix-like systems) the configuration lives in `$XDG_CONFIG_HOME/openpol`,
+//! falling back to `~/.config/openpol` as described by the
+//! [XDG Base Directory Specification](https://specifications.freedesktop.org/basedir-spec/latest/).
+//! On Windows `%APPDATA%\openpol` is used.
+
+use std::env;
+use std::path;
+
+/// The name of the application's configuration subdirectory.
+pub const APPLICATION_DIRECTORY: &str = "openpol";
+
+/// The directory openpol configuration files are stored in. Returns `None` if it can't be
+/// determined (no relevant environment variables are set). The directory is not guaranteed to
+/// exist.
+pub fn config_dir() -> Option<path::PathBuf> {
+    config_dir_from(|name| env::var_os(name))
+}
+
+fn config_dir_from<F: Fn(&str) -> Option<std::ffi::OsString>>(var: F) -> Option<path::PathBuf> {
+    let base = if cfg!(windows) {
+        var("APPDATA").map(path::PathBuf::from)
+    } else {
+        match var("XDG_CONFIG_HOME") {
+            // The specification says relative paths are to be ignored.
+            Some(dir) if path::Path::new(&dir).is_absolute() => Some(path::PathBuf::from(dir)),
+            _ => var("HOME").map(|home| path::Path::new(&home).join(".config")),
+        }
+    };
+    base.map(|base| base.join(APPLICATION_DIRECTORY))
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use crate::config::config_dir_from;
+    use std::path;
+
+    #[test]
+    fn test_config_dir_works() {
+        let dir = config_dir_from(|name| match name {
+            "XDG_CONFIG_HOME" => Some("/xdg".into()),
+            "HOME" => Some("/home/user".into()),
+            _ => None,
+        });
+        assert_eq!(dir, Some(path::PathBuf::from("/xdg/openpol")));
+
+        let dir = config_dir_from(|name| match name {
+            "XDG_CONFIG_HOME" => Some("relative".into()),
+            "HOME" => Some("/home/user".into()),
+            _ => None,
+        });
+        assert_eq!(dir, Some(path::PathBuf::from("/home/user/.config/openpol")));
+
+        assert_eq!(config_dir_from(|_| None), None);
+    }
+}
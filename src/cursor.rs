@@ -0,0 +1,254 @@
+//! Mouse cursors.
+//!
+//! The in-game cursors come from `Grafdat::mouse`, the menus use one of the button images. Which
+//! in-game cursor image serves which purpose is a working hypothesis (based on how the images
+//! look) and is likely to change once the original game's behaviour is examined more closely.
+//!
+//! Every cursor has a hotspot: the point of the image that's placed at the mouse position, so
+//! for example an arrow points with its tip and a crosshair with its center. Cursors are drawn
+//! clipped, they can be partially outside of the screen. They should be drawn last, on top of
+//! everything else.
+
+use crate::grafdat::Grafdat;
+use crate::image13h::{Image13h, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::input::MousePosition;
+
+/// How close to the screen edge (in pixels) the mouse cursor has to be to scroll the map.
+pub const SCROLL_MARGIN: usize = 2;
+
+/// The button image used as the menu cursor.
+const MENU_CURSOR_BUTTON: usize = 6;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScrollDirection {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CursorKind {
+    /// The cursor used in the menus.
+    Menu,
+    Normal,
+    /// Over one of the player's units.
+    Select,
+    /// Over an enemy.
+    Attack,
+    /// Over something that can be gathered.
+    Resource,
+    /// Near the screen edge, scrolling the map.
+    Scroll(ScrollDirection),
+    /// The game is busy (loading or saving, for example).
+    Busy,
+    /// The command can't be carried out where the cursor is.
+    Forbidden,
+}
+
+impl CursorKind {
+    /// The index of the cursor image in `Grafdat::mouse`, `None` for the menu cursor which
+    /// comes from the buttons.
+    fn mouse_index(self) -> Option<usize> {
+        Some(match self {
+            CursorKind::Menu => return None,
+            CursorKind::Normal => 0,
+            CursorKind::Select => 1,
+            CursorKind::Attack => 2,
+            CursorKind::Resource => 3,
+            CursorKind::Busy => 4,
+            CursorKind::Forbidden => 5,
+            CursorKind::Scroll(direction) => match direction {
+                ScrollDirection::Up | ScrollDirection::UpLeft | ScrollDirection::UpRight => 6,
+                ScrollDirection::Right => 7,
+                ScrollDirection::Down | ScrollDirection::DownLeft | ScrollDirection::DownRight => 8,
+                ScrollDirection::Left => 9,
+            },
+        })
+    }
+
+    pub fn image(self, grafdat: &Grafdat) -> &Image13h {
+        match self.mouse_index() {
+            Some(index) => grafdat.mouse(index),
+            None => grafdat.button(MENU_CURSOR_BUTTON),
+        }
+    }
+
+    /// The point of the cursor image (relative to its top left corner) placed at the mouse
+    /// position.
+    pub fn hotspot(self, image: &Image13h) -> (usize, usize) {
+        let (right, bottom) = (
+            image.width().saturating_sub(1),
+            image.height().saturating_sub(1),
+        );
+        let (center_x, center_y) = (image.width() / 2, image.height() / 2);
+        match self {
+            CursorKind::Menu | CursorKind::Normal => (0, 0),
+            CursorKind::Scroll(direction) => match direction {
+                ScrollDirection::Up => (center_x, 0),
+                ScrollDirection::UpRight => (right, 0),
+                ScrollDirection::Right => (right, center_y),
+                ScrollDirection::DownRight => (right, bottom),
+                ScrollDirection::Down => (center_x, bottom),
+                ScrollDirection::DownLeft => (0, bottom),
+                ScrollDirection::Left => (0, center_y),
+                ScrollDirection::UpLeft => (0, 0),
+            },
+            CursorKind::Select
+            | CursorKind::Attack
+            | CursorKind::Resource
+            | CursorKind::Busy
+            | CursorKind::Forbidden => (center_x, center_y),
+        }
+    }
+
+    /// Draw the cursor with its hotspot at `position`.
+    pub fn draw(self, grafdat: &Grafdat, image: &mut Image13h, position: MousePosition) {
+        let cursor = self.image(grafdat);
+        let (x, y) = self.hotspot(cursor);
+        image.blit_clipped_with_transparency(
+            cursor,
+            position.x as isize - x as isize,
+            position.y as isize - y as isize,
+        );
+    }
+}
+
+/// Which way should the map scroll with the mouse cursor at `position`? Returns `None` if the
+/// cursor isn't close to any of the screen edges.
+pub fn scroll_direction(position: MousePosition) -> Option<ScrollDirection> {
+    let left = position.x < SCROLL_MARGIN;
+    let right = position.x >= SCREEN_WIDTH - SCROLL_MARGIN;
+    let up = position.y < SCROLL_MARGIN;
+    let down = position.y >= SCREEN_HEIGHT - SCROLL_MARGIN;
+    match (up, down, left, right) {
+        (true, _, true, _) => Some(ScrollDirection::UpLeft),
+        (true, _, _, true) => Some(ScrollDirection::UpRight),
+        (_, true, true, _) => Some(ScrollDirection::DownLeft),
+        (_, true, _, true) => Some(ScrollDirection::DownRight),
+        (true, _, _, _) => Some(ScrollDirection::Up),
+        (_, true, _, _) => Some(ScrollDirection::Down),
+        (_, _, true, _) => Some(ScrollDirection::Left),
+        (_, _, _, true) => Some(ScrollDirection::Right),
+        _ => None,
+    }
+}
+
+/// What's under the mouse cursor in the game world.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+    Nothing,
+    OwnUnit,
+    Enemy,
+    Resource,
+    /// A place the selected units can't be sent to.
+    Unreachable,
+}
+
+/// Keeps track of the cursor to show.
+pub struct Cursor {
+    kind: CursorKind,
+}
+
+impl Default for Cursor {
+    fn default() -> Cursor {
+        Cursor::new(CursorKind::Normal)
+    }
+}
+
+impl Cursor {
+    pub fn new(kind: CursorKind) -> Cursor {
+        Cursor { kind }
+    }
+
+    pub fn kind(&self) -> CursorKind {
+        self.kind
+    }
+
+    /// Use a specific cursor regardless of the context (in the menus, for example).
+    pub fn set_kind(&mut self, kind: CursorKind) {
+        self.kind = kind;
+    }
+
+    /// Pick the in-game cursor for the context. Being busy takes precedence over scrolling,
+    /// which takes precedence over whatever is under the cursor.
+    pub fn update(&mut self, position: MousePosition, target: Target, busy: bool) {
+        self.kind = if busy {
+            CursorKind::Busy
+        } else if let Some(direction) = scroll_direction(position) {
+            CursorKind::Scroll(direction)
+        } else {
+            match target {
+                Target::Nothing => CursorKind::Normal,
+                Target::OwnUnit => CursorKind::Select,
+                Target::Enemy => CursorKind::Attack,
+                Target::Resource => CursorKind::Resource,
+                Target::Unreachable => CursorKind::Forbidden,
+            }
+        };
+    }
+
+    /// Draw the cursor with its hotspot at `position`. This should be done after everything
+    /// else has been drawn.
+    pub fn draw(&self, grafdat: &Grafdat, image: &mut Image13h, position: MousePosition) {
+        self.kind.draw(grafdat, image, position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cursor::{scroll_direction, Cursor, CursorKind, ScrollDirection, Target};
+    use crate::grafdat::Grafdat;
+    use crate::image13h::Image13h;
+    use crate::input::MousePosition;
+
+    #[test]
+    fn test_scroll_direction_works() {
+        let direction = |x, y| scroll_direction(MousePosition::new(x, y));
+        assert_eq!(direction(100, 100), None);
+        assert_eq!(direction(0, 100), Some(ScrollDirection::Left));
+        assert_eq!(direction(319, 100), Some(ScrollDirection::Right));
+        assert_eq!(direction(100, 1), Some(ScrollDirection::Up));
+        assert_eq!(direction(100, 199), Some(ScrollDirection::Down));
+        assert_eq!(direction(0, 0), Some(ScrollDirection::UpLeft));
+        assert_eq!(direction(319, 199), Some(ScrollDirection::DownRight));
+    }
+
+    #[test]
+    fn test_cursor_choice_works() {
+        let mut cursor = Cursor::default();
+        let middle = MousePosition::new(100, 100);
+        cursor.update(middle, Target::Enemy, false);
+        assert_eq!(cursor.kind(), CursorKind::Attack);
+        cursor.update(MousePosition::new(0, 100), Target::Enemy, false);
+        assert_eq!(cursor.kind(), CursorKind::Scroll(ScrollDirection::Left));
+        cursor.update(MousePosition::new(0, 100), Target::Enemy, true);
+        assert_eq!(cursor.kind(), CursorKind::Busy);
+        cursor.update(middle, Target::Nothing, false);
+        assert_eq!(cursor.kind(), CursorKind::Normal);
+    }
+
+    #[test]
+    fn test_hotspots_work() {
+        let image = Image13h::empty(16, 14);
+        assert_eq!(CursorKind::Normal.hotspot(&image), (0, 0));
+        assert_eq!(CursorKind::Attack.hotspot(&image), (8, 7));
+        assert_eq!(
+            CursorKind::Scroll(ScrollDirection::DownRight).hotspot(&image),
+            (15, 13)
+        );
+    }
+
+    #[test]
+    fn test_drawing_near_the_edge_works() {
+        let grafdat = Grafdat::empty();
+        let mut screen = Image13h::empty_screen_sized();
+        // Doesn't panic even though most of the cursor is outside of the screen.
+        CursorKind::Attack.draw(&grafdat, &mut screen, MousePosition::new(0, 0));
+        CursorKind::Menu.draw(&grafdat, &mut screen, MousePosition::new(319, 199));
+    }
+}
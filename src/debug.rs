@@ -0,0 +1,233 @@
+//! The debug overlay: performance numbers and some of the game's internal state drawn on top of
+//! the game.
+//!
+//! The overlay is drawn over the RGB frame (see `image13h::indices_to_rgb()`) in fixed colors,
+//! so it looks the same regardless of the palette in use.
+
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{self, Image13h, Rect};
+use crate::input::MousePosition;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many frames the statistics (and the frame time graph) cover.
+pub const FRAMES: usize = 100;
+
+/// How many milliseconds of frame time a pixel of the graph bars represents.
+const GRAPH_MS_PER_PIXEL: u32 = 1;
+
+const GRAPH_HEIGHT: usize = 34;
+
+/// Frames taking longer than this (in milliseconds) are drawn in `SLOW_FRAME_COLOR`.
+const SLOW_FRAME_MS: u32 = 17;
+
+const TEXT_COLOR: [u8; 3] = [255, 255, 255];
+const FRAME_COLOR: [u8; 3] = [0, 200, 0];
+const SLOW_FRAME_COLOR: [u8; 3] = [220, 0, 0];
+
+/// The timing of a single frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Sample {
+    /// The time since the previous frame, in milliseconds.
+    dt: u32,
+    /// How many simulation ticks (see `units::TICK_MS`) were run during the frame.
+    ticks: u32,
+    /// How long did running the ticks take, rendering not included.
+    tick_time: Duration,
+}
+
+/// Frame and tick timing of the last `FRAMES` frames.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    samples: VecDeque<Sample>,
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats::default()
+    }
+
+    /// Record a frame that came `dt` milliseconds after the previous one and ran `ticks`
+    /// simulation ticks, which took `tick_time`.
+    pub fn record(&mut self, dt: u32, ticks: u32, tick_time: Duration) {
+        if self.samples.len() == FRAMES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            dt,
+            ticks,
+            tick_time,
+        });
+    }
+
+    fn total_ms(&self) -> u32 {
+        self.samples.iter().map(|sample| sample.dt).sum()
+    }
+
+    pub fn frames_per_second(&self) -> f32 {
+        match self.total_ms() {
+            0 => 0.0,
+            total => self.samples.len() as f32 * 1000.0 / total as f32,
+        }
+    }
+
+    pub fn ticks_per_second(&self) -> f32 {
+        match self.total_ms() {
+            0 => 0.0,
+            total => {
+                let ticks: u32 = self.samples.iter().map(|sample| sample.ticks).sum();
+                ticks as f32 * 1000.0 / total as f32
+            }
+        }
+    }
+
+    /// The average time a tick took, zero if there were no ticks.
+    pub fn average_tick_time(&self) -> Duration {
+        let ticks: u32 = self.samples.iter().map(|sample| sample.ticks).sum();
+        if ticks == 0 {
+            return Duration::ZERO;
+        }
+        self.samples
+            .iter()
+            .map(|sample| sample.tick_time)
+            .sum::<Duration>()
+            / ticks
+    }
+
+    /// The frame times (in milliseconds), the oldest first.
+    pub fn frame_times(&self) -> impl Iterator<Item = u32> + '_ {
+        self.samples.iter().map(|sample| sample.dt)
+    }
+}
+
+/// The game state shown in the overlay.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Info {
+    pub scene: String,
+    /// The mouse position in game (320x200) coordinates.
+    pub mouse: MousePosition,
+    /// The pal.dat palette in use, `None` if the scene uses a palette of its own (like the
+    /// intros).
+    pub palette: Option<usize>,
+    /// A description of what's under the mouse cursor, if the scene can tell.
+    pub hovered: Option<String>,
+}
+
+impl Info {
+    /// The text lines of the overlay.
+    pub fn lines(&self, stats: &FrameStats) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "FPS {:.1}  ticks/s {:.1}",
+                stats.frames_per_second(),
+                stats.ticks_per_second()
+            ),
+            format!(
+                "tick {:.2} ms",
+                stats.average_tick_time().as_secs_f32() * 1000.0
+            ),
+            format!("scene {}", self.scene),
+            format!("mouse {} {}", self.mouse.x, self.mouse.y),
+            match self.palette {
+                Some(palette) => format!("palette {palette}"),
+                None => "palette: own".to_string(),
+            },
+        ];
+        if let Some(hovered) = &self.hovered {
+            lines.push(format!("over {hovered}"));
+        }
+        lines
+    }
+}
+
+/// Draw the overlay in the top left corner of `frame`, an RGB frame: the `info` text lines with
+/// the frame time graph below them.
+pub fn draw_overlay(fontdat: &Fontdat, stats: &FrameStats, info: &Info, frame: &mut [u8]) {
+    let lines = info.lines(stats);
+    let mut text = Image13h::empty_screen_sized();
+    let mut width = FRAMES;
+    for (i, line) in lines.iter().enumerate() {
+        let drawn = fontdat.draw_text(&mut text, line, 2, (i * fontdat::CHARACTER_HEIGHT) as isize);
+        width = width.max(drawn + 2);
+    }
+    let graph_top = lines.len() * fontdat::CHARACTER_HEIGHT + 2;
+    let area = Rect::from_ranges(0..width + 2, 0..graph_top + GRAPH_HEIGHT + 2);
+    image13h::shade_rgb(frame, image13h::SCREEN_WIDTH, &area);
+    image13h::overlay_rgb(&text, frame, TEXT_COLOR);
+
+    let graph_bottom = graph_top + GRAPH_HEIGHT;
+    for (i, dt) in stats.frame_times().enumerate() {
+        let height = ((dt / GRAPH_MS_PER_PIXEL) as usize).clamp(1, GRAPH_HEIGHT);
+        let color = if dt > SLOW_FRAME_MS {
+            SLOW_FRAME_COLOR
+        } else {
+            FRAME_COLOR
+        };
+        image13h::fill_rect_rgb(
+            frame,
+            image13h::SCREEN_WIDTH,
+            &Rect::from_ranges(2 + i..3 + i, graph_bottom - height..graph_bottom),
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::{draw_overlay, FrameStats, Info, FRAMES};
+    use crate::fontdat::Fontdat;
+    use crate::image13h;
+    use crate::input::MousePosition;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frame_stats_work() {
+        let mut stats = FrameStats::new();
+        assert_eq!(stats.frames_per_second(), 0.0);
+        assert_eq!(stats.average_tick_time(), Duration::ZERO);
+        stats.record(20, 1, Duration::from_millis(2));
+        stats.record(30, 3, Duration::from_millis(6));
+        assert_eq!(stats.frames_per_second(), 40.0);
+        assert_eq!(stats.ticks_per_second(), 80.0);
+        assert_eq!(stats.average_tick_time(), Duration::from_millis(2));
+
+        for _ in 0..FRAMES {
+            stats.record(10, 1, Duration::ZERO);
+        }
+        assert_eq!(stats.frame_times().count(), FRAMES);
+        assert_eq!(stats.frames_per_second(), 100.0);
+    }
+
+    #[test]
+    fn test_info_lines_work() {
+        let mut info = Info {
+            scene: "menu".to_string(),
+            mouse: MousePosition::new(10, 20),
+            palette: Some(2),
+            hovered: None,
+        };
+        let lines = info.lines(&FrameStats::new());
+        assert_eq!(lines[2..], ["scene menu", "mouse 10 20", "palette 2"]);
+        info.hovered = Some("viewport".to_string());
+        info.palette = None;
+        let lines = info.lines(&FrameStats::new());
+        assert_eq!(lines[4..], ["palette: own", "over viewport"]);
+    }
+
+    #[test]
+    fn test_drawing_works() {
+        let mut stats = FrameStats::new();
+        stats.record(100, 1, Duration::ZERO);
+        let info = Info {
+            scene: "intro".to_string(),
+            mouse: MousePosition::new(0, 0),
+            palette: None,
+            hovered: None,
+        };
+        let mut frame = vec![200; image13h::SCREEN_PIXELS * 3];
+        draw_overlay(&Fontdat::empty(), &stats, &info, &mut frame);
+        // The background is darkened, the far corner isn't.
+        assert_eq!(frame[0], 50);
+        assert_eq!(frame[frame.len() - 1], 200);
+    }
+}
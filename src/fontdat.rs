@@ -10,10 +10,29 @@
 //! * The third row: line 56, column 7, 27 characters
 //!
 //! The widths of the characters are hardcoded, `CHARACTER_WIDTHS` array is provided for convenience.
+//!
+//! # Character mapping
+//!
+//! The characters are stored in ASCII order, starting with the space (32) and ending with `z`
+//! (122). Glyphs 59..64 (ASCII `[\\]^_`) have letter-like widths, so it's possible the original
+//! game stores some of its Polish letters there, but this hasn't been confirmed. Polish letters
+//! are therefore drawn by composing the glyph of the base letter with a diacritic mark (see
+//! `Fontdat::draw_text()`).
+//!
+//! # Extra glyphs file format
+//!
+//! Glyphs that don't fit the font.dat layout (characters it doesn't have or glyphs of different
+//! widths, see [fontimport](../fontimport/index.html)) can be stored in a separate file. The
+//! file is a sequence of glyphs, each of them a 4-byte little-endian Unicode scalar value
+//! followed by an image13h image `CHARACTER_HEIGHT` pixels high. Extra glyphs take precedence
+//! over the font.dat ones when drawing.
 
 use crate::image13h;
 use std::io;
 
+/// The name of the extra glyphs file the game loads from its directory, if it exists.
+pub const EXTRA_GLYPHS_FILE_NAME: &str = "font-extra.dat";
+
 /// Where do rows start.
 pub const ROWS: usize = 3;
 
@@ -41,6 +60,77 @@ pub const CHARACTER_WIDTHS: [usize; CHARACTERS] = [
     4, 6, 6, 6, 6, 6, 4, 6, 6, 2, 2, 5, 2, 8, 6, 6, 6, 6, 4, 6, 3, 6, 6, 10, 6, 6, 6,
 ];
 
+/// The character stored as the first glyph.
+pub const FIRST_CHARACTER: char = ' ';
+
+/// Polish letters, their base letters and diacritic marks.
+const POLISH_LETTERS: [(char, char, Diacritic); 18] = [
+    ('ą', 'a', Diacritic::Ogonek),
+    ('ć', 'c', Diacritic::Acute),
+    ('ę', 'e', Diacritic::Ogonek),
+    ('ł', 'l', Diacritic::Stroke),
+    ('ń', 'n', Diacritic::Acute),
+    ('ó', 'o', Diacritic::Acute),
+    ('ś', 's', Diacritic::Acute),
+    ('ź', 'z', Diacritic::Acute),
+    ('ż', 'z', Diacritic::Dot),
+    ('Ą', 'A', Diacritic::Ogonek),
+    ('Ć', 'C', Diacritic::Acute),
+    ('Ę', 'E', Diacritic::Ogonek),
+    ('Ł', 'L', Diacritic::Stroke),
+    ('Ń', 'N', Diacritic::Acute),
+    ('Ó', 'O', Diacritic::Acute),
+    ('Ś', 'S', Diacritic::Acute),
+    ('Ź', 'Z', Diacritic::Acute),
+    ('Ż', 'Z', Diacritic::Dot),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Diacritic {
+    Acute,
+    Dot,
+    Ogonek,
+    Stroke,
+}
+
+/// The glyph index of a character stored directly in the font, if any.
+pub fn character_index(c: char) -> Option<usize> {
+    let index = (c as usize).checked_sub(FIRST_CHARACTER as usize)?;
+    if index < CHARACTERS {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// The glyph index and the diacritic mark to draw a character with.
+fn character_glyph(c: char) -> Option<(usize, Option<Diacritic>)> {
+    match character_index(c) {
+        Some(index) => Some((index, None)),
+        None => POLISH_LETTERS
+            .iter()
+            .find(|(letter, _, _)| *letter == c)
+            .and_then(|(_, base, diacritic)| {
+                character_index(*base).map(|index| (index, Some(*diacritic)))
+            }),
+    }
+}
+
+/// Can the character be drawn with the font? Polish letters are supported.
+pub fn can_draw(c: char) -> bool {
+    character_glyph(c).is_some()
+}
+
+/// The width of a character in pixels, `None` if the character can't be drawn.
+pub fn character_width(c: char) -> Option<usize> {
+    character_glyph(c).map(|(index, _)| CHARACTER_WIDTHS[index])
+}
+
+/// The width of a text in pixels. Characters that can't be drawn are skipped.
+pub fn text_width(text: &str) -> usize {
+    text.chars().filter_map(character_width).sum()
+}
+
 /// The x positions of the characters in the font image.
 pub const CHARACTER_X_POSITIONS: [usize; CHARACTERS] = [
     // THe first row
@@ -55,6 +145,8 @@ pub const CHARACTER_X_POSITIONS: [usize; CHARACTERS] = [
 #[derive(Debug, Eq, PartialEq)]
 pub struct Fontdat {
     glyphs: Vec<image13h::Image13h>,
+    /// See the extra glyphs file format above.
+    extra_glyphs: Vec<(char, image13h::Image13h)>,
 }
 
 impl Fontdat {
@@ -76,7 +168,10 @@ impl Fontdat {
             let glyph = image.subimage(&rect);
             glyphs.push(glyph);
         }
-        Some(Fontdat { glyphs })
+        Some(Fontdat {
+            glyphs,
+            extra_glyphs: Vec::new(),
+        })
     }
 
     /// Create a new empty font (all characters are filled with color 0).
@@ -87,7 +182,10 @@ impl Fontdat {
             let glyph = image13h::Image13h::empty(rect.width, rect.height);
             glyphs.push(glyph);
         }
-        Fontdat { glyphs }
+        Fontdat {
+            glyphs,
+            extra_glyphs: Vec::new(),
+        }
     }
 
     /// Save the font to a writer.
@@ -101,6 +199,83 @@ impl Fontdat {
         image.save(writer);
     }
 
+    /// Load extra glyphs from a reader, replacing the extra glyphs with the same characters.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read or if the data is invalid.
+    pub fn load_extra_glyphs<T: io::Read>(&mut self, mut reader: T) -> Result<(), String> {
+        loop {
+            // The data may only end between glyphs, not in the middle of a character code.
+            let mut buffer = [0; 4];
+            let mut read = 0;
+            while read < buffer.len() {
+                match reader.read(&mut buffer[read..]) {
+                    Ok(0) => break,
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+            match read {
+                0 => return Ok(()),
+                4 => (),
+                _ => return Err(format!("Truncated character code ({read} of 4 bytes)")),
+            }
+            let code = u32::from_le_bytes(buffer);
+            let character =
+                char::from_u32(code).ok_or_else(|| format!("Invalid character {code:#x}"))?;
+            let glyph = image13h::Image13h::load(&mut reader)
+                .filter(|glyph| glyph.height() == CHARACTER_HEIGHT)
+                .ok_or_else(|| format!("Invalid glyph of {character:?}"))?;
+            self.set_extra_glyph(character, glyph);
+        }
+    }
+
+    /// Save the extra glyphs to a writer, in a form `load_extra_glyphs()` can read.
+    pub fn save_extra_glyphs<T: io::Write>(&self, mut writer: T) -> io::Result<()> {
+        for (character, glyph) in &self.extra_glyphs {
+            writer.write_all(&(*character as u32).to_le_bytes())?;
+            glyph.save(&mut writer);
+        }
+        Ok(())
+    }
+
+    /// Use `glyph` (`CHARACTER_HEIGHT` pixels high) to draw `character`.
+    pub fn set_extra_glyph(&mut self, character: char, glyph: image13h::Image13h) {
+        debug_assert_eq!(glyph.height(), CHARACTER_HEIGHT);
+        match self.extra_glyphs.iter_mut().find(|(c, _)| *c == character) {
+            Some((_, extra)) => *extra = glyph,
+            None => self.extra_glyphs.push((character, glyph)),
+        }
+    }
+
+    pub fn extra_glyph(&self, character: char) -> Option<&image13h::Image13h> {
+        self.extra_glyphs
+            .iter()
+            .find(|(c, _)| *c == character)
+            .map(|(_, glyph)| glyph)
+    }
+
+    /// Can the character be drawn, with an extra glyph or like `can_draw()`?
+    pub fn can_draw(&self, c: char) -> bool {
+        self.extra_glyph(c).is_some() || can_draw(c)
+    }
+
+    /// The width of a character in pixels, like `character_width()`, but taking the extra glyphs
+    /// into account.
+    pub fn character_width(&self, c: char) -> Option<usize> {
+        match self.extra_glyph(c) {
+            Some(glyph) => Some(glyph.width()),
+            None => character_width(c),
+        }
+    }
+
+    /// The width of a text in pixels, like `text_width()`, but taking the extra glyphs into
+    /// account.
+    pub fn text_width(&self, text: &str) -> usize {
+        text.chars().filter_map(|c| self.character_width(c)).sum()
+    }
+
     /// Get a reference to a character glyph.
     pub fn glyph(&self, character: usize) -> &image13h::Image13h {
         &self.glyphs[character]
@@ -110,6 +285,89 @@ impl Fontdat {
     pub fn glyph_mut(&mut self, character: usize) -> &mut image13h::Image13h {
         &mut self.glyphs[character]
     }
+
+    /// Draw `text` on `image` with the top left corner of the first character at (`x`, `y`).
+    /// Color 0 in the glyphs is transparent. The text is clipped to the image boundaries,
+    /// characters that can't be drawn are skipped. Returns the width of the text drawn.
+    ///
+    /// Polish letters are composed from their base letters and diacritic marks drawn with the
+    /// most common color of the base glyph.
+    pub fn draw_text(
+        &self,
+        image: &mut image13h::Image13h,
+        text: &str,
+        x: isize,
+        y: isize,
+    ) -> usize {
+        let mut offset = 0;
+        for c in text.chars() {
+            if let Some(glyph) = self.extra_glyph(c) {
+                image.blit_clipped_with_transparency(glyph, x + offset as isize, y);
+                offset += glyph.width();
+                continue;
+            }
+            let (index, diacritic) = match character_glyph(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let glyph = &self.glyphs[index];
+            let left = x + offset as isize;
+            image.blit_clipped_with_transparency(glyph, left, y);
+            if let Some(diacritic) = diacritic {
+                draw_diacritic(image, glyph, diacritic, left, y);
+            }
+            offset += glyph.width();
+        }
+        offset
+    }
+}
+
+fn draw_diacritic(
+    image: &mut image13h::Image13h,
+    glyph: &image13h::Image13h,
+    diacritic: Diacritic,
+    x: isize,
+    y: isize,
+) {
+    // Find the bounding box of the base letter and its most common color.
+    let mut counts = [0usize; image13h::COLORS];
+    let (mut top, mut bottom, mut right) = (glyph.height(), 0, 0);
+    for row in 0..glyph.height() {
+        for (column, pixel) in glyph.line(row).iter().enumerate() {
+            if *pixel != 0 {
+                counts[*pixel as usize] += 1;
+                top = top.min(row);
+                bottom = bottom.max(row);
+                right = right.max(column);
+            }
+        }
+    }
+    if top > bottom {
+        // Empty glyph, there's nothing to decorate.
+        return;
+    }
+    let color = (1..image13h::COLORS)
+        .max_by_key(|color| counts[*color])
+        .unwrap() as u8;
+    let (top, bottom, right) = (top as isize, bottom as isize, right as isize);
+    let center = (right + 1) / 2;
+    let pixels: Vec<(isize, isize)> = match diacritic {
+        Diacritic::Acute => vec![(center + 1, top - 3), (center, top - 2)],
+        Diacritic::Dot => vec![(center, top - 2)],
+        Diacritic::Ogonek => vec![(right - 1, bottom + 1), (right, bottom + 2)],
+        Diacritic::Stroke => {
+            let middle = (top + bottom) / 2;
+            vec![
+                (center - 1, middle + 1),
+                (center, middle),
+                (center + 1, middle - 1),
+            ]
+        }
+    };
+    for (px, py) in pixels {
+        // Pixels above the top of the image are clipped like the others.
+        image.set_pixel_clipped(x + px, y + py, color);
+    }
 }
 
 pub fn character_rect(character: usize) -> image13h::Rect {
@@ -129,7 +387,10 @@ pub fn character_rect(character: usize) -> image13h::Rect {
 
 #[cfg(test)]
 mod tests {
-    use crate::fontdat::{Fontdat, CHARACTERS};
+    use crate::fontdat::{
+        can_draw, character_index, text_width, Fontdat, CHARACTERS, CHARACTER_HEIGHT,
+    };
+    use crate::image13h::Image13h;
     use std::fs;
 
     #[test]
@@ -149,4 +410,84 @@ mod tests {
         fontdat.save(&mut buf);
         assert_eq!(buf, dummy_font_dat);
     }
+
+    #[test]
+    fn test_character_mapping_works() {
+        assert_eq!(character_index(' '), Some(0));
+        assert_eq!(character_index('A'), Some(33));
+        assert_eq!(character_index('z'), Some(CHARACTERS - 1));
+        assert_eq!(character_index('{'), None);
+        assert!(can_draw('ż'));
+        assert!(can_draw('Ł'));
+        assert!(!can_draw('ü'));
+        assert_eq!(text_width("Ab"), 8 + 6);
+        // Polish letters are as wide as their base letters, unknown characters are skipped.
+        assert_eq!(text_width("óü"), 6);
+    }
+
+    #[test]
+    fn test_drawing_text_works() {
+        let mut fontdat = Fontdat::empty();
+        let a = character_index('a').unwrap();
+        fontdat.glyph_mut(a).fill(5);
+        fontdat.glyph_mut(a).mut_line(0).fill(0);
+        fontdat.glyph_mut(a).mut_line(1).fill(0);
+        fontdat.glyph_mut(a).mut_line(2).fill(0);
+        let mut image = Image13h::empty(20, 20);
+        assert_eq!(fontdat.draw_text(&mut image, "aą", 1, 2), 12);
+        // The first glyph is drawn as is.
+        assert_eq!(image.line(4)[0..7], [0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(image.line(5)[0..7], [0, 5, 5, 5, 5, 5, 5]);
+        // The second one has an ogonek below its bottom right corner.
+        assert_eq!(image.line(15)[7..13], [0, 0, 0, 0, 5, 0]);
+        assert_eq!(image.line(16)[7..13], [0, 0, 0, 0, 0, 5]);
+        // Drawing partially outside of the image doesn't crash.
+        fontdat.draw_text(&mut image, "aaaa", 15, 15);
+        fontdat.draw_text(&mut image, "a", -3, -3);
+    }
+
+    #[test]
+    fn test_diacritics_are_clipped_at_the_top() {
+        let mut fontdat = Fontdat::empty();
+        let o = character_index('o').unwrap();
+        fontdat.glyph_mut(o).fill(5);
+        let mut image = Image13h::empty(20, 20);
+        // The acute accent goes 2 and 3 rows above the glyph, only the lower row is visible.
+        fontdat.draw_text(&mut image, "ó", 0, 2);
+        let width = fontdat.glyph_mut(o).width();
+        let center = width / 2;
+        let mut expected = vec![0; width];
+        expected[center] = 5;
+        assert_eq!(image.line(0)[..width], expected[..]);
+        assert_eq!(image.line(1)[..width], vec![0; width][..]);
+    }
+
+    #[test]
+    fn test_extra_glyphs_work() {
+        let mut fontdat = Fontdat::empty();
+        fontdat.set_extra_glyph('ą', Image13h::filled_with_color(3, CHARACTER_HEIGHT, 9));
+        fontdat.set_extra_glyph('€', Image13h::filled_with_color(7, CHARACTER_HEIGHT, 8));
+        assert_eq!(fontdat.text_width("ą€a"), 3 + 7 + 6);
+        assert_eq!(fontdat.character_width('€'), Some(7));
+        assert!(fontdat.can_draw('€'));
+        assert!(!fontdat.can_draw('ü'));
+        let mut image = Image13h::empty(20, CHARACTER_HEIGHT);
+        assert_eq!(fontdat.draw_text(&mut image, "ą€", 0, 0), 10);
+        assert_eq!(image.line(0)[..11], [9, 9, 9, 8, 8, 8, 8, 8, 8, 8, 0]);
+
+        let mut saved = Vec::new();
+        fontdat.save_extra_glyphs(&mut saved).unwrap();
+        let mut loaded = Fontdat::empty();
+        loaded.load_extra_glyphs(&saved[..]).unwrap();
+        assert_eq!(loaded, fontdat);
+        assert!(Fontdat::empty()
+            .load_extra_glyphs(&saved[..saved.len() - 1])
+            .is_err());
+        // A partial character code after the last glyph isn't ignored either.
+        saved.extend_from_slice(&[0x41, 0]);
+        assert_eq!(
+            Fontdat::empty().load_extra_glyphs(&saved[..]),
+            Err("Truncated character code (2 of 4 bytes)".to_string())
+        );
+    }
 }
@@ -0,0 +1,370 @@
+//! Importing fonts from other formats into [font.dat](../fontdat/index.html).
+//!
+//! Glyphs can be imported from [BDF](https://en.wikipedia.org/wiki/Glyph_Bitmap_Distribution_Format)
+//! files and from PNG images with the glyphs laid out in a grid of equally sized cells. The glyphs
+//! are converted to `fontdat::CHARACTER_HEIGHT` pixels high images with the baseline below row
+//! `BASELINE` and the glyph pixels set to a single color.
+//!
+//! font.dat has a fixed layout: a fixed set of characters of fixed widths. `build()` fits the
+//! imported glyphs into that layout, cropping or padding them as needed, and can put the glyphs
+//! that don't fit it into the extra glyphs (see `Fontdat::save_extra_glyphs()`).
+
+use crate::fontdat::{self, Fontdat, CHARACTERS, CHARACTER_HEIGHT, CHARACTER_WIDTHS};
+use crate::image13h::Image13h;
+use std::io::{self, BufRead};
+
+/// How many rows of a glyph are above the baseline. The rest is left for the descenders.
+// TODO Confirm against the original font, this is a guess based on its lowercase letters.
+pub const BASELINE: usize = 10;
+
+/// The horizontal space left after the last column of a glyph imported from a PNG grid.
+const LETTER_SPACING: usize = 1;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Glyph {
+    pub character: char,
+    /// `CHARACTER_HEIGHT` pixels high.
+    pub image: Image13h,
+}
+
+/// Import glyphs from a BDF font, using `color` for the pixels that are set. The glyphs are as
+/// wide as the distance to the next character (`DWIDTH`), characters without a Unicode encoding
+/// are skipped. The parts of glyphs that don't fit the glyph height are cropped.
+///
+/// # Errors
+/// An error message is returned if the reader can't be read or the font can't be parsed.
+pub fn load_bdf<T: io::Read>(reader: T, color: u8) -> Result<Vec<Glyph>, String> {
+    let mut glyphs = Vec::new();
+    let mut lines = io::BufReader::new(reader).lines().enumerate();
+    // The properties of the glyph being read.
+    let mut character = None;
+    let mut advance = None;
+    let mut bounding_box = None;
+    while let Some((number, line)) = lines.next() {
+        let line = line.map_err(|e| e.to_string())?;
+        let error = |message: &str| format!("Line {}: {}", number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let numbers = || -> Result<Vec<i32>, String> {
+            line.split_whitespace()
+                .skip(1)
+                .map(|word| word.parse().map_err(|_| error("invalid number")))
+                .collect()
+        };
+        match keyword {
+            "STARTCHAR" => {
+                character = None;
+                advance = None;
+                bounding_box = None;
+            }
+            "ENCODING" => {
+                character = match numbers()?.first() {
+                    Some(code) if *code >= 0 => char::from_u32(*code as u32),
+                    _ => None,
+                }
+            }
+            "DWIDTH" => advance = numbers()?.first().copied(),
+            "BBX" => match numbers()?[..] {
+                [width, height, x, y] => bounding_box = Some((width, height, x, y)),
+                _ => return Err(error("expected BBX width height x y")),
+            },
+            "BITMAP" => {
+                let (width, height, x_offset, y_offset) =
+                    bounding_box.ok_or_else(|| error("BITMAP without BBX"))?;
+                let mut rows = Vec::new();
+                for _ in 0..height {
+                    let (number, row) = lines.next().ok_or_else(|| error("truncated BITMAP"))?;
+                    let row = row.map_err(|e| e.to_string())?;
+                    let bits = u64::from_str_radix(row.trim(), 16)
+                        .map_err(|_| format!("Line {}: invalid bitmap row", number + 1))?;
+                    rows.push((bits, row.trim().len() * 4));
+                }
+                let character = match character {
+                    Some(character) => character,
+                    None => continue,
+                };
+                let glyph_width = advance.unwrap_or(x_offset + width).max(0) as usize;
+                let mut image = Image13h::empty(glyph_width.max(1), CHARACTER_HEIGHT);
+                // The top row of the bitmap is this many rows above the baseline.
+                let top = y_offset + height;
+                for (row, (bits, bit_count)) in rows.into_iter().enumerate() {
+                    for column in 0..width.max(0) as usize {
+                        if column < bit_count && bits >> (bit_count - 1 - column) & 1 == 1 {
+                            image.set_pixel_clipped(
+                                (x_offset + column as i32) as isize,
+                                (BASELINE as i32 - top + row as i32) as isize,
+                                color,
+                            );
+                        }
+                    }
+                }
+                glyphs.push(Glyph { character, image });
+            }
+            _ => (),
+        }
+    }
+    Ok(glyphs)
+}
+
+/// The layout of a PNG glyph sheet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Grid {
+    pub cell_width: usize,
+    pub cell_height: usize,
+    /// The row of a cell right above the baseline, it becomes row `BASELINE - 1` of the glyph.
+    pub baseline: usize,
+    /// The characters of the cells, left to right and top to bottom.
+    pub characters: Vec<char>,
+}
+
+/// Import glyphs from a PNG sheet laid out as described by `grid`, using `color` for the glyph
+/// pixels. The color of the top left pixel of the sheet is the background, all the other opaque
+/// pixels belong to the glyphs. A glyph is as wide as its cell's contents plus `LETTER_SPACING`,
+/// empty cells (the space, for example) are half a cell wide.
+///
+/// # Errors
+/// An error message is returned if the image can't be decoded or if it's too small for the
+/// grid.
+pub fn load_png_grid<T: io::Read>(reader: T, grid: &Grid, color: u8) -> Result<Vec<Glyph>, String> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+    let channels = info.color_type.samples();
+    let (width, height) = (info.width as usize, info.height as usize);
+    let pixel = |x: usize, y: usize| {
+        let start = y * info.line_size + x * channels;
+        &data[start..start + channels]
+    };
+    let background = pixel(0, 0).to_vec();
+    let is_set = |x: usize, y: usize| {
+        let pixel = pixel(x, y);
+        let opaque = match channels {
+            2 => pixel[1] >= 128,
+            4 => pixel[3] >= 128,
+            _ => true,
+        };
+        opaque && pixel != &background[..]
+    };
+
+    let columns = width / grid.cell_width.max(1);
+    let rows_needed = grid.characters.len().div_ceil(columns.max(1));
+    if columns == 0 || rows_needed * grid.cell_height > height {
+        return Err(format!(
+            "The {width}x{height} image is too small for {} {}x{} cells",
+            grid.characters.len(),
+            grid.cell_width,
+            grid.cell_height
+        ));
+    }
+    let mut glyphs = Vec::new();
+    for (i, character) in grid.characters.iter().enumerate() {
+        let (left, top) = (
+            i % columns * grid.cell_width,
+            i / columns * grid.cell_height,
+        );
+        let used = (0..grid.cell_width)
+            .rev()
+            .find(|x| (0..grid.cell_height).any(|y| is_set(left + x, top + y)));
+        let glyph_width = match used {
+            Some(last) => last + 1 + LETTER_SPACING,
+            None => (grid.cell_width / 2).max(1),
+        };
+        let mut image = Image13h::empty(glyph_width, CHARACTER_HEIGHT);
+        for y in 0..grid.cell_height {
+            for x in 0..grid.cell_width.min(glyph_width) {
+                if is_set(left + x, top + y) {
+                    let row = y as isize + BASELINE as isize - grid.baseline as isize - 1;
+                    image.set_pixel_clipped(x as isize, row, color);
+                }
+            }
+        }
+        glyphs.push(Glyph {
+            character: *character,
+            image,
+        });
+    }
+    Ok(glyphs)
+}
+
+/// Crop or pad (with transparent columns on the right) `image` to `width`.
+fn fit(image: &Image13h, width: usize) -> Image13h {
+    let mut fitted = Image13h::empty(width, image.height());
+    fitted.blit_clipped_with_transparency(image, 0, 0);
+    fitted
+}
+
+/// The result of `build()`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Built {
+    pub fontdat: Fontdat,
+    /// The characters whose glyphs had to be cropped or padded to fit font.dat.
+    pub fitted: Vec<char>,
+    /// The characters font.dat doesn't have. They're either in the extra glyphs or dropped.
+    pub outside: Vec<char>,
+}
+
+/// Make a font out of `glyphs`. font.dat characters without glyphs are left empty.
+///
+/// Without `extended`, the font only has the font.dat layout glyphs. With `extended`, the
+/// glyphs that had to be fitted and those of characters font.dat doesn't have are added as
+/// extra glyphs as well, so that they're drawn as imported.
+pub fn build(glyphs: &[Glyph], extended: bool) -> Built {
+    let mut fontdat = Fontdat::empty();
+    let mut fitted = Vec::new();
+    let mut outside = Vec::new();
+    for glyph in glyphs {
+        match fontdat::character_index(glyph.character) {
+            Some(index) => {
+                debug_assert!(index < CHARACTERS);
+                let width = CHARACTER_WIDTHS[index];
+                if glyph.image.width() != width {
+                    fitted.push(glyph.character);
+                    if extended {
+                        fontdat.set_extra_glyph(glyph.character, glyph.image.clone());
+                    }
+                }
+                *fontdat.glyph_mut(index) = fit(&glyph.image, width);
+            }
+            None => {
+                outside.push(glyph.character);
+                if extended {
+                    fontdat.set_extra_glyph(glyph.character, glyph.image.clone());
+                }
+            }
+        }
+    }
+    Built {
+        fontdat,
+        fitted,
+        outside,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::{character_index, CHARACTER_HEIGHT, CHARACTER_WIDTHS};
+    use crate::fontimport::{build, load_bdf, load_png_grid, Glyph, Grid, BASELINE};
+    use crate::image13h::Image13h;
+
+    const BDF: &str = "STARTFONT 2.1
+FONT test
+SIZE 8 75 75
+FONTBOUNDINGBOX 4 6 0 -1
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 5 0
+BBX 4 4 0 0
+BITMAP
+60
+90
+F0
+90
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+DWIDTH 5 0
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+STARTCHAR j
+ENCODING 106
+DWIDTH 3 0
+BBX 2 3 0 -1
+BITMAP
+40
+40
+80
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn test_bdf_import_works() {
+        let glyphs = load_bdf(BDF.as_bytes(), 7).unwrap();
+        assert_eq!(glyphs.len(), 2);
+        let a = &glyphs[0];
+        assert_eq!(a.character, 'A');
+        assert_eq!((a.image.width(), a.image.height()), (5, CHARACTER_HEIGHT));
+        // The bottom row of the A sits right above the baseline.
+        assert_eq!(a.image.line(BASELINE - 4), [0, 7, 7, 0, 0]);
+        assert_eq!(a.image.line(BASELINE - 1), [7, 0, 0, 7, 0]);
+        assert_eq!(a.image.line(BASELINE), [0, 0, 0, 0, 0]);
+        // The descender of the j goes below it.
+        let j = &glyphs[1];
+        assert_eq!(j.image.line(BASELINE), [7, 0, 0]);
+
+        assert!(load_bdf("BBX 1 1 0 0\nBITMAP\nxyz\n".as_bytes(), 7).is_err());
+        assert!(load_bdf("BITMAP\n".as_bytes(), 7).is_err());
+    }
+
+    /// Encode an 8-bit grayscale PNG.
+    fn png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_png_import_works() {
+        // Two 4x3 cells: a 2 pixel wide bar and nothing.
+        #[rustfmt::skip]
+        let pixels = [
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0, 255, 255, 0, 0, 0, 0, 0,
+            0, 255, 255, 0, 0, 0, 0, 0,
+        ];
+        let grid = Grid {
+            cell_width: 4,
+            cell_height: 3,
+            baseline: 2,
+            characters: vec!['I', ' '],
+        };
+        let glyphs = load_png_grid(&png(8, 3, &pixels)[..], &grid, 3).unwrap();
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(glyphs[0].image.width(), 4);
+        assert_eq!(glyphs[0].image.line(BASELINE - 1), [0, 3, 3, 0]);
+        assert_eq!(glyphs[0].image.line(BASELINE - 3), [0, 0, 0, 0]);
+        assert_eq!(glyphs[1].image.width(), 2);
+
+        let too_many = Grid {
+            characters: vec!['a', 'b', 'c'],
+            ..grid
+        };
+        assert!(load_png_grid(&png(8, 3, &pixels)[..], &too_many, 3).is_err());
+        assert!(load_png_grid(&b"not a png"[..], &too_many, 3).is_err());
+    }
+
+    #[test]
+    fn test_building_works() {
+        let glyph = |character, width| Glyph {
+            character,
+            image: Image13h::filled_with_color(width, CHARACTER_HEIGHT, 1),
+        };
+        let a = character_index('A').unwrap();
+        let glyphs = [
+            glyph('A', CHARACTER_WIDTHS[a] + 2),
+            glyph('b', CHARACTER_WIDTHS[character_index('b').unwrap()]),
+            glyph('ą', 5),
+        ];
+
+        let built = build(&glyphs, false);
+        assert_eq!(built.fitted, vec!['A']);
+        assert_eq!(built.outside, vec!['ą']);
+        assert_eq!(built.fontdat.glyph(a).width(), CHARACTER_WIDTHS[a]);
+        assert_eq!(built.fontdat.extra_glyph('ą'), None);
+
+        let built = build(&glyphs, true);
+        assert_eq!(built.fontdat.extra_glyph('ą'), Some(&glyphs[2].image));
+        assert_eq!(built.fontdat.extra_glyph('A'), Some(&glyphs[0].image));
+        assert_eq!(built.fontdat.extra_glyph('b'), None);
+    }
+}
@@ -206,6 +206,36 @@ impl Grafdat {
     pub fn button(&self, index: usize) -> &image13h::Image13h {
         &self.buttons[index]
     }
+
+    /// The large buttons (used by dialogs and the in-game panel).
+    pub fn second_button(&self, index: usize) -> &image13h::Image13h {
+        &self.second_buttons[index]
+    }
+
+    /// The pieces of the frame around the in-game screen: left, top, right and bottom.
+    pub fn border(&self, index: usize) -> &image13h::Image13h {
+        &self.borders[index]
+    }
+
+    /// The frames of a unit dying.
+    pub fn dead(&self, index: usize) -> &image13h::Image13h {
+        &self.dead[index]
+    }
+
+    /// The frames of the splash shown where a unit is hit.
+    pub fn hit(&self, index: usize) -> &image13h::Image13h {
+        &self.hit[index]
+    }
+
+    /// The terrain, building and other map pictures (see `map::Tile::picture()`).
+    pub fn picture(&self, index: usize) -> &image13h::Image13h {
+        &self.pictures[index]
+    }
+
+    /// The wooden textures used as panel backgrounds.
+    pub fn wood(&self, index: usize) -> &image13h::Image13h {
+        &self.wood[index]
+    }
 }
 
 fn get_image_rects() -> Vec<(usize, image13h::Rect)> {
@@ -0,0 +1,322 @@
+//! The in-game screen layout.
+//!
+//! The screen is put together from graf.dat sprites the same way the original game does it: the
+//! four `border` strips frame the map viewport on the left, the top, the right and the bottom, and
+//! the side panel to the right of the frame is covered with the `wood` texture. The side panel
+//! holds the resource readout, a grid of command buttons drawn with `second_buttons` and the
+//! minimap (drawn by `viewport::Viewport::draw_minimap()`). Below the viewport, inside the frame,
+//! there's a status line. The command buttons show the first letters of the commands available, the
+//! full name of the hovered one replaces the status line.
+//!
+//! ```text
+//! x: 0  11                  267 274      320
+//!    +--+---- top border -----+--+--------+ y: 0
+//!    |  |                     |  |resource| 8
+//!    |  |      viewport       |  +--------+ 50
+//!    |  |                     |  |commands| 54
+//!    |  |                     |  +--------+ 124
+//!    |  |                     |  |minimap | 130
+//!    |  +---------------------+  +--------+ 172
+//!    |  |     status line     |  |        | 176
+//!    +--+--- bottom border ---+--+        | 190
+//!                                +--------+ 200
+//! ```
+
+use crate::fontdat;
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::map::{TILE_HEIGHT, TILE_WIDTH};
+use crate::ui::{Button, Skin};
+use sdl2::mouse::MouseButton;
+
+/// The number of tile columns visible in the viewport.
+pub const VIEWPORT_COLUMNS: usize = 16;
+
+/// The number of tile rows visible in the viewport.
+pub const VIEWPORT_ROWS: usize = 12;
+
+/// The part of the screen the map is drawn in.
+pub const VIEWPORT: Rect = Rect {
+    left: 11,
+    top: 8,
+    width: VIEWPORT_COLUMNS * TILE_WIDTH,
+    height: VIEWPORT_ROWS * TILE_HEIGHT,
+};
+
+/// The line of text below the viewport.
+pub const STATUS_LINE: Rect = Rect {
+    left: VIEWPORT.left,
+    top: VIEWPORT.top + VIEWPORT.height,
+    width: VIEWPORT.width,
+    height: BOTTOM_BORDER_TOP - (VIEWPORT.top + VIEWPORT.height),
+};
+
+/// Where the bottom border strip starts.
+const BOTTOM_BORDER_TOP: usize = 190;
+
+/// Where the right border strip starts.
+const RIGHT_BORDER_LEFT: usize = 267;
+
+/// The area to the right of the frame.
+pub const SIDE_PANEL: Rect = Rect {
+    left: 274,
+    top: 0,
+    width: 46,
+    height: 200,
+};
+
+/// The part of the side panel showing the player's resources.
+pub const RESOURCES: Rect = Rect {
+    left: SIDE_PANEL.left + 2,
+    top: 8,
+    width: SIDE_PANEL.width - 4,
+    height: 42,
+};
+
+/// The number of columns of command buttons.
+pub const COMMAND_COLUMNS: usize = 2;
+
+/// The number of rows of command buttons.
+pub const COMMAND_ROWS: usize = 4;
+
+/// The number of command buttons.
+pub const COMMAND_BUTTONS: usize = COMMAND_COLUMNS * COMMAND_ROWS;
+
+/// The dimensions of a command button (the size of the `second_buttons` sprites used).
+pub const COMMAND_BUTTON_SIZE: (usize, usize) = (18, 16);
+
+/// The space between command buttons in pixels.
+const COMMAND_BUTTON_SPACING: usize = 2;
+
+/// The part of the side panel occupied by the command buttons.
+pub const COMMANDS: Rect = Rect {
+    left: SIDE_PANEL.left + 4,
+    top: 54,
+    width: COMMAND_COLUMNS * (COMMAND_BUTTON_SIZE.0 + COMMAND_BUTTON_SPACING)
+        - COMMAND_BUTTON_SPACING,
+    height: COMMAND_ROWS * (COMMAND_BUTTON_SIZE.1 + COMMAND_BUTTON_SPACING)
+        - COMMAND_BUTTON_SPACING,
+};
+
+/// The part of the side panel showing the whole map.
+pub const MINIMAP: Rect = Rect {
+    left: SIDE_PANEL.left + 2,
+    top: 130,
+    width: SIDE_PANEL.width - 4,
+    height: 42,
+};
+
+/// The second button sprites used for released and pressed command buttons.
+const COMMAND_BUTTON_SPRITES: [usize; 2] = [2, 3];
+
+/// The wood sprite the side panel is covered with.
+const SIDE_PANEL_WOOD: usize = 2;
+
+/// The color the status line is cleared with.
+const STATUS_LINE_BACKGROUND: u8 = 0;
+
+/// The screen area of a command button, buttons are numbered row by row.
+pub fn command_button_rect(index: usize) -> Rect {
+    assert!(index < COMMAND_BUTTONS);
+    let (column, row) = (index % COMMAND_COLUMNS, index / COMMAND_COLUMNS);
+    let left = COMMANDS.left + column * (COMMAND_BUTTON_SIZE.0 + COMMAND_BUTTON_SPACING);
+    let top = COMMANDS.top + row * (COMMAND_BUTTON_SIZE.1 + COMMAND_BUTTON_SPACING);
+    Rect::from_ranges(
+        left..left + COMMAND_BUTTON_SIZE.0,
+        top..top + COMMAND_BUTTON_SIZE.1,
+    )
+}
+
+/// A part of the in-game screen.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Region {
+    Viewport,
+    StatusLine,
+    Resources,
+    CommandButton(usize),
+    Minimap,
+    /// The frame and the parts of the side panel with nothing in them.
+    Frame,
+}
+
+/// The part of the screen a point belongs to.
+pub fn region_at(x: usize, y: usize) -> Region {
+    if VIEWPORT.contains(x, y) {
+        Region::Viewport
+    } else if STATUS_LINE.contains(x, y) {
+        Region::StatusLine
+    } else if RESOURCES.contains(x, y) {
+        Region::Resources
+    } else if let Some(index) =
+        (0..COMMAND_BUTTONS).find(|index| command_button_rect(*index).contains(x, y))
+    {
+        Region::CommandButton(index)
+    } else if MINIMAP.contains(x, y) {
+        Region::Minimap
+    } else {
+        Region::Frame
+    }
+}
+
+/// The in-game screen frame, side panel and status line.
+pub struct Hud {
+    buttons: Vec<Button>,
+    /// The command button being held down.
+    pressed: Option<usize>,
+    /// The labels and amounts shown in the resource readout, one per line.
+    pub resources: Vec<(String, u32)>,
+    /// The names of the commands on the command buttons, in button order.
+    pub commands: Vec<String>,
+    pub status: String,
+}
+
+impl Default for Hud {
+    fn default() -> Hud {
+        Hud::new()
+    }
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud {
+            buttons: (0..COMMAND_BUTTONS)
+                .map(|index| Button::new(command_button_rect(index)))
+                .collect(),
+            pressed: None,
+            resources: Vec::new(),
+            commands: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    /// Process a frame of input. Returns the index of the command button clicked, if any. A
+    /// button counts as clicked when the mouse button is released over it.
+    pub fn update(&mut self, input: &Input) -> Option<usize> {
+        for (index, button) in self.buttons.iter_mut().enumerate() {
+            if button.update(input) {
+                self.pressed = Some(index);
+            }
+        }
+        if !input.mouse_buttons.is_released(MouseButton::Left) {
+            return None;
+        }
+        let pressed = self.pressed.take();
+        pressed.filter(|index| self.buttons[*index].is_hovered())
+    }
+
+    /// Draw everything except for the viewport contents.
+    pub fn draw(&self, skin: &Skin, image: &mut Image13h) {
+        let grafdat = skin.grafdat;
+        image.tile(grafdat.wood(SIDE_PANEL_WOOD), &SIDE_PANEL);
+        image.blit_clipped_with_transparency(grafdat.border(0), 0, 0);
+        image.blit_clipped_with_transparency(grafdat.border(1), 0, 0);
+        image.blit_clipped_with_transparency(grafdat.border(2), RIGHT_BORDER_LEFT as isize, 0);
+        image.blit_clipped_with_transparency(grafdat.border(3), 0, BOTTOM_BORDER_TOP as isize);
+
+        for (line, (label, amount)) in self.resources.iter().enumerate() {
+            let y = RESOURCES.top + line * fontdat::CHARACTER_HEIGHT;
+            if y + fontdat::CHARACTER_HEIGHT > RESOURCES.beyond_bottom() {
+                break;
+            }
+            skin.fontdat.draw_text(
+                image,
+                &format!("{}{}", label, amount),
+                RESOURCES.left as isize,
+                y as isize,
+            );
+        }
+
+        for (index, button) in self.buttons.iter().enumerate() {
+            let held = self.pressed == Some(index) && button.is_hovered();
+            let rect = button.rect();
+            image.blit_within(
+                grafdat.second_button(COMMAND_BUTTON_SPRITES[held as usize]),
+                rect.left as isize,
+                rect.top as isize,
+                &rect,
+            );
+            if let Some(letter) = self
+                .commands
+                .get(index)
+                .and_then(|command| command.chars().next())
+            {
+                let letter = letter.to_string();
+                let width = skin.fontdat.text_width(&letter);
+                skin.fontdat.draw_text(
+                    image,
+                    &letter,
+                    (rect.left + rect.width.saturating_sub(width) / 2) as isize,
+                    (rect.top + rect.height.saturating_sub(fontdat::CHARACTER_HEIGHT) / 2) as isize,
+                );
+            }
+        }
+
+        let hovered_command = self
+            .buttons
+            .iter()
+            .zip(&self.commands)
+            .find(|(button, _)| button.is_hovered())
+            .map(|(_, command)| command);
+        image.fill_rect(&STATUS_LINE, STATUS_LINE_BACKGROUND);
+        skin.fontdat.draw_text(
+            image,
+            hovered_command.unwrap_or(&self.status),
+            STATUS_LINE.left as isize,
+            STATUS_LINE.top as isize,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hud::{
+        command_button_rect, region_at, Hud, Region, COMMANDS, COMMAND_BUTTONS, SIDE_PANEL,
+    };
+    use crate::input::{Input, MousePosition};
+    use sdl2::mouse::MouseButton;
+
+    #[test]
+    fn test_command_button_rects_work() {
+        assert_eq!(command_button_rect(0).left, COMMANDS.left);
+        assert_eq!(command_button_rect(0).top, COMMANDS.top);
+        let last = command_button_rect(COMMAND_BUTTONS - 1);
+        assert_eq!(last.beyond_right(), COMMANDS.beyond_right());
+        assert_eq!(last.beyond_bottom(), COMMANDS.beyond_bottom());
+        assert!(COMMANDS.beyond_right() <= SIDE_PANEL.beyond_right());
+    }
+
+    #[test]
+    fn test_region_at_works() {
+        assert_eq!(region_at(11, 8), Region::Viewport);
+        assert_eq!(region_at(10, 8), Region::Frame);
+        assert_eq!(region_at(266, 175), Region::Viewport);
+        assert_eq!(region_at(100, 180), Region::StatusLine);
+        assert_eq!(region_at(280, 10), Region::Resources);
+        let rect = command_button_rect(3);
+        assert_eq!(region_at(rect.left, rect.top), Region::CommandButton(3));
+        assert_eq!(region_at(300, 150), Region::Minimap);
+        assert_eq!(region_at(319, 199), Region::Frame);
+    }
+
+    #[test]
+    fn test_command_buttons_work() {
+        let mut hud = Hud::new();
+        let rect = command_button_rect(5);
+        let position = MousePosition::new(rect.left + 1, rect.top + 1);
+
+        let mut input = Input::idle(position);
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        assert_eq!(hud.update(&input), None);
+        let mut input = Input::idle(position);
+        input.mouse_buttons.released.insert(MouseButton::Left);
+        assert_eq!(hud.update(&input), Some(5));
+
+        // Releasing the mouse button somewhere else cancels the click.
+        let mut input = Input::idle(position);
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        hud.update(&input);
+        let mut input = Input::idle(MousePosition::new(0, 0));
+        input.mouse_buttons.released.insert(MouseButton::Left);
+        assert_eq!(hud.update(&input), None);
+    }
+}
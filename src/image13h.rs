@@ -13,6 +13,7 @@
 //!   unknown.
 //! * `data` is `width * height` unsigned bytes containing color indices
 
+use std::cmp;
 use std::io;
 use std::ops;
 
@@ -31,6 +32,9 @@ pub const COLORS: usize = 256;
 /// The header size in bytes.
 pub const HEADER_SIZE: usize = 6;
 
+/// A color translation table, see `Image13h::blit_remapped_within()`.
+pub type Remap = [u8; 256];
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Image13h {
     data: Vec<u8>,
@@ -175,6 +179,108 @@ impl Image13h {
         }
     }
 
+    /// Like `blit_with_transparency`, but the source image may cross the destination image
+    /// boundary (or be completely outside of it), only the overlapping part is transferred.
+    pub fn blit_clipped_with_transparency(&mut self, image: &Image13h, x: isize, y: isize) {
+        let clip = Rect::from_ranges(0..self.width, 0..self.height);
+        self.blit_within(image, x, y, &clip);
+    }
+
+    /// Like `blit_clipped_with_transparency`, but only the pixels landing inside of `clip` (and
+    /// inside of this image) are transferred.
+    pub fn blit_within(&mut self, image: &Image13h, x: isize, y: isize, clip: &Rect) {
+        self.blit_mapped_within(image, x, y, clip, |pixel| pixel);
+    }
+
+    /// Like `blit_within`, but every transferred pixel's color is translated with `remap` (the
+    /// color `c` becomes `remap[c]`). Transparency is decided before the translation, so color 0
+    /// stays transparent whatever it's remapped to.
+    pub fn blit_remapped_within(
+        &mut self,
+        image: &Image13h,
+        x: isize,
+        y: isize,
+        clip: &Rect,
+        remap: &Remap,
+    ) {
+        self.blit_mapped_within(image, x, y, clip, |pixel| remap[pixel as usize]);
+    }
+
+    fn blit_mapped_within<F: Fn(u8) -> u8>(
+        &mut self,
+        image: &Image13h,
+        x: isize,
+        y: isize,
+        clip: &Rect,
+        map: F,
+    ) {
+        let right = cmp::min(clip.beyond_right(), self.width);
+        let bottom = cmp::min(clip.beyond_bottom(), self.height);
+        let columns = clip_span(x, image.width(), clip.left, right);
+        let lines = clip_span(y, image.height(), clip.top, bottom);
+        for src_line_index in lines {
+            let dst_line_index = (y + src_line_index as isize) as usize;
+            let src_line = image.line(src_line_index);
+            let dst_line = self.mut_line(dst_line_index);
+            for src_column_index in columns.clone() {
+                let pixel = src_line[src_column_index];
+                if pixel != 0 {
+                    dst_line[(x + src_column_index as isize) as usize] = map(pixel);
+                }
+            }
+        }
+    }
+
+    /// Fill `rect` with copies of `image` (with transparency), starting at its top left corner.
+    /// The copies on the right and bottom edges are clipped to the rect.
+    pub fn tile(&mut self, image: &Image13h, rect: &Rect) {
+        if image.width() == 0 || image.height() == 0 {
+            return;
+        }
+        for y in (rect.top..rect.beyond_bottom()).step_by(image.height()) {
+            for x in (rect.left..rect.beyond_right()).step_by(image.width()) {
+                self.blit_within(image, x as isize, y as isize, rect);
+            }
+        }
+    }
+
+    /// Set a single pixel. Coordinates outside of the image are ignored.
+    pub fn set_pixel_clipped(&mut self, x: isize, y: isize, color: u8) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.data[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    /// Fill a rectangle with a color. The parts of the rectangle outside of the image are
+    /// ignored.
+    pub fn fill_rect(&mut self, rect: &Rect, color: u8) {
+        let right = cmp::min(rect.beyond_right(), self.width);
+        let bottom = cmp::min(rect.beyond_bottom(), self.height);
+        if rect.left >= right {
+            return;
+        }
+        for line in rect.top..bottom {
+            self.mut_line(line)[rect.left..right].fill(color);
+        }
+    }
+
+    /// Draw a 1 pixel wide outline of a rectangle (the outline is inside of the rectangle). The
+    /// parts of the outline outside of the image are ignored.
+    pub fn draw_rect(&mut self, rect: &Rect, color: u8) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let (left, top) = (rect.left, rect.top);
+        let (right, bottom) = (rect.right_inclusive(), rect.bottom_inclusive());
+        self.fill_rect(&Rect::from_ranges(left..right + 1, top..top + 1), color);
+        self.fill_rect(
+            &Rect::from_ranges(left..right + 1, bottom..bottom + 1),
+            color,
+        );
+        self.fill_rect(&Rect::from_ranges(left..left + 1, top..bottom + 1), color);
+        self.fill_rect(&Rect::from_ranges(right..right + 1, top..bottom + 1), color);
+    }
+
     /// Fill the image with a color.
     pub fn fill(&mut self, color: u8) {
         let len = self.data.len();
@@ -183,7 +289,15 @@ impl Image13h {
     }
 }
 
-#[derive(Debug)]
+/// Get the range of source indices that land inside of the `lower..upper` destination span when a
+/// `length`-long source span is placed at `position`.
+fn clip_span(position: isize, length: usize, lower: usize, upper: usize) -> ops::Range<usize> {
+    let start = cmp::max(0, lower as isize - position) as usize;
+    let end = cmp::max(0, cmp::min(length as isize, upper as isize - position)) as usize;
+    start..cmp::max(start, end)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Rect {
     /// The position of the left border, inclusive.
     pub left: usize,
@@ -240,9 +354,55 @@ pub fn indices_to_rgb<T: io::Write>(indices: &[u8], palette: &[u8], mut writer:
     }
 }
 
+/// Draw the non-transparent pixels of `image` over `rgb`, an RGB buffer of the same dimensions
+/// (see `indices_to_rgb()`), all in `color`. This is meant for text that has to be readable
+/// regardless of the palette the buffer was produced with.
+pub fn overlay_rgb(image: &Image13h, rgb: &mut [u8], color: [u8; 3]) {
+    debug_assert_eq!(image.data.len() * 3, rgb.len());
+    for (index, pixel) in image.data.iter().zip(rgb.chunks_exact_mut(3)) {
+        if *index != 0 {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// Darken a rectangle of `rgb`, an RGB buffer `width` pixels wide. The parts of the rectangle
+/// outside of the buffer are ignored.
+pub fn shade_rgb(rgb: &mut [u8], width: usize, rect: &Rect) {
+    let height = rgb.len() / 3 / width;
+    let right = cmp::min(rect.beyond_right(), width);
+    if rect.left >= right {
+        return;
+    }
+    for line in rect.top..cmp::min(rect.beyond_bottom(), height) {
+        let start = (line * width + rect.left) * 3;
+        let end = (line * width + right) * 3;
+        for component in &mut rgb[start..end] {
+            *component /= 4;
+        }
+    }
+}
+
+/// Fill a rectangle of `rgb`, an RGB buffer `width` pixels wide, with `color`. The parts of the
+/// rectangle outside of the buffer are ignored.
+pub fn fill_rect_rgb(rgb: &mut [u8], width: usize, rect: &Rect, color: [u8; 3]) {
+    let height = rgb.len() / 3 / width;
+    let right = cmp::min(rect.beyond_right(), width);
+    if rect.left >= right {
+        return;
+    }
+    for line in rect.top..cmp::min(rect.beyond_bottom(), height) {
+        let start = (line * width + rect.left) * 3;
+        let end = (line * width + right) * 3;
+        for pixel in rgb[start..end].chunks_exact_mut(3) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::image13h::{indices_to_rgb, Image13h, Rect};
+    use crate::image13h::{fill_rect_rgb, indices_to_rgb, overlay_rgb, shade_rgb, Image13h, Rect};
 
     // 3 by 2 image, we have 1 byte extra at the end to see if we ignore it correctly
     static GOOD_DATA: [u8; 13] = [3, 0, 2, 0, 1, 0, 1, 2, 3, 4, 5, 6, 7];
@@ -354,6 +514,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_blit_clipped_with_transparency_works() {
+        let mut main_image = Image13h::filled_with_color(3, 3, 9);
+        let mut src_image = Image13h::empty(2, 2);
+        src_image.data_mut().copy_from_slice(&[1, 2, 3, 0]);
+        main_image.blit_clipped_with_transparency(&src_image, -1, 2);
+        main_image.blit_clipped_with_transparency(&src_image, 2, -1);
+        main_image.blit_clipped_with_transparency(&src_image, 5, 5);
+        main_image.blit_clipped_with_transparency(&src_image, -5, -5);
+        assert_eq!(main_image.data(), &[9, 9, 3, 9, 9, 9, 2, 9, 9]);
+    }
+
+    #[test]
+    fn test_blit_within_works() {
+        let mut main_image = Image13h::filled_with_color(4, 3, 9);
+        let src_image = Image13h::filled_with_color(3, 3, 1);
+        main_image.blit_within(&src_image, 0, -1, &Rect::from_ranges(1..3, 1..5));
+        assert_eq!(main_image.data(), &[9, 9, 9, 9, 9, 1, 1, 9, 9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn test_blit_remapped_within_works() {
+        let mut main_image = Image13h::filled_with_color(3, 1, 9);
+        let mut src_image = Image13h::empty(3, 1);
+        src_image.data_mut().copy_from_slice(&[1, 0, 2]);
+        let mut remap = [0; 256];
+        remap[0] = 7;
+        remap[1] = 5;
+        remap[2] = 2;
+        main_image.blit_remapped_within(&src_image, 0, 0, &Rect::from_ranges(0..3, 0..1), &remap);
+        assert_eq!(main_image.data(), &[5, 9, 2]);
+    }
+
+    #[test]
+    fn test_tile_works() {
+        let mut main_image = Image13h::empty(5, 2);
+        let mut src_image = Image13h::empty(2, 1);
+        src_image.data_mut().copy_from_slice(&[1, 2]);
+        main_image.tile(&src_image, &Rect::from_ranges(0..5, 1..2));
+        assert_eq!(main_image.data(), &[0, 0, 0, 0, 0, 1, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_fill_rect_works() {
+        let mut image = Image13h::empty(3, 3);
+        image.fill_rect(&Rect::from_ranges(1..5, 2..7), 1);
+        image.set_pixel_clipped(0, 0, 2);
+        image.set_pixel_clipped(-1, 0, 2);
+        image.set_pixel_clipped(3, 0, 2);
+        assert_eq!(image.data(), &[2, 0, 0, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_draw_rect_works() {
+        let mut image = Image13h::empty(4, 4);
+        image.draw_rect(&Rect::from_ranges(1..4, 0..3), 1);
+        assert_eq!(
+            image.data(),
+            &[0, 1, 1, 1, 0, 1, 0, 1, 0, 1, 1, 1, 0, 0, 0, 0],
+        );
+    }
+
     #[test]
     fn test_indices_to_rgb_works() {
         let indices = [1, 2, 0];
@@ -363,4 +585,17 @@ mod tests {
         indices_to_rgb(&indices, &palette, &mut buffer);
         assert_eq!(buffer, expected_rgb);
     }
+
+    #[test]
+    fn test_overlay_rgb_works() {
+        let mut image = Image13h::empty(2, 1);
+        image.set_pixel_clipped(1, 0, 7);
+        let mut rgb = vec![100; 6];
+        overlay_rgb(&image, &mut rgb, [1, 2, 3]);
+        assert_eq!(rgb, [100, 100, 100, 1, 2, 3]);
+        shade_rgb(&mut rgb, 2, &Rect::from_ranges(0..1, 0..5));
+        assert_eq!(rgb, [25, 25, 25, 1, 2, 3]);
+        fill_rect_rgb(&mut rgb, 2, &Rect::from_ranges(1..3, 0..1), [4, 5, 6]);
+        assert_eq!(rgb, [25, 25, 25, 4, 5, 6]);
+    }
 }
@@ -1,64 +1,343 @@
+use crate::image13h::{self, Rect};
 use sdl2::{
     event::{Event, EventPollIterator},
     keyboard::Scancode,
     mouse::MouseButton,
 };
+use std::cmp;
+use std::collections::HashSet;
+use std::hash::Hash;
 
+/// How far (in logical pixels, in any direction) the mouse needs to travel with a button held
+/// for the movement to be considered dragging rather than clicking.
+pub const DRAG_THRESHOLD: usize = 3;
+
+/// Turns SDL events into per-frame `Input` snapshots. The processor remembers which keys and
+/// mouse buttons are held between frames, so that held buttons and dragging can be reported.
 pub struct InputProcessor {
-    // TODO: we need to store something like this in order to handle mouse dragging with a key pressed
-    // etc.
-    // key_pressed: Option<Scancode>,
-    // mouse_button_pressed: Option<MouseButton>,
     mouse_position: MousePosition,
+    keys_held: HashSet<Scancode>,
+    mouse_buttons_held: HashSet<MouseButton>,
+    drag: Option<Drag>,
+    scale: u32,
 }
 
 impl InputProcessor {
     pub fn new() -> InputProcessor {
         InputProcessor {
             mouse_position: MousePosition::new(0, 0),
+            keys_held: HashSet::new(),
+            mouse_buttons_held: HashSet::new(),
+            drag: None,
+            scale: 2,
         }
     }
 
+    /// Set how many times the window is larger than the logical screen, window coordinates
+    /// are divided by this when converted to logical ones.
+    pub fn set_scale(&mut self, scale: u32) {
+        assert!(scale > 0);
+        self.scale = scale;
+    }
+
     pub fn process_frame_events(&mut self, iterator: EventPollIterator) -> InputProcessorResult {
-        let mut key_pressed: Option<Scancode> = None;
-        let mut mouse_button_pressed: Option<MouseButton> = None;
-        for event in iterator {
+        self.process_events(iterator)
+    }
+
+    /// Like `process_frame_events`, but works with any source of events. All the events that
+    /// happened since the previous frame are expected to be passed at once.
+    pub fn process_events<T: IntoIterator<Item = Event>>(
+        &mut self,
+        events: T,
+    ) -> InputProcessorResult {
+        let mut keys = ButtonState::new(&self.keys_held);
+        let mut mouse_buttons = ButtonState::new(&self.mouse_buttons_held);
+        let mut wheel = 0;
+        let mut text = String::new();
+        let mut keys_typed = Vec::new();
+        // A drag that ended during the previous frame has been reported already.
+        if let Some(Drag {
+            phase: DragPhase::Ended,
+            ..
+        }) = self.drag
+        {
+            self.drag = None;
+        }
+
+        for event in events {
             match event {
                 Event::Quit { .. } => return InputProcessorResult::Quit,
-                Event::KeyDown { scancode, .. } => {
-                    key_pressed = scancode;
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat,
+                    ..
+                } => {
+                    if !repeat {
+                        keys.press(scancode);
+                    }
+                    keys_typed.push(scancode);
                 }
-                Event::MouseButtonDown { mouse_btn, .. } => {
-                    mouse_button_pressed = Some(mouse_btn);
+                Event::TextInput { text: typed, .. } => {
+                    text.push_str(&typed);
+                }
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => {
+                    keys.release(scancode);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => {
+                    self.mouse_position = MousePosition::from_window(x, y, self.scale);
+                    mouse_buttons.press(mouse_btn);
+                    if self.drag.is_none() {
+                        self.drag = Some(Drag {
+                            button: mouse_btn,
+                            start: self.mouse_position,
+                            end: self.mouse_position,
+                            phase: DragPhase::Pending,
+                        });
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn, x, y, ..
+                } => {
+                    self.mouse_position = MousePosition::from_window(x, y, self.scale);
+                    mouse_buttons.release(mouse_btn);
+                    if let Some(drag) = &mut self.drag {
+                        if drag.button == mouse_btn {
+                            drag.end = self.mouse_position;
+                            if drag.phase == DragPhase::Pending {
+                                // The mouse hasn't moved far enough, this was just a click.
+                                self.drag = None;
+                            } else {
+                                drag.phase = DragPhase::Ended;
+                            }
+                        }
+                    }
                 }
                 Event::MouseMotion { x, y, .. } => {
-                    // We currently have to divide the coordinates by two, because we
-                    // scale the screen to be double the game's original resolution.
-                    self.mouse_position = MousePosition::new(x as usize / 2, y as usize / 2);
+                    self.mouse_position = MousePosition::from_window(x, y, self.scale);
+                    if let Some(drag) = &mut self.drag {
+                        drag.end = self.mouse_position;
+                        if drag.phase == DragPhase::Pending
+                            && drag.start.distance(&drag.end) >= DRAG_THRESHOLD
+                        {
+                            drag.phase = DragPhase::Started;
+                        }
+                    }
+                }
+                Event::MouseWheel { y, .. } => {
+                    wheel += y;
                 }
                 _ => (),
             }
         }
-        InputProcessorResult::Input(Input {
+
+        let drag = match self.drag {
+            Some(drag) if drag.phase != DragPhase::Pending => {
+                // Only report the start once, the following frames see the drag in progress.
+                if drag.phase == DragPhase::Started {
+                    self.drag = Some(Drag {
+                        phase: DragPhase::InProgress,
+                        ..drag
+                    });
+                }
+                Some(drag)
+            }
+            _ => None,
+        };
+
+        self.keys_held = keys.held.clone();
+        self.mouse_buttons_held = mouse_buttons.held.clone();
+        let modifiers = keys.modifiers();
+        InputProcessorResult::Input(Box::new(Input {
             mouse_position: self.mouse_position,
-            key_pressed,
-            mouse_button_pressed,
-        })
+            keys,
+            mouse_buttons,
+            modifiers,
+            wheel,
+            drag,
+            text,
+            keys_typed,
+        }))
+    }
+}
+
+impl Default for InputProcessor {
+    fn default() -> Self {
+        InputProcessor::new()
     }
 }
 
 pub enum InputProcessorResult {
     Quit,
-    Input(Input),
+    Input(Box<Input>),
 }
 
+/// The state of the input devices during a single frame.
+#[derive(Clone, Debug)]
 pub struct Input {
     pub mouse_position: MousePosition,
-    pub key_pressed: Option<Scancode>,
-    pub mouse_button_pressed: Option<MouseButton>,
+    pub keys: ButtonState<Scancode>,
+    pub mouse_buttons: ButtonState<MouseButton>,
+    pub modifiers: Modifiers,
+    /// The vertical mouse wheel movement, positive values mean scrolling away from the user.
+    pub wheel: i32,
+    /// The current drag, if any. A drag is reported in every frame between (and including) the
+    /// frame it started and the frame it ended in.
+    pub drag: Option<Drag>,
+    /// The text typed during the frame, as reported by the operating system (with the keyboard
+    /// layout and the input method applied).
+    pub text: String,
+    /// The keys pressed during the frame including the keyboard auto-repeat presses, in order.
+    /// Text editing should use this instead of `keys`.
+    pub keys_typed: Vec<Scancode>,
+}
+
+impl Input {
+    /// Create an `Input` with nothing pressed and the mouse at `mouse_position`.
+    pub fn idle(mouse_position: MousePosition) -> Input {
+        Input {
+            mouse_position,
+            keys: ButtonState::default(),
+            mouse_buttons: ButtonState::default(),
+            modifiers: Modifiers::default(),
+            wheel: 0,
+            drag: None,
+            text: String::new(),
+            keys_typed: Vec::new(),
+        }
+    }
+
+    /// Was any key pressed during the frame?
+    pub fn any_key_pressed(&self) -> bool {
+        !self.keys.pressed.is_empty()
+    }
+
+    /// Was `button` pressed during the frame?
+    pub fn mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.is_pressed(button)
+    }
+}
+
+/// Per-frame state of a set of buttons (keyboard keys or mouse buttons).
+#[derive(Clone, Debug)]
+pub struct ButtonState<T: Eq + Hash> {
+    /// Buttons that went down during the frame.
+    pub pressed: HashSet<T>,
+    /// Buttons that went up during the frame.
+    pub released: HashSet<T>,
+    /// Buttons that are down at the end of the frame.
+    pub held: HashSet<T>,
+}
+
+impl<T: Copy + Eq + Hash> ButtonState<T> {
+    fn new(held: &HashSet<T>) -> ButtonState<T> {
+        ButtonState {
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            held: held.clone(),
+        }
+    }
+
+    fn press(&mut self, button: T) {
+        self.pressed.insert(button);
+        self.held.insert(button);
+    }
+
+    fn release(&mut self, button: T) {
+        self.released.insert(button);
+        self.held.remove(&button);
+    }
+
+    pub fn is_pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn is_released(&self, button: T) -> bool {
+        self.released.contains(&button)
+    }
+
+    pub fn is_held(&self, button: T) -> bool {
+        self.held.contains(&button)
+    }
+}
+
+impl ButtonState<Scancode> {
+    /// The modifiers held, based on the held keys.
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers::from_keys(&self.held)
+    }
+}
+
+impl<T: Eq + Hash> Default for ButtonState<T> {
+    fn default() -> Self {
+        ButtonState {
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            held: HashSet::new(),
+        }
+    }
+}
+
+/// Keyboard modifiers held at the end of the frame. Left and right variants are not
+/// distinguished.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    fn from_keys(keys: &HashSet<Scancode>) -> Modifiers {
+        let any = |a, b| keys.contains(&a) || keys.contains(&b);
+        Modifiers {
+            shift: any(Scancode::LShift, Scancode::RShift),
+            ctrl: any(Scancode::LCtrl, Scancode::RCtrl),
+            alt: any(Scancode::LAlt, Scancode::RAlt),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DragPhase {
+    /// A button is held but the mouse hasn't moved far enough yet. Never reported in `Input`.
+    Pending,
+    /// The drag started during this frame.
+    Started,
+    InProgress,
+    /// The drag ended (the button was released) during this frame.
+    Ended,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Drag {
+    pub button: MouseButton,
+    pub start: MousePosition,
+    /// The current mouse position or, if the drag ended, the position where it ended.
+    pub end: MousePosition,
+    pub phase: DragPhase,
+}
+
+impl Drag {
+    /// The rectangle spanned by the drag, both the start and the end positions are inside.
+    pub fn rect(&self) -> Rect {
+        let (x1, x2) = (
+            cmp::min(self.start.x, self.end.x),
+            cmp::max(self.start.x, self.end.x),
+        );
+        let (y1, y2) = (
+            cmp::min(self.start.y, self.end.y),
+            cmp::max(self.start.y, self.end.y),
+        );
+        Rect::from_ranges(x1..x2 + 1, y1..y2 + 1)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct MousePosition {
     pub x: usize,
     pub y: usize,
@@ -69,4 +348,197 @@ impl MousePosition {
         // TODO: Check the range
         MousePosition { x, y }
     }
+
+    /// Convert window coordinates to logical screen coordinates.
+    fn from_window(x: i32, y: i32, scale: u32) -> MousePosition {
+        let scale = scale as usize;
+        MousePosition::new(
+            cmp::min(cmp::max(x, 0) as usize / scale, image13h::SCREEN_WIDTH - 1),
+            cmp::min(cmp::max(y, 0) as usize / scale, image13h::SCREEN_HEIGHT - 1),
+        )
+    }
+
+    /// The Chebyshev distance between two positions.
+    pub fn distance(&self, other: &MousePosition) -> usize {
+        cmp::max(
+            (self.x as isize - other.x as isize).unsigned_abs(),
+            (self.y as isize - other.y as isize).unsigned_abs(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{DragPhase, Input, InputProcessor, InputProcessorResult, MousePosition};
+    use sdl2::event::Event;
+    use sdl2::keyboard::{Mod, Scancode};
+    use sdl2::mouse::{MouseButton, MouseState, MouseWheelDirection};
+
+    fn key_down(scancode: Scancode) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: None,
+            scancode: Some(scancode),
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn key_up(scancode: Scancode) -> Event {
+        Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: None,
+            scancode: Some(scancode),
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn button_down(x: i32, y: i32) -> Event {
+        Event::MouseButtonDown {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x,
+            y,
+        }
+    }
+
+    fn button_up(x: i32, y: i32) -> Event {
+        Event::MouseButtonUp {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x,
+            y,
+        }
+    }
+
+    fn motion(x: i32, y: i32) -> Event {
+        Event::MouseMotion {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mousestate: MouseState::from_sdl_state(0),
+            x,
+            y,
+            xrel: 0,
+            yrel: 0,
+        }
+    }
+
+    fn process(processor: &mut InputProcessor, events: Vec<Event>) -> Input {
+        match processor.process_events(events) {
+            InputProcessorResult::Input(input) => *input,
+            InputProcessorResult::Quit => panic!("Unexpected quit"),
+        }
+    }
+
+    #[test]
+    fn test_simultaneous_and_held_keys_work() {
+        let mut processor = InputProcessor::new();
+        let input = process(
+            &mut processor,
+            vec![key_down(Scancode::LShift), key_down(Scancode::A)],
+        );
+        assert!(input.keys.is_pressed(Scancode::LShift));
+        assert!(input.keys.is_pressed(Scancode::A));
+        assert!(input.modifiers.shift);
+        assert!(!input.modifiers.ctrl);
+
+        let input = process(&mut processor, vec![key_up(Scancode::A)]);
+        assert!(input.keys.pressed.is_empty());
+        assert!(input.keys.is_released(Scancode::A));
+        assert!(input.keys.is_held(Scancode::LShift));
+        assert!(!input.keys.is_held(Scancode::A));
+        assert!(input.modifiers.shift);
+    }
+
+    #[test]
+    fn test_text_and_repeated_keys_work() {
+        let mut processor = InputProcessor::new();
+        let repeated = Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: None,
+            scancode: Some(Scancode::Backspace),
+            keymod: Mod::NOMOD,
+            repeat: true,
+        };
+        let text = |text: &str| Event::TextInput {
+            timestamp: 0,
+            window_id: 0,
+            text: text.to_string(),
+        };
+        let input = process(
+            &mut processor,
+            vec![
+                key_down(Scancode::Backspace),
+                repeated,
+                text("ż"),
+                text("a"),
+            ],
+        );
+        assert_eq!(input.text, "ża");
+        assert_eq!(input.keys_typed, vec![Scancode::Backspace; 2]);
+        assert_eq!(input.keys.pressed.len(), 1);
+    }
+
+    #[test]
+    fn test_wheel_works() {
+        let mut processor = InputProcessor::new();
+        let wheel = Event::MouseWheel {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            x: 0,
+            y: -1,
+            direction: MouseWheelDirection::Normal,
+        };
+        let input = process(&mut processor, vec![wheel.clone(), wheel]);
+        assert_eq!(input.wheel, -2);
+        let input = process(&mut processor, vec![]);
+        assert_eq!(input.wheel, 0);
+    }
+
+    #[test]
+    fn test_click_is_not_a_drag() {
+        let mut processor = InputProcessor::new();
+        let input = process(&mut processor, vec![button_down(20, 20), motion(22, 22)]);
+        assert!(input.mouse_button_pressed(MouseButton::Left));
+        assert_eq!(input.drag, None);
+        let input = process(&mut processor, vec![button_up(22, 22)]);
+        assert!(input.mouse_buttons.is_released(MouseButton::Left));
+        assert_eq!(input.drag, None);
+    }
+
+    #[test]
+    fn test_dragging_works() {
+        let mut processor = InputProcessor::new();
+        let input = process(&mut processor, vec![button_down(20, 40), motion(60, 30)]);
+        let drag = input.drag.unwrap();
+        assert_eq!(drag.phase, DragPhase::Started);
+        assert_eq!(drag.start, MousePosition::new(10, 20));
+        assert_eq!(drag.end, MousePosition::new(30, 15));
+
+        let input = process(&mut processor, vec![motion(62, 32)]);
+        assert_eq!(input.drag.unwrap().phase, DragPhase::InProgress);
+        assert!(input.mouse_buttons.is_held(MouseButton::Left));
+
+        let input = process(&mut processor, vec![button_up(64, 34)]);
+        let drag = input.drag.unwrap();
+        assert_eq!(drag.phase, DragPhase::Ended);
+        let rect = drag.rect();
+        assert_eq!((rect.left, rect.top), (10, 17));
+        assert_eq!((rect.width, rect.height), (23, 4));
+
+        let input = process(&mut processor, vec![]);
+        assert_eq!(input.drag, None);
+    }
 }
@@ -0,0 +1,291 @@
+//! Localization: the user interface texts in the supported languages.
+//!
+//! Every language has a catalog of messages keyed by message IDs. The Polish and English
+//! catalogs are built into the game, a catalog file in the `l10n` subdirectory of the
+//! [configuration directory](../config/index.html) (`l10n/pl.cfg`, for example) overrides the
+//! built-in messages of its language. Messages missing from a catalog are taken from the
+//! English one.
+//!
+//! # Catalog file format
+//!
+//! The file is a sequence of `id = message` lines. Empty lines and lines starting with `#` are
+//! ignored, `{}` in a message is replaced with an argument (see `Localization::format()`). Messages
+//! can only use the characters `Fontdat::can_draw()` accepts. `shortcut.ACTION` entries set the
+//! keys of menu actions in the language, so that the keys match the initials of the menu items.
+//! `ACTION` is one of `Action::name()` and the key is one of `actions::key_name()`. The shortcuts
+//! only apply in the menus, see `actions::Context`. Example:
+//!
+//! ```text
+//! options.title = Opcje
+//! options.language = Język: {}
+//! shortcut.new-game = N
+//! ```
+
+use crate::actions::{self, Action, Bindings};
+use crate::config;
+use crate::fontdat::Fontdat;
+use sdl2::keyboard::Scancode;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path;
+
+/// The language messages missing from other catalogs are taken from.
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+/// The prefix of the IDs of keyboard shortcuts.
+const SHORTCUT_PREFIX: &str = "shortcut.";
+
+/// The catalogs built into the game.
+const BUILTIN_CATALOGS: [(&str, &str); 2] = [
+    ("pl", include_str!("../l10n/pl.cfg")),
+    ("en", include_str!("../l10n/en.cfg")),
+];
+
+/// The messages of a single language.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Load a catalog from a reader.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read, if a line can't be parsed or
+    /// if a message contains characters `fontdat` can't draw.
+    pub fn load<T: io::Read>(reader: T, fontdat: &Fontdat) -> Result<Catalog, String> {
+        let mut catalog = Catalog::default();
+        for (number, line) in io::BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {}", number + 1, message);
+            let (id, message) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected id = message"))?;
+            let (id, message) = (id.trim(), message.trim());
+            let text = message.replace("{}", "");
+            if let Some(c) = text.chars().find(|c| !fontdat.can_draw(*c)) {
+                return Err(error(&format!("{c:?} can't be displayed")));
+            }
+            catalog.messages.insert(id.to_string(), message.to_string());
+        }
+        Ok(catalog)
+    }
+
+    /// The built-in catalog of a language, `None` for unsupported languages.
+    pub fn builtin(language: &str) -> Option<Catalog> {
+        BUILTIN_CATALOGS
+            .iter()
+            .find(|(builtin, _)| *builtin == language)
+            .map(|(_, data)| {
+                Catalog::load(data.as_bytes(), &Fontdat::empty()).expect("Invalid built-in catalog")
+            })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.messages.get(id).map(String::as_str)
+    }
+
+    /// Add the messages of `other` to the catalog, replacing the messages with the same IDs.
+    pub fn merge(&mut self, other: Catalog) {
+        self.messages.extend(other.messages);
+    }
+
+    /// The IDs of all messages, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+}
+
+/// The messages of the language the game is played in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Localization {
+    language: String,
+    catalog: Catalog,
+    fallback: Catalog,
+}
+
+impl Localization {
+    pub fn new(language: &str, catalog: Catalog, fallback: Catalog) -> Localization {
+        Localization {
+            language: language.to_string(),
+            catalog,
+            fallback,
+        }
+    }
+
+    /// The built-in messages of `language`. Unsupported languages get the English messages.
+    pub fn builtin(language: &str) -> Localization {
+        let fallback = Catalog::builtin(FALLBACK_LANGUAGE).unwrap();
+        let catalog = Catalog::builtin(language).unwrap_or_default();
+        Localization::new(language, catalog, fallback)
+    }
+
+    /// The built-in messages of `language`, overridden by the catalog file in the configuration
+    /// directory, if there is one. The file's messages are checked against `fontdat`, extra glyphs
+    /// included.
+    pub fn load_from_config_dir(language: &str, fontdat: &Fontdat) -> Result<Localization, String> {
+        let mut localization = Localization::builtin(language);
+        if let Some(path) = path(language).filter(|path| path.is_file()) {
+            let file = fs::File::open(&path).map_err(|e| e.to_string())?;
+            let catalog = Catalog::load(file, fontdat)
+                .map_err(|e| format!("Cannot load messages from {path:?}: {e}"))?;
+            localization.catalog.merge(catalog);
+        }
+        Ok(localization)
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The message with the ID, in English if the language's catalog doesn't have it. Missing
+    /// messages are presented as their IDs, so they're easy to spot.
+    pub fn text<'a>(&'a self, id: &'a str) -> &'a str {
+        self.catalog
+            .get(id)
+            .or_else(|| self.fallback.get(id))
+            .unwrap_or(id)
+    }
+
+    /// The message with the ID, with the `{}` placeholders replaced by `arguments`, in order.
+    pub fn format(&self, id: &str, arguments: &[&str]) -> String {
+        let mut pieces = self.text(id).split("{}");
+        let mut text = pieces.next().unwrap_or_default().to_string();
+        let mut arguments = arguments.iter();
+        for piece in pieces {
+            text.push_str(arguments.next().unwrap_or(&""));
+            text.push_str(piece);
+        }
+        text
+    }
+
+    /// The name of a language in this language.
+    pub fn language_name(&self, language: &str) -> String {
+        self.text(&format!("language.{language}")).to_string()
+    }
+
+    /// The language's keyboard shortcuts: the keys of the actions with `shortcut.` entries.
+    /// Entries with unknown actions or keys are ignored.
+    pub fn shortcuts(&self) -> Vec<(Action, Scancode)> {
+        let mut ids: Vec<&str> = self
+            .catalog
+            .ids()
+            .chain(self.fallback.ids())
+            .filter(|id| id.starts_with(SHORTCUT_PREFIX))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter()
+            .filter_map(|id| {
+                let action = Action::from_name(&id[SHORTCUT_PREFIX.len()..])?;
+                let key = actions::key_from_name(self.text(id))?;
+                Some((action, key))
+            })
+            .collect()
+    }
+
+    /// The default key bindings with the language's shortcuts.
+    pub fn default_bindings(&self) -> Bindings {
+        let mut bindings = Bindings::defaults();
+        for (action, key) in self.shortcuts() {
+            bindings.rebind(action, &[key]);
+        }
+        bindings
+    }
+}
+
+/// The location of the catalog file of a language, `None` if the configuration directory can't
+/// be determined.
+pub fn path(language: &str) -> Option<path::PathBuf> {
+    config::config_dir().map(|dir| dir.join("l10n").join(format!("{language}.cfg")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, Context};
+    use crate::fontdat::{self, Fontdat};
+    use crate::image13h::Image13h;
+    use crate::l10n::{Catalog, Localization, BUILTIN_CATALOGS, FALLBACK_LANGUAGE};
+    use crate::settings::LANGUAGES;
+    use sdl2::keyboard::Scancode;
+
+    #[test]
+    fn test_builtin_catalogs_are_complete() {
+        let fallback = Catalog::builtin(FALLBACK_LANGUAGE).unwrap();
+        for language in LANGUAGES {
+            let catalog = Catalog::builtin(language).unwrap();
+            let mut missing: Vec<_> = fallback
+                .ids()
+                .filter(|id| catalog.get(id).is_none())
+                .collect();
+            missing.sort_unstable();
+            assert!(missing.is_empty(), "{} is missing {:?}", language, missing);
+        }
+        assert_eq!(BUILTIN_CATALOGS.len(), LANGUAGES.len());
+    }
+
+    #[test]
+    fn test_fallback_works() {
+        let fontdat = Fontdat::empty();
+        let catalog = Catalog::load("back = Wróć\n".as_bytes(), &fontdat).unwrap();
+        let fallback = Catalog::load(
+            "back = Back\noptions.title = Options\n".as_bytes(),
+            &fontdat,
+        );
+        let localization = Localization::new("pl", catalog, fallback.unwrap());
+        assert_eq!(localization.text("back"), "Wróć");
+        assert_eq!(localization.text("options.title"), "Options");
+        assert_eq!(localization.text("no.such.message"), "no.such.message");
+        assert_eq!(Localization::builtin("xx").text("back"), "Back");
+    }
+
+    #[test]
+    fn test_formatting_works() {
+        let localization = Localization::builtin("pl");
+        assert_eq!(
+            localization.format("options.language", &[&localization.language_name("en")]),
+            "Język: angielski"
+        );
+        assert_eq!(localization.format("back", &["ignored"]), "Wróć");
+    }
+
+    #[test]
+    fn test_shortcuts_work() {
+        let polish = Localization::builtin("pl").shortcuts();
+        assert!(polish.contains(&(Action::LoadGame, Scancode::W)));
+        assert!(polish.contains(&(Action::Quit, Scancode::K)));
+        let english = Localization::builtin("en").shortcuts();
+        assert!(english.contains(&(Action::LoadGame, Scancode::L)));
+        assert!(english.contains(&(Action::Quit, Scancode::Q)));
+
+        let bindings = Localization::builtin("en").default_bindings();
+        assert_eq!(bindings.keys(Action::Quit), vec![Scancode::Q]);
+        assert_eq!(bindings.action(Scancode::K, Context::Menu), None);
+        assert_eq!(
+            bindings.action(Scancode::Escape, Context::Menu),
+            Some(Action::Back)
+        );
+    }
+
+    #[test]
+    fn test_loading_errors_are_reported() {
+        let mut fontdat = Fontdat::empty();
+        assert_eq!(
+            Catalog::load("back".as_bytes(), &fontdat),
+            Err("Line 1: expected id = message".to_string())
+        );
+        assert_eq!(
+            Catalog::load("\nback = ←".as_bytes(), &fontdat),
+            Err("Line 2: '←' can't be displayed".to_string())
+        );
+        // Characters drawn with extra glyphs are fine.
+        fontdat.set_extra_glyph('←', Image13h::empty(5, fontdat::CHARACTER_HEIGHT));
+        let catalog = Catalog::load("\nback = ←".as_bytes(), &fontdat).unwrap();
+        assert_eq!(catalog.get("back"), Some("←"));
+    }
+}
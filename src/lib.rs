@@ -1,8 +1,32 @@
+pub mod actions;
+pub mod animation;
+pub mod args;
 pub mod audio;
+pub mod autotile;
+pub mod combat;
+pub mod commands;
+pub mod config;
+pub mod cursor;
+pub mod debug;
 pub mod fontdat;
+pub mod fontimport;
 pub mod grafdat;
+pub mod hud;
 pub mod image13h;
 pub mod input;
+pub mod l10n;
+pub mod log;
+pub mod map;
+pub mod movement;
 pub mod paldat;
+pub mod pathfinding;
+pub mod playercolors;
 pub mod ppm;
+pub mod replay;
+pub mod selection;
+pub mod settings;
+pub mod sfx;
 pub mod sounddat;
+pub mod ui;
+pub mod units;
+pub mod viewport;
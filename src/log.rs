@@ -0,0 +1,344 @@
+//! Logging.
+//!
+//! Messages have a `Level` and a `Target`, the subsystem they come from. They're written to
+//! stderr and, optionally, to a log file as `[level target] message` lines. The most recent
+//! messages are also kept in memory so they can be shown on screen (see `draw_overlay()`).
+//!
+//! Use the `error!`, `warn!`, `info!`, `debug!` and `trace!` macros to log:
+//!
+//! ```
+//! use openpol::log::Target;
+//! openpol::warn!(Target::Audio, "Music file {:?} not found", "track2.ogg");
+//! ```
+
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{self, Image13h, Rect};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// How many of the most recent messages are kept in memory.
+pub const RECENT_MESSAGES: usize = 64;
+
+/// The color of the log overlay text.
+const OVERLAY_COLOR: [u8; 3] = [255, 255, 0];
+
+/// The levels are ordered from the most to the least important.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    /// The inverse of `name()`.
+    pub fn from_name(name: &str) -> Option<Level> {
+        [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .iter()
+        .copied()
+        .find(|level| level.name() == name)
+    }
+}
+
+/// The subsystem a message comes from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// Loading and saving files.
+    Data,
+    Audio,
+    Input,
+    /// The game simulation.
+    Sim,
+    Render,
+}
+
+impl Target {
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Data => "data",
+            Target::Audio => "audio",
+            Target::Input => "input",
+            Target::Sim => "sim",
+            Target::Render => "render",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    pub level: Level,
+    pub target: Target,
+    pub message: String,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{} {}] {}",
+            self.level.name(),
+            self.target.name(),
+            self.message
+        )
+    }
+}
+
+pub struct Logger {
+    /// Messages less important than this are dropped.
+    level: Level,
+    stderr: bool,
+    file: Option<Box<dyn Write + Send>>,
+    recent: VecDeque<Record>,
+}
+
+impl Logger {
+    /// A logger writing to stderr only.
+    pub const fn new(level: Level) -> Logger {
+        Logger {
+            level,
+            stderr: true,
+            file: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// A logger that only keeps the messages in memory (and writes them to the file, if one is
+    /// set).
+    pub const fn quiet(level: Level) -> Logger {
+        Logger {
+            level,
+            stderr: false,
+            file: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: Level) {
+        self.level = level;
+    }
+
+    /// Write the messages to `file` as well.
+    pub fn set_file(&mut self, file: Box<dyn Write + Send>) {
+        self.file = Some(file);
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    pub fn log(&mut self, record: Record) {
+        if !self.enabled(record.level) {
+            return;
+        }
+        if self.stderr {
+            eprintln!("{record}");
+        }
+        if let Some(file) = &mut self.file {
+            // There's nowhere to report the failure to, other than stderr.
+            if let Err(e) = writeln!(file, "{record}").and_then(|_| file.flush()) {
+                eprintln!("Cannot write to the log file: {e}");
+                self.file = None;
+            }
+        }
+        if self.recent.len() == RECENT_MESSAGES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(record);
+    }
+
+    /// The most recent messages, the oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &Record> {
+        self.recent.iter()
+    }
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger::new(Level::Info));
+
+fn with_logger<T, F: FnOnce(&mut Logger) -> T>(f: F) -> T {
+    // A panic while logging doesn't leave the logger in an unusable state.
+    let mut logger = LOGGER.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut logger)
+}
+
+/// Set up the global logger: the level of messages to log and, optionally, a file to write them
+/// to. The defaults are `Level::Info` and no file.
+pub fn init(level: Level, file: Option<Box<dyn Write + Send>>) {
+    with_logger(|logger| {
+        logger.set_level(level);
+        if let Some(file) = file {
+            logger.set_file(file);
+        }
+    });
+}
+
+/// Would a message of `level` be logged?
+pub fn enabled(level: Level) -> bool {
+    with_logger(|logger| logger.enabled(level))
+}
+
+/// Log a message with the global logger. The macros are more convenient to use.
+pub fn log(level: Level, target: Target, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let record = Record {
+        level,
+        target,
+        message: args.to_string(),
+    };
+    with_logger(|logger| logger.log(record));
+}
+
+/// The most recent `count` messages logged with the global logger, the oldest first.
+pub fn recent(count: usize) -> Vec<Record> {
+    with_logger(|logger| {
+        let skip = logger.recent.len().saturating_sub(count);
+        logger.recent().skip(skip).cloned().collect()
+    })
+}
+
+/// Draw `records` over the bottom of `frame`, an RGB frame (see `image13h::indices_to_rgb()`).
+/// The text is drawn in a single color on a darkened background, so it's readable no matter the
+/// palette in use.
+pub fn draw_overlay(fontdat: &Fontdat, records: &[Record], frame: &mut [u8]) {
+    if records.is_empty() {
+        return;
+    }
+    let height = records.len() * fontdat::CHARACTER_HEIGHT;
+    let top = image13h::SCREEN_HEIGHT.saturating_sub(height);
+    let mut text = Image13h::empty_screen_sized();
+    for (i, record) in records.iter().enumerate() {
+        let y = top + i * fontdat::CHARACTER_HEIGHT;
+        fontdat.draw_text(&mut text, &record.to_string(), 2, y as isize);
+    }
+    let area = Rect::from_ranges(0..image13h::SCREEN_WIDTH, top..image13h::SCREEN_HEIGHT);
+    image13h::shade_rgb(frame, image13h::SCREEN_WIDTH, &area);
+    image13h::overlay_rgb(&text, frame, OVERLAY_COLOR);
+}
+
+#[macro_export]
+macro_rules! error {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Error, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Warn, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Info, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Debug, $target, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Trace, $target, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log::{Level, Logger, Record, Target, RECENT_MESSAGES};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// A log file we can look into after handing it over to the logger.
+    #[derive(Clone, Default)]
+    struct SharedFile(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedFile {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(level: Level, message: &str) -> Record {
+        Record {
+            level,
+            target: Target::Data,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_levels_work() {
+        let mut logger = Logger::quiet(Level::Warn);
+        assert!(logger.enabled(Level::Error));
+        assert!(!logger.enabled(Level::Info));
+        logger.log(record(Level::Info, "dropped"));
+        logger.log(record(Level::Error, "kept"));
+        let messages: Vec<_> = logger.recent().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, vec!["kept"]);
+        assert_eq!(Level::from_name("debug"), Some(Level::Debug));
+        assert_eq!(Level::from_name("loud"), None);
+    }
+
+    #[test]
+    fn test_file_output_works() {
+        let file = SharedFile::default();
+        let mut logger = Logger::quiet(Level::Info);
+        logger.set_file(Box::new(file.clone()));
+        logger.log(Record {
+            level: Level::Warn,
+            target: Target::Audio,
+            message: "Music file not found".to_string(),
+        });
+        assert_eq!(
+            String::from_utf8(file.0.lock().unwrap().clone()).unwrap(),
+            "[warn audio] Music file not found\n"
+        );
+    }
+
+    #[test]
+    fn test_only_recent_messages_are_kept() {
+        let mut logger = Logger::quiet(Level::Trace);
+        for i in 0..RECENT_MESSAGES + 5 {
+            logger.log(record(Level::Info, &i.to_string()));
+        }
+        assert_eq!(logger.recent().count(), RECENT_MESSAGES);
+        assert_eq!(logger.recent().next().unwrap().message, "5");
+    }
+}
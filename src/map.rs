@@ -0,0 +1,421 @@
+//! The tile map: a grid of terrain cells.
+//!
+//! Every cell shows one of the terrain tiles from graf.dat `pictures` (see `Tile`) and carries
+//! the gameplay properties of the spot: whether units can walk through it, whether buildings can
+//! be placed on it and what resource can be gathered there. The properties start out as the
+//! defaults of the terrain (see `Cell::new()`), but can be changed per cell.
+//!
+//! # Map file format
+//!
+//! All integers are little endian.
+//!
+//! * The header: the `OPMP` magic, a version byte (currently 1), 2-byte width and 2-byte height
+//!   (in cells).
+//! * One record per cell, row by row, starting at the top left corner:
+//!   * a terrain byte (see `Terrain::to_byte()`)
+//!   * a variant byte
+//!   * a flags byte (`PASSABLE`, `BUILDABLE`, `RESOURCE`)
+//!   * (`RESOURCE`) a resource kind byte and 2-byte amount
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+/// The width of a map tile (a cell) in pixels.
+pub const TILE_WIDTH: usize = 16;
+
+/// The height of a map tile (a cell) in pixels.
+pub const TILE_HEIGHT: usize = 14;
+
+pub const MAGIC: &[u8; 4] = b"OPMP";
+pub const VERSION: u8 = 1;
+
+const PASSABLE: u8 = 1;
+const BUILDABLE: u8 = 1 << 1;
+const RESOURCE: u8 = 1 << 2;
+
+/// How much wood a tree cell holds initially. A guess, the value the original game uses isn't
+/// known yet.
+pub const TREE_WOOD: u16 = 100;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Terrain {
+    Grass,
+    Rocks,
+    DryEarth,
+    Road,
+    Bridge,
+    Water,
+    Trees,
+    Palisade,
+}
+
+pub const TERRAINS: [Terrain; 8] = [
+    Terrain::Grass,
+    Terrain::Rocks,
+    Terrain::DryEarth,
+    Terrain::Road,
+    Terrain::Bridge,
+    Terrain::Water,
+    Terrain::Trees,
+    Terrain::Palisade,
+];
+
+impl Terrain {
+    /// The graf.dat `pictures` of the terrain.
+    pub fn pictures(self) -> Range<usize> {
+        match self {
+            Terrain::Grass => 0..9,
+            Terrain::Rocks => 9..22,
+            Terrain::DryEarth => 22..25,
+            Terrain::Road => 25..46,
+            Terrain::Bridge => 46..54,
+            Terrain::Water => 74..113,
+            Terrain::Trees => 113..127,
+            Terrain::Palisade => 266..278,
+        }
+    }
+
+    /// The number of different tiles of the terrain. Tree pictures are two tiles wide, every
+    /// half is a variant of its own.
+    pub fn variants(self) -> usize {
+        match self {
+            Terrain::Trees => self.pictures().len() * 2,
+            _ => self.pictures().len(),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        TERRAINS.iter().position(|t| *t == self).unwrap() as u8
+    }
+
+    /// The inverse of `to_byte()`.
+    pub fn from_byte(byte: u8) -> Option<Terrain> {
+        TERRAINS.get(byte as usize).copied()
+    }
+}
+
+/// A terrain tile: one of the graf.dat pictures of a terrain, or a piece of it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Tile {
+    pub terrain: Terrain,
+    /// Which of the terrain's tiles (less than `Terrain::variants()`).
+    pub variant: u8,
+}
+
+impl Tile {
+    /// # Panics
+    /// Panics if the terrain doesn't have the variant.
+    pub fn new(terrain: Terrain, variant: u8) -> Tile {
+        assert!(
+            (variant as usize) < terrain.variants(),
+            "{:?} has no variant {}",
+            terrain,
+            variant
+        );
+        Tile { terrain, variant }
+    }
+
+    /// The index of the graf.dat picture the tile is drawn from.
+    pub fn picture(self) -> usize {
+        let start = self.terrain.pictures().start;
+        match self.terrain {
+            Terrain::Trees => start + self.variant as usize / 2,
+            _ => start + self.variant as usize,
+        }
+    }
+
+    /// Where in the picture the tile (`TILE_WIDTH`x`TILE_HEIGHT`) is. Only the tree pictures
+    /// hold more than one tile, the dry earth pictures are larger than a tile and only their top
+    /// left corner is used.
+    pub fn offset(self) -> (usize, usize) {
+        match self.terrain {
+            Terrain::Trees => ((self.variant as usize % 2) * TILE_WIDTH, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ResourceKind {
+    Wood,
+}
+
+impl ResourceKind {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ResourceKind::Wood => 0,
+        }
+    }
+
+    /// The inverse of `to_byte()`.
+    pub fn from_byte(byte: u8) -> Option<ResourceKind> {
+        match byte {
+            0 => Some(ResourceKind::Wood),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Resource {
+    pub kind: ResourceKind,
+    pub amount: u16,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Cell {
+    pub tile: Tile,
+    /// Can units walk through the cell?
+    pub passable: bool,
+    /// Can buildings be placed on the cell?
+    pub buildable: bool,
+    pub resource: Option<Resource>,
+}
+
+impl Cell {
+    /// A cell with the default properties of the tile's terrain: grass and dry earth can be
+    /// walked on and built on, roads and bridges can only be walked on and trees hold
+    /// `TREE_WOOD` wood.
+    pub fn new(tile: Tile) -> Cell {
+        let (passable, buildable) = match tile.terrain {
+            Terrain::Grass | Terrain::DryEarth => (true, true),
+            Terrain::Road | Terrain::Bridge => (true, false),
+            Terrain::Rocks | Terrain::Water | Terrain::Trees | Terrain::Palisade => (false, false),
+        };
+        let resource = match tile.terrain {
+            Terrain::Trees => Some(Resource {
+                kind: ResourceKind::Wood,
+                amount: TREE_WOOD,
+            }),
+            _ => None,
+        };
+        Cell {
+            tile,
+            passable,
+            buildable,
+            resource,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Map {
+    width: usize,
+    height: usize,
+    /// Row by row.
+    cells: Vec<Cell>,
+}
+
+impl Map {
+    /// A `width`x`height` map with every cell showing `tile`.
+    pub fn new(width: usize, height: usize, tile: Tile) -> Map {
+        Map {
+            width,
+            height,
+            cells: vec![Cell::new(tile); width * height],
+        }
+    }
+
+    /// The width in cells.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height in cells.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// The cell at column `x` and row `y`, `None` outside the map.
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        if self.contains(x, y) {
+            Some(&self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
+        if self.contains(x, y) {
+            Some(&mut self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Replace the cell at `x`, `y` with a cell showing `tile`, with the default properties of
+    /// its terrain. Positions outside the map are ignored.
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: Tile) {
+        if let Some(cell) = self.get_mut(x, y) {
+            *cell = Cell::new(tile);
+        }
+    }
+
+    /// Load a map from a reader.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read or the data isn't a valid map.
+    pub fn load<T: Read>(mut reader: T) -> Result<Map, String> {
+        let mut header = [0; 9];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| format!("Cannot read the map header: {e}"))?;
+        if &header[0..4] != MAGIC {
+            return Err("Not an openpol map".to_string());
+        }
+        if header[4] != VERSION {
+            return Err(format!("Unsupported map version {}", header[4]));
+        }
+        let width = u16::from_le_bytes([header[5], header[6]]) as usize;
+        let height = u16::from_le_bytes([header[7], header[8]]) as usize;
+        // The dimensions can't be trusted before the cells are actually there, no preallocating.
+        let mut cells = Vec::new();
+        for i in 0..width * height {
+            let cell = read_cell(&mut reader)
+                .map_err(|e| format!("Cannot read cell {} {}: {e}", i % width, i / width))?;
+            cells.push(cell);
+        }
+        Ok(Map {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    /// Save the map to a writer.
+    ///
+    /// # Panics
+    /// Panics if the map is larger than 65535 cells in either dimension.
+    pub fn save<T: Write>(&self, mut writer: T) -> io::Result<()> {
+        let width = u16::try_from(self.width).expect("The map is too wide");
+        let height = u16::try_from(self.height).expect("The map is too high");
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        for cell in &self.cells {
+            let mut flags = 0;
+            if cell.passable {
+                flags |= PASSABLE;
+            }
+            if cell.buildable {
+                flags |= BUILDABLE;
+            }
+            if cell.resource.is_some() {
+                flags |= RESOURCE;
+            }
+            writer.write_all(&[cell.tile.terrain.to_byte(), cell.tile.variant, flags])?;
+            if let Some(resource) = cell.resource {
+                writer.write_all(&[resource.kind.to_byte()])?;
+                writer.write_all(&resource.amount.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_cell<T: Read>(reader: &mut T) -> io::Result<Cell> {
+    let mut buffer = [0; 3];
+    reader.read_exact(&mut buffer)?;
+    let [terrain, variant, flags] = buffer;
+    let terrain = Terrain::from_byte(terrain).ok_or_else(|| invalid_data("invalid terrain"))?;
+    if variant as usize >= terrain.variants() {
+        return Err(invalid_data("invalid terrain variant"));
+    }
+    let resource = if flags & RESOURCE != 0 {
+        let mut buffer = [0; 3];
+        reader.read_exact(&mut buffer)?;
+        Some(Resource {
+            kind: ResourceKind::from_byte(buffer[0])
+                .ok_or_else(|| invalid_data("invalid resource kind"))?,
+            amount: u16::from_le_bytes([buffer[1], buffer[2]]),
+        })
+    } else {
+        None
+    };
+    Ok(Cell {
+        tile: Tile { terrain, variant },
+        passable: flags & PASSABLE != 0,
+        buildable: flags & BUILDABLE != 0,
+        resource,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{Cell, Map, Resource, ResourceKind, Terrain, Tile, TERRAINS, TREE_WOOD};
+
+    #[test]
+    fn test_tiles_map_to_pictures() {
+        assert_eq!(Tile::new(Terrain::Grass, 3).picture(), 3);
+        assert_eq!(Tile::new(Terrain::Water, 38).picture(), 112);
+        let tree = Tile::new(Terrain::Trees, 5);
+        assert_eq!(tree.picture(), 115);
+        assert_eq!(tree.offset(), (16, 0));
+        assert_eq!(Terrain::Trees.variants(), 28);
+        for terrain in TERRAINS.iter().copied() {
+            assert_eq!(Terrain::from_byte(terrain.to_byte()), Some(terrain));
+        }
+    }
+
+    #[test]
+    fn test_cells_have_terrain_defaults() {
+        let grass = Cell::new(Tile::new(Terrain::Grass, 0));
+        assert!(grass.passable && grass.buildable);
+        let road = Cell::new(Tile::new(Terrain::Road, 0));
+        assert!(road.passable && !road.buildable);
+        let water = Cell::new(Tile::new(Terrain::Water, 0));
+        assert!(!water.passable && !water.buildable);
+        let trees = Cell::new(Tile::new(Terrain::Trees, 0));
+        assert_eq!(
+            trees.resource,
+            Some(Resource {
+                kind: ResourceKind::Wood,
+                amount: TREE_WOOD
+            })
+        );
+    }
+
+    #[test]
+    fn test_saving_and_loading_works() {
+        let mut map = Map::new(3, 2, Tile::new(Terrain::Grass, 1));
+        map.set_tile(2, 1, Tile::new(Terrain::Trees, 27));
+        map.set_tile(0, 1, Tile::new(Terrain::Water, 4));
+        map.get_mut(1, 0).unwrap().buildable = false;
+        map.get_mut(2, 1).unwrap().resource.as_mut().unwrap().amount = 7;
+        assert_eq!(map.get(3, 0), None);
+
+        let mut data = Vec::new();
+        map.save(&mut data).unwrap();
+        assert_eq!(Map::load(&data[..]), Ok(map));
+        assert_eq!(
+            Map::load(&data[..data.len() - 1]),
+            Err("Cannot read cell 2 1: failed to fill whole buffer".to_string())
+        );
+        data[0] = b'X';
+        assert_eq!(Map::load(&data[..]), Err("Not an openpol map".to_string()));
+    }
+
+    #[test]
+    fn test_bad_headers_are_rejected() {
+        let mut data = b"OPMP\x01\xff\xff\xff\xff".to_vec();
+        assert_eq!(
+            Map::load(&data[..]),
+            Err("Cannot read cell 0 0: failed to fill whole buffer".to_string())
+        );
+        data.pop();
+        assert_eq!(
+            Map::load(&data[..]),
+            Err("Cannot read the map header: failed to fill whole buffer".to_string())
+        );
+    }
+}
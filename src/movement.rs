@@ -0,0 +1,260 @@
+//! Movement: units walking where they've been ordered to.
+//!
+//! `Movement::update()` runs once per simulation tick, after the tick's commands have been
+//! applied. Units ordered to move walk along a `pathfinding::Route` to their target cell and go
+//! idle once they're there (or can't get any closer). Units ordered to attack an enemy out of
+//! their range walk towards it, following it when it moves, until it's in range.
+//!
+//! A unit covers `Stats::speed` thousandths of a cell in a tick and enters the next cell of its
+//! route once it has covered the whole step, diagonal steps being `DIAGONAL_COST` /
+//! `STRAIGHT_COST` times longer. The cell a unit is stepping into counts as taken, so no two units
+//! end up in the same cell. Gathering and building aren't carried out yet, units with those
+//! orders stay where they are.
+
+use crate::combat;
+use crate::map::Map;
+use crate::pathfinding::{self, Pathfinder, Position, Route, Step, DIAGONAL_COST, STRAIGHT_COST};
+use crate::units::{Order, UnitId, UnitTable, Units};
+use std::collections::{BTreeMap, HashSet};
+
+/// The length of a straight step, in the units of `Stats::speed`.
+const STEP_LENGTH: u32 = 1000;
+
+/// The state of a walking unit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Walker {
+    route: Route,
+    /// The cell the unit is stepping into.
+    next: Option<Position>,
+    /// How much of the step the unit has covered (or carries over to the next step).
+    covered: u32,
+}
+
+/// The walking units of a game.
+#[derive(Clone, Debug, Default)]
+pub struct Movement {
+    pathfinder: Pathfinder,
+    walkers: BTreeMap<UnitId, Walker>,
+}
+
+impl Movement {
+    pub fn new() -> Movement {
+        Movement::default()
+    }
+
+    /// The cell a unit is stepping into, if any.
+    pub fn next_cell(&self, id: UnitId) -> Option<Position> {
+        self.walkers.get(&id).and_then(|walker| walker.next)
+    }
+
+    /// Forget the cached paths, see `Pathfinder::invalidate()`.
+    pub fn invalidate(&mut self) {
+        self.pathfinder.invalidate();
+    }
+
+    /// Run a tick of movement.
+    pub fn update(&mut self, map: &Map, table: &UnitTable, units: &mut Units) {
+        self.pathfinder.start_tick();
+        let goals: Vec<(UnitId, Option<Position>)> = units
+            .iter()
+            .map(|unit| (unit.id, goal(units, table, unit.id)))
+            .collect();
+        self.walkers.retain(|id, _| {
+            goals
+                .iter()
+                .any(|(unit, goal)| unit == id && goal.is_some())
+        });
+
+        for (id, goal) in goals {
+            let goal = match goal {
+                Some(goal) => goal,
+                None => continue,
+            };
+            let walker = self.walkers.entry(id).or_insert_with(|| Walker {
+                route: Route::new(goal),
+                next: None,
+                covered: 0,
+            });
+            if walker.route.goal != goal {
+                // The target moved.
+                walker.route = Route::new(goal);
+            }
+            let position = units.get(id).unwrap().position;
+            if walker.next.is_none() {
+                let mut occupied = pathfinding::occupied_cells(units, id);
+                occupied.extend(self.walkers_next_cells(id));
+                let walker = self.walkers.get_mut(&id).unwrap();
+                let step = if occupied.contains(&goal) && combat::cells_between(position, goal) == 1
+                {
+                    // Another unit is in the goal, this is as close as it gets.
+                    Step::Arrived
+                } else {
+                    walker
+                        .route
+                        .next(&mut self.pathfinder, map, position, &occupied)
+                };
+                match step {
+                    Step::Move(next) => walker.next = Some(next),
+                    Step::Wait => walker.covered = 0,
+                    Step::Arrived | Step::Stuck => {
+                        self.walkers.remove(&id);
+                        let unit = units.get_mut(id).unwrap();
+                        if let Order::Move { .. } = unit.order {
+                            unit.order = Order::Idle;
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let walker = self.walkers.get_mut(&id).unwrap();
+            let next = match walker.next {
+                Some(next) => next,
+                None => continue,
+            };
+            let unit = units.get_mut(id).unwrap();
+            walker.covered += table.stats(unit.kind).speed;
+            let length = step_length(position, next);
+            if walker.covered >= length {
+                walker.covered -= length;
+                walker.next = None;
+                unit.position = next;
+            }
+        }
+    }
+
+    /// The cells the walkers other than `except` are stepping into.
+    fn walkers_next_cells(&self, except: UnitId) -> HashSet<Position> {
+        self.walkers
+            .iter()
+            .filter(|(id, _)| **id != except)
+            .filter_map(|(_, walker)| walker.next)
+            .collect()
+    }
+}
+
+/// Where a unit has to walk to, `None` if it doesn't need to walk anywhere.
+fn goal(units: &Units, table: &UnitTable, id: UnitId) -> Option<Position> {
+    let unit = units.get(id)?;
+    match unit.order {
+        Order::Move { target } => Some(target),
+        Order::Attack { target } => {
+            let target = units.get(target)?.position;
+            let range = table.stats(unit.kind).range;
+            if combat::cells_between(unit.position, target) > range {
+                Some(target)
+            } else {
+                None
+            }
+        }
+        Order::Idle | Order::Gather { .. } | Order::Build { .. } => None,
+    }
+}
+
+/// How long a step between neighboring cells is, in the units of `Stats::speed`.
+fn step_length(from: Position, to: Position) -> u32 {
+    if from.0 != to.0 && from.1 != to.1 {
+        STEP_LENGTH * DIAGONAL_COST / STRAIGHT_COST
+    } else {
+        STEP_LENGTH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{Map, Terrain, Tile};
+    use crate::movement::Movement;
+    use crate::units::{Order, Player, UnitKind, UnitTable, Units};
+
+    /// Peasants cover half a cell per tick, warriors and archers a quarter.
+    fn table() -> UnitTable {
+        UnitTable::load(
+            "peasant 5 500 1 1 1 1\nwarrior 10 250 3 1 1 1\narcher 5 250 1 3 1 1\n\
+             horseman 5 1000 1 1 1 1"
+                .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_units_walk_to_their_targets() {
+        let mut map = Map::new(6, 3, Tile::new(Terrain::Grass, 0));
+        let table = table();
+        let mut units = Units::new();
+        let peasant = units.spawn(&table, UnitKind::Peasant, Player(0), (0, 0));
+        units.get_mut(peasant).unwrap().order = Order::Move { target: (2, 0) };
+        let mut movement = Movement::new();
+
+        // Half a cell per tick, every step takes two ticks.
+        movement.update(&map, &table, &mut units);
+        assert_eq!(units.get(peasant).unwrap().position, (0, 0));
+        assert_eq!(movement.next_cell(peasant), Some((1, 0)));
+        for _ in 0..3 {
+            movement.update(&map, &table, &mut units);
+        }
+        assert_eq!(units.get(peasant).unwrap().position, (2, 0));
+        assert_eq!(
+            units.get(peasant).unwrap().order,
+            Order::Move { target: (2, 0) }
+        );
+        movement.update(&map, &table, &mut units);
+        assert_eq!(units.get(peasant).unwrap().order, Order::Idle);
+        assert_eq!(movement.next_cell(peasant), None);
+
+        // Around rocks.
+        map.set_tile(3, 0, Tile::new(Terrain::Rocks, 0));
+        units.get_mut(peasant).unwrap().order = Order::Move { target: (5, 0) };
+        let mut positions = Vec::new();
+        for _ in 0..20 {
+            movement.update(&map, &table, &mut units);
+            positions.push(units.get(peasant).unwrap().position);
+        }
+        assert!(positions.contains(&(3, 1)));
+        assert!(!positions.contains(&(3, 0)));
+        assert_eq!(units.get(peasant).unwrap().position, (5, 0));
+        assert_eq!(units.get(peasant).unwrap().order, Order::Idle);
+    }
+
+    #[test]
+    fn test_units_dont_share_cells() {
+        let map = Map::new(5, 1, Tile::new(Terrain::Grass, 0));
+        let table = table();
+        let mut units = Units::new();
+        let first = units.spawn(&table, UnitKind::Horseman, Player(0), (0, 0));
+        let second = units.spawn(&table, UnitKind::Horseman, Player(0), (2, 0));
+        units.get_mut(first).unwrap().order = Order::Move { target: (1, 0) };
+        units.get_mut(second).unwrap().order = Order::Move { target: (1, 0) };
+        let mut movement = Movement::new();
+        for _ in 0..10 {
+            movement.update(&map, &table, &mut units);
+        }
+        assert_eq!(units.get(first).unwrap().position, (1, 0));
+        // The second unit got as close as it could.
+        assert_eq!(units.get(second).unwrap().position, (2, 0));
+        assert_eq!(units.get(second).unwrap().order, Order::Idle);
+    }
+
+    #[test]
+    fn test_attackers_approach_their_targets() {
+        let map = Map::new(8, 1, Tile::new(Terrain::Grass, 0));
+        let table = table();
+        let mut units = Units::new();
+        let warrior = units.spawn(&table, UnitKind::Warrior, Player(0), (0, 0));
+        let archer = units.spawn(&table, UnitKind::Archer, Player(0), (0, 0));
+        let enemy = units.spawn(&table, UnitKind::Peasant, Player(1), (7, 0));
+        units.get_mut(warrior).unwrap().order = Order::Attack { target: enemy };
+        units.get_mut(archer).unwrap().position = (1, 0);
+        units.get_mut(archer).unwrap().order = Order::Attack { target: enemy };
+        let mut movement = Movement::new();
+        for _ in 0..40 {
+            movement.update(&map, &table, &mut units);
+        }
+        // Each stops once the enemy is in its range, still ordered to attack.
+        assert_eq!(units.get(archer).unwrap().position, (4, 0));
+        assert_eq!(units.get(warrior).unwrap().position, (3, 0));
+        assert_eq!(
+            units.get(warrior).unwrap().order,
+            Order::Attack { target: enemy }
+        );
+    }
+}
@@ -0,0 +1,581 @@
+//! Pathfinding on the tile map.
+//!
+//! Paths are found with A* over the map cells, units move to any of the 8 neighboring cells.
+//! Only passable cells (see `map::Cell::passable`) can be walked through and diagonal moves
+//! can't cut corners: both cells next to the diagonal have to be passable as well.
+//!
+//! Searching is the expensive part of the simulation, so it's capped: a `Pathfinder` expands at
+//! most `NODES_PER_TICK` nodes per tick, shared by all searches, and a single search at most
+//! `NODES_PER_SEARCH`. A search that runs out of its nodes (or can't reach the goal at all)
+//! results in a partial path to the closest cell it found, units follow it and search again once
+//! there. A search cut short by the tick's budget running out is thrown away and repeated in a
+//! later tick instead, as its result depends on how much of the budget other searches used.
+//! Paths only depending on the map are cached until the map changes (see
+//! `Pathfinder::invalidate()`).
+//!
+//! Units follow their paths with `Route`, which waits when the next cell is taken by another
+//! unit and plans a way around it if the unit doesn't move out of the way soon enough.
+
+use crate::map::Map;
+use crate::units::{UnitId, Units};
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// A cell position: column and row.
+pub type Position = (usize, usize);
+
+/// The cost of moving to a horizontally or vertically neighboring cell.
+pub const STRAIGHT_COST: u32 = 10;
+
+/// The cost of moving to a diagonally neighboring cell (roughly `STRAIGHT_COST` * √2).
+pub const DIAGONAL_COST: u32 = 14;
+
+/// How many nodes all searches of a tick can expand together.
+pub const NODES_PER_TICK: usize = 4000;
+
+/// How many nodes a single search can expand.
+pub const NODES_PER_SEARCH: usize = 1000;
+
+/// How many ticks a unit waits for another unit to move out of its way before it looks for a
+/// way around it.
+pub const WAIT_TICKS: u32 = 4;
+
+/// The neighbor offsets, the straight ones first.
+const NEIGHBORS: [(isize, isize); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Path {
+    /// The cells to go through, without the starting cell.
+    pub cells: Vec<Position>,
+    /// Does the path lead to the goal? Partial paths end at the cell closest to the goal the
+    /// search found.
+    pub complete: bool,
+}
+
+/// The estimated cost of getting from `a` to `b` (the exact cost if there are no obstacles).
+pub fn distance(a: Position, b: Position) -> u32 {
+    let dx = (a.0 as isize - b.0 as isize).unsigned_abs() as u32;
+    let dy = (a.1 as isize - b.1 as isize).unsigned_abs() as u32;
+    let (short, long) = (cmp::min(dx, dy), cmp::max(dx, dy));
+    short * DIAGONAL_COST + (long - short) * STRAIGHT_COST
+}
+
+/// Find a path from `start` to `goal` avoiding impassable cells and the `blocked` ones,
+/// expanding at most `max_nodes` nodes. Returns the path and the number of nodes expanded.
+pub fn find_path(
+    map: &Map,
+    start: Position,
+    goal: Position,
+    blocked: &HashSet<Position>,
+    max_nodes: usize,
+) -> (Path, usize) {
+    let index = |(x, y): Position| y * map.width() + x;
+    let walkable = |position: Position| {
+        map.get(position.0, position.1)
+            .is_some_and(|cell| cell.passable)
+            && !blocked.contains(&position)
+    };
+    let mut costs = vec![u32::MAX; map.width() * map.height()];
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+    // Ordered by the estimated total cost, then by the estimated remaining cost and then by the
+    // position, so that the results don't depend on anything but the input.
+    let mut open = BinaryHeap::new();
+    let mut closest = (distance(start, goal), start);
+    let mut expanded = 0;
+    if map.contains(start.0, start.1) {
+        costs[index(start)] = 0;
+        open.push(Reverse((
+            distance(start, goal),
+            distance(start, goal),
+            start,
+        )));
+    }
+
+    while let Some(Reverse((_, remaining, position))) = open.pop() {
+        if position == goal {
+            closest = (0, goal);
+            break;
+        }
+        if expanded == max_nodes {
+            break;
+        }
+        expanded += 1;
+        if remaining < closest.0 {
+            closest = (remaining, position);
+        }
+        let cost = costs[index(position)];
+        for (dx, dy) in NEIGHBORS.iter().copied() {
+            let (x, y) = (position.0 as isize + dx, position.1 as isize + dy);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let next = (x as usize, y as usize);
+            if !walkable(next) {
+                continue;
+            }
+            let diagonal = dx != 0 && dy != 0;
+            if diagonal
+                && !(walkable(((position.0 as isize + dx) as usize, position.1))
+                    && walkable((position.0, (position.1 as isize + dy) as usize)))
+            {
+                continue;
+            }
+            let next_cost = cost
+                + if diagonal {
+                    DIAGONAL_COST
+                } else {
+                    STRAIGHT_COST
+                };
+            if next_cost < costs[index(next)] {
+                costs[index(next)] = next_cost;
+                came_from.insert(next, position);
+                let remaining = distance(next, goal);
+                open.push(Reverse((next_cost + remaining, remaining, next)));
+            }
+        }
+    }
+
+    let end = closest.1;
+    let mut cells = Vec::new();
+    let mut position = end;
+    while position != start {
+        cells.push(position);
+        position = came_from[&position];
+    }
+    cells.reverse();
+    (
+        Path {
+            cells,
+            complete: end == goal,
+        },
+        expanded,
+    )
+}
+
+/// Runs searches within the per-tick budget and caches their results.
+#[derive(Clone, Debug)]
+pub struct Pathfinder {
+    cache: HashMap<(Position, Position), Path>,
+    /// The nodes searches can still expand in the current tick.
+    budget: usize,
+}
+
+impl Default for Pathfinder {
+    fn default() -> Pathfinder {
+        Pathfinder::new()
+    }
+}
+
+impl Pathfinder {
+    pub fn new() -> Pathfinder {
+        Pathfinder {
+            cache: HashMap::new(),
+            budget: NODES_PER_TICK,
+        }
+    }
+
+    /// Start a new tick, restoring the budget.
+    pub fn start_tick(&mut self) {
+        self.budget = NODES_PER_TICK;
+    }
+
+    /// Forget the cached paths. Needs to be called whenever the passability of the map changes
+    /// (a building is placed, for example).
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Find a path (see `find_path()`). Returns `None` if the tick's budget is used up (before
+    /// or during the search), the search needs to be repeated in one of the next ticks. Paths
+    /// without `blocked` cells are cached.
+    pub fn request(
+        &mut self,
+        map: &Map,
+        start: Position,
+        goal: Position,
+        blocked: &HashSet<Position>,
+    ) -> Option<Path> {
+        let cacheable = blocked.is_empty();
+        if cacheable {
+            if let Some(path) = self.cache.get(&(start, goal)) {
+                return Some(path.clone());
+            }
+        }
+        if self.budget == 0 {
+            return None;
+        }
+        let max_nodes = cmp::min(self.budget, NODES_PER_SEARCH);
+        let (path, expanded) = find_path(map, start, goal, blocked, max_nodes);
+        self.budget -= expanded;
+        if !path.complete && expanded == max_nodes && max_nodes < NODES_PER_SEARCH {
+            // Cut short by the budget, not by the search's own limit.
+            return None;
+        }
+        if cacheable {
+            self.cache.insert((start, goal), path.clone());
+        }
+        Some(path)
+    }
+}
+
+/// What a unit following a `Route` should do in a tick.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Move to the neighboring cell.
+    Move(Position),
+    /// Stay put, the way is blocked for now or there's no path yet.
+    Wait,
+    /// The unit is at the goal.
+    Arrived,
+    /// The goal can't be reached, no cell closer to it can be reached either.
+    Stuck,
+}
+
+/// A unit's way to a goal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Route {
+    pub goal: Position,
+    cells: VecDeque<Position>,
+    /// Is `cells` a complete path to the goal?
+    complete: bool,
+    /// For how many ticks has the unit been waiting for the next cell to be free.
+    waited: u32,
+}
+
+impl Route {
+    pub fn new(goal: Position) -> Route {
+        Route {
+            goal,
+            cells: VecDeque::new(),
+            complete: false,
+            waited: 0,
+        }
+    }
+
+    /// The next step of a unit at `position`. `occupied` are the cells taken by other units.
+    pub fn next(
+        &mut self,
+        pathfinder: &mut Pathfinder,
+        map: &Map,
+        position: Position,
+        occupied: &HashSet<Position>,
+    ) -> Step {
+        if position == self.goal {
+            return Step::Arrived;
+        }
+        if self.cells.is_empty() && !self.plan(pathfinder, map, position, &HashSet::new()) {
+            return Step::Wait;
+        }
+        let next = match self.cells.front() {
+            Some(next) => *next,
+            None => return Step::Stuck,
+        };
+        let passable = map.get(next.0, next.1).is_some_and(|cell| cell.passable);
+        if !passable {
+            // The map changed since the path was found, the cached paths may be outdated too.
+            pathfinder.invalidate();
+            self.cells.clear();
+            return Step::Wait;
+        }
+        if occupied.contains(&next) {
+            self.waited += 1;
+            if self.waited > WAIT_TICKS {
+                self.waited = 0;
+                self.plan(pathfinder, map, position, occupied);
+            }
+            return Step::Wait;
+        }
+        self.waited = 0;
+        self.cells.pop_front();
+        Step::Move(next)
+    }
+
+    /// Replace the path with a new one. Returns `false` if the search has to wait for the next
+    /// tick.
+    fn plan(
+        &mut self,
+        pathfinder: &mut Pathfinder,
+        map: &Map,
+        position: Position,
+        blocked: &HashSet<Position>,
+    ) -> bool {
+        match pathfinder.request(map, position, self.goal, blocked) {
+            Some(path) => {
+                self.cells = path.cells.into_iter().collect();
+                self.complete = path.complete;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Does the unit know a complete path to the goal?
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// The cells taken by units other than `except`.
+pub fn occupied_cells(units: &Units, except: UnitId) -> HashSet<Position> {
+    units
+        .iter()
+        .filter(|unit| unit.id != except)
+        .map(|unit| unit.position)
+        .collect()
+}
+
+/// The goals of a group of units at `positions` moving to `goal`, keeping their formation: every
+/// unit keeps its offset from the center of the group. Units whose spot is impassable (or
+/// outside of the map, or taken by another unit of the group) get the nearest free spot instead.
+pub fn formation(map: &Map, positions: &[Position], goal: Position) -> Vec<Position> {
+    if positions.is_empty() {
+        return Vec::new();
+    }
+    let count = positions.len();
+    let center_x = positions.iter().map(|p| p.0).sum::<usize>() / count;
+    let center_y = positions.iter().map(|p| p.1).sum::<usize>() / count;
+    let mut taken = HashSet::new();
+    let mut goals = Vec::new();
+    for position in positions {
+        let x = goal.0 as isize + position.0 as isize - center_x as isize;
+        let y = goal.1 as isize + position.1 as isize - center_y as isize;
+        let free = |(x, y): (isize, isize), taken: &HashSet<Position>| {
+            x >= 0
+                && y >= 0
+                && map
+                    .get(x as usize, y as usize)
+                    .is_some_and(|cell| cell.passable)
+                && !taken.contains(&(x as usize, y as usize))
+        };
+        let spot =
+            nearest(x, y, map.width() + map.height(), |spot| free(spot, &taken)).unwrap_or((x, y));
+        let spot = (
+            cmp::min(cmp::max(spot.0, 0) as usize, map.width().saturating_sub(1)),
+            cmp::min(cmp::max(spot.1, 0) as usize, map.height().saturating_sub(1)),
+        );
+        taken.insert(spot);
+        goals.push(spot);
+    }
+    goals
+}
+
+/// The spot closest to `x`, `y` (by rings of growing size, searched clockwise from the top left
+/// corner) satisfying `accept`, looking at most `radius` cells away.
+fn nearest<F: Fn((isize, isize)) -> bool>(
+    x: isize,
+    y: isize,
+    radius: usize,
+    accept: F,
+) -> Option<(isize, isize)> {
+    for ring in 0..=radius as isize {
+        let mut spots = Vec::new();
+        for i in -ring..=ring {
+            spots.push((x + i, y - ring));
+        }
+        for i in -ring + 1..=ring {
+            spots.push((x + ring, y + i));
+        }
+        for i in (-ring..ring).rev() {
+            spots.push((x + i, y + ring));
+        }
+        for i in (-ring + 1..ring).rev() {
+            spots.push((x - ring, y + i));
+        }
+        if let Some(spot) = spots.into_iter().find(|spot| accept(*spot)) {
+            return Some(spot);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{Map, Terrain, Tile};
+    use crate::pathfinding::{
+        find_path, formation, Pathfinder, Route, Step, NODES_PER_SEARCH, NODES_PER_TICK, WAIT_TICKS,
+    };
+    use std::collections::HashSet;
+
+    /// A map drawn with characters: `.` is grass, `#` is rocks.
+    fn drawn(rows: &[&str]) -> Map {
+        let mut map = Map::new(rows[0].len(), rows.len(), Tile::new(Terrain::Grass, 0));
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    map.set_tile(x, y, Tile::new(Terrain::Rocks, 0));
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn test_paths_go_around_obstacles() {
+        let map = drawn(&[
+            "....", //
+            ".##.", //
+            ".#..", //
+            "....", //
+        ]);
+        let (path, _) = find_path(&map, (0, 2), (2, 2), &HashSet::new(), 100);
+        assert!(path.complete);
+        // Diagonal moves don't cut the corners of the rocks.
+        assert_eq!(path.cells, vec![(0, 3), (1, 3), (2, 3), (2, 2)]);
+        let (path, _) = find_path(&map, (0, 0), (3, 3), &HashSet::new(), 100);
+        assert_eq!(path.cells.len(), 6);
+        assert_eq!(path.cells.last(), Some(&(3, 3)));
+    }
+
+    #[test]
+    fn test_unreachable_goals_give_partial_paths() {
+        let map = drawn(&[
+            "...#.", //
+            "...#.", //
+            "...#.", //
+        ]);
+        let (path, _) = find_path(&map, (0, 1), (4, 1), &HashSet::new(), 100);
+        assert!(!path.complete);
+        assert_eq!(path.cells.last(), Some(&(2, 1)));
+
+        // Running out of nodes results in a partial path as well.
+        let map = drawn(&["...................."]);
+        let (path, expanded) = find_path(&map, (0, 0), (19, 0), &HashSet::new(), 5);
+        assert_eq!(expanded, 5);
+        assert!(!path.complete);
+        assert_eq!(path.cells.last(), Some(&(4, 0)));
+    }
+
+    #[test]
+    fn test_budget_and_caching_work() {
+        let map = Map::new(200, 200, Tile::new(Terrain::Grass, 0));
+        let mut walls = map.clone();
+        for y in 0..199 {
+            walls.set_tile(100, y, Tile::new(Terrain::Rocks, 0));
+        }
+        let mut pathfinder = Pathfinder::new();
+        let none = HashSet::new();
+        let mut searches = 0;
+        // Searches that don't find the way quickly use up their share of the tick's budget.
+        while pathfinder
+            .request(&walls, (99, 0), (101, 0), &none)
+            .is_some()
+        {
+            pathfinder.invalidate();
+            searches += 1;
+        }
+        assert_eq!(searches, NODES_PER_TICK / NODES_PER_SEARCH);
+
+        // Cached paths don't need the budget.
+        pathfinder.start_tick();
+        let path = pathfinder.request(&map, (0, 0), (5, 5), &none).unwrap();
+        pathfinder.budget = 0;
+        assert_eq!(pathfinder.request(&map, (0, 0), (5, 5), &none), Some(path));
+        assert_eq!(pathfinder.request(&map, (0, 0), (5, 6), &none), None);
+    }
+
+    #[test]
+    fn test_searches_cut_short_by_the_budget_are_repeated() {
+        let map = drawn(&["........"]);
+        let none = HashSet::new();
+        let mut pathfinder = Pathfinder::new();
+        pathfinder.budget = 1;
+        let mut route = Route::new((7, 0));
+        assert_eq!(pathfinder.request(&map, (0, 0), (7, 0), &none), None);
+        assert_eq!(pathfinder.budget, 0);
+        assert_eq!(route.next(&mut pathfinder, &map, (0, 0), &none), Step::Wait);
+
+        // Nothing was cached, the next tick finds the whole path.
+        pathfinder.start_tick();
+        assert_eq!(
+            route.next(&mut pathfinder, &map, (0, 0), &none),
+            Step::Move((1, 0))
+        );
+        assert!(route.is_complete());
+    }
+
+    #[test]
+    fn test_routes_wait_and_replan() {
+        let map = drawn(&[
+            "...", //
+            "...", //
+            "...", //
+        ]);
+        let mut pathfinder = Pathfinder::new();
+        let mut route = Route::new((2, 1));
+        let occupied: HashSet<_> = vec![(1, 1)].into_iter().collect();
+        for _ in 0..WAIT_TICKS {
+            assert_eq!(
+                route.next(&mut pathfinder, &map, (0, 1), &occupied),
+                Step::Wait
+            );
+        }
+        // The unit in the way didn't move, go around it (without cutting its corners).
+        assert_eq!(
+            route.next(&mut pathfinder, &map, (0, 1), &occupied),
+            Step::Wait
+        );
+        let step = route.next(&mut pathfinder, &map, (0, 1), &occupied);
+        assert!(step == Step::Move((0, 0)) || step == Step::Move((0, 2)));
+        assert_eq!(
+            route.next(&mut pathfinder, &map, (2, 1), &occupied),
+            Step::Arrived
+        );
+
+        let walled = drawn(&[
+            ".#.", //
+            ".#.", //
+            ".#.", //
+        ]);
+        let mut route = Route::new((2, 0));
+        assert_eq!(
+            route.next(&mut pathfinder, &walled, (0, 0), &HashSet::new()),
+            Step::Stuck
+        );
+    }
+
+    #[test]
+    fn test_routes_notice_map_changes() {
+        let mut map = drawn(&["....", "...."]);
+        let mut pathfinder = Pathfinder::new();
+        let mut route = Route::new((3, 0));
+        let none = HashSet::new();
+        assert_eq!(
+            route.next(&mut pathfinder, &map, (0, 0), &none),
+            Step::Move((1, 0))
+        );
+        // A building went up in the way.
+        map.get_mut(2, 0).unwrap().passable = false;
+        assert_eq!(route.next(&mut pathfinder, &map, (1, 0), &none), Step::Wait);
+        assert_eq!(
+            route.next(&mut pathfinder, &map, (1, 0), &none),
+            Step::Move((1, 1))
+        );
+    }
+
+    #[test]
+    fn test_formations_are_kept() {
+        let map = drawn(&[
+            "......", //
+            "......", //
+            "....#.", //
+            "......", //
+        ]);
+        let goals = formation(&map, &[(0, 0), (1, 0), (0, 1)], (3, 2));
+        // The center is (0, 0), the spot of the second unit is taken by rocks.
+        assert_eq!(goals[0], (3, 2));
+        assert_ne!(goals[1], (4, 2));
+        assert_eq!(goals[2], (3, 3));
+        let unique: HashSet<_> = goals.iter().collect();
+        assert_eq!(unique.len(), 3);
+        assert!(goals.iter().all(|(x, y)| map.get(*x, *y).unwrap().passable));
+    }
+}
@@ -0,0 +1,219 @@
+//! Player colors: color translation tables that recolor sprites for their owners.
+//!
+//! The original game tells the players apart by drawing the same unit and building sprites with
+//! a range of palette colors (`PLAYER_COLORS`) replaced. Instead of keeping a copy of every
+//! sprite per player, the sprites are drawn with `Image13h::blit_remapped_within()` and one of the
+//! tables built here. Player 0 keeps the colors of the sprites, the other players' colors are the
+//! palette colors nearest to the original ones tinted with `PLAYER_TINTS`.
+//!
+//! The tables also cover highlights: a selected unit is drawn lighter and a damaged one flashes
+//! red (see `is_flashing()`). The highlight is applied on top of the player colors, to every
+//! color of the sprite.
+//!
+//! # Status
+//!
+//! Telling the players apart doesn't work yet. Which palette colors are the player colors isn't
+//! known: the range hasn't been checked against the original sprites (the `dead` frames of
+//! graf.dat, or the mover sprites once they're decoded). Until it is, `PLAYER_COLORS` is `None`,
+//! `new()` builds tables that don't recolor anything and only the highlights work.
+//! `with_player_colors()` builds the tables for a given range of colors, for trying ranges out.
+
+use crate::image13h::Remap;
+use crate::paldat::Paldat;
+use crate::units::Player;
+use std::ops;
+
+/// The number of players with their own colors.
+pub const PLAYERS: usize = 6;
+
+/// The palette colors replaced with the owner's colors, `None` while they're unknown.
+// TODO Find them in the mover sprites once they're decoded, the working hypothesis is 200..208.
+pub const PLAYER_COLORS: Option<ops::Range<usize>> = None;
+
+/// The tints of the players' colors, the first player keeps the original colors.
+const PLAYER_TINTS: [Option<[u32; 3]>; PLAYERS] = [
+    None,
+    Some([255, 48, 32]),
+    Some([48, 96, 255]),
+    Some([255, 224, 32]),
+    Some([176, 48, 224]),
+    Some([240, 240, 240]),
+];
+
+/// How much (in percent) the selected highlight moves the colors towards white.
+const SELECTED_LIGHTEN: u32 = 40;
+
+/// How much (in percent) the damaged flash moves the colors towards red.
+const DAMAGED_REDDEN: u32 = 60;
+
+/// For how many ticks after a hit a unit flashes.
+pub const FLASH_TICKS: u32 = 6;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Highlight {
+    None,
+    Selected,
+    Damaged,
+}
+
+const HIGHLIGHTS: [Highlight; 3] = [Highlight::None, Highlight::Selected, Highlight::Damaged];
+
+/// Should a unit hit `ticks_since_hit` ticks ago be drawn with the `Damaged` highlight? It
+/// flashes on and off every other tick for `FLASH_TICKS` ticks.
+pub fn is_flashing(ticks_since_hit: u32) -> bool {
+    ticks_since_hit < FLASH_TICKS && ticks_since_hit.is_multiple_of(2)
+}
+
+/// The translation tables of all players and highlights for a palette.
+pub struct PlayerColors {
+    /// Indexed with `player * HIGHLIGHTS.len() + highlight`.
+    tables: Vec<Remap>,
+}
+
+impl PlayerColors {
+    /// Build the tables for the `palette` of `paldat` (see `Paldat::palette_data()`), with
+    /// `PLAYER_COLORS` as the player colors.
+    pub fn new(paldat: &Paldat, palette: usize) -> PlayerColors {
+        PlayerColors::with_player_colors(paldat, palette, PLAYER_COLORS.unwrap_or(0..0))
+    }
+
+    /// Like `new()`, but with `player_colors` as the player colors.
+    pub fn with_player_colors(
+        paldat: &Paldat,
+        palette: usize,
+        player_colors: ops::Range<usize>,
+    ) -> PlayerColors {
+        let palette = paldat.palette_data(palette);
+        let color = |index: usize| {
+            let rgb = &palette[index * 3..index * 3 + 3];
+            [rgb[0] as u32, rgb[1] as u32, rgb[2] as u32]
+        };
+        let brightest = palette.iter().copied().max().unwrap_or(0) as u32;
+        let mut tables = Vec::with_capacity(PLAYERS * HIGHLIGHTS.len());
+        for tint in PLAYER_TINTS.iter() {
+            let mut player = [0; 256];
+            for (index, entry) in player.iter_mut().enumerate() {
+                *entry = match tint {
+                    Some(tint) if player_colors.contains(&index) => {
+                        let [r, g, b] = color(index);
+                        let brightness = r.max(g).max(b);
+                        nearest(palette, tint.map(|channel| channel * brightness / 255))
+                    }
+                    _ => index as u8,
+                };
+            }
+            for highlight in HIGHLIGHTS.iter() {
+                let (target, percent) = match highlight {
+                    Highlight::None => {
+                        tables.push(player);
+                        continue;
+                    }
+                    Highlight::Selected => ([brightest; 3], SELECTED_LIGHTEN),
+                    Highlight::Damaged => ([brightest, 0, 0], DAMAGED_REDDEN),
+                };
+                let mut table = [0; 256];
+                for (entry, index) in table.iter_mut().zip(player.iter()) {
+                    let original = color(*index as usize);
+                    let mut mixed = [0; 3];
+                    for channel in 0..3 {
+                        mixed[channel] =
+                            (original[channel] * (100 - percent) + target[channel] * percent) / 100;
+                    }
+                    *entry = nearest(palette, mixed);
+                }
+                tables.push(table);
+            }
+        }
+        PlayerColors { tables }
+    }
+
+    /// The table to draw `player`'s sprites with, `None` for players without colors (`PLAYERS`
+    /// and above).
+    pub fn remap(&self, player: Player, highlight: Highlight) -> Option<&Remap> {
+        let player = player.0 as usize;
+        if player >= PLAYERS {
+            return None;
+        }
+        let highlight = match highlight {
+            Highlight::None => 0,
+            Highlight::Selected => 1,
+            Highlight::Damaged => 2,
+        };
+        self.tables.get(player * HIGHLIGHTS.len() + highlight)
+    }
+}
+
+/// The palette color nearest to `rgb`, the lowest index if there are more of them. Color 0 is
+/// never picked, it's transparent.
+fn nearest(palette: &[u8], rgb: [u32; 3]) -> u8 {
+    (1..256)
+        .min_by_key(|index| {
+            palette[index * 3..index * 3 + 3]
+                .iter()
+                .zip(rgb.iter())
+                .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paldat::Paldat;
+    use crate::playercolors::{is_flashing, Highlight, PlayerColors, PLAYERS};
+    use crate::units::Player;
+    use std::ops;
+
+    /// A palette of grays (color `i` is `i, i, i`) with a ramp of reds at 100..108.
+    fn palette() -> Paldat {
+        let mut data: Vec<u8> = (0..768).map(|i| (i / 3) as u8).collect();
+        for (i, index) in (100..108).enumerate() {
+            data[index * 3..index * 3 + 3].copy_from_slice(&[128 + i as u8 * 16, 0, 0]);
+        }
+        Paldat::load(&data[..]).unwrap()
+    }
+
+    /// Stands in for the player colors, which aren't known yet.
+    const COLORS: ops::Range<usize> = 200..208;
+
+    #[test]
+    fn test_player_colors_work() {
+        let colors = PlayerColors::with_player_colors(&palette(), 0, COLORS);
+        let first = colors.remap(Player(0), Highlight::None).unwrap();
+        assert!(first.iter().enumerate().all(|(i, c)| i == *c as usize));
+
+        let second = colors.remap(Player(1), Highlight::None).unwrap();
+        let brightest = COLORS.end - 1;
+        assert!((100..108).contains(&second[brightest]));
+        assert!(second[brightest] > second[COLORS.start]);
+        assert_eq!(second[COLORS.start - 1] as usize, COLORS.start - 1);
+        assert_eq!(second[10], 10);
+
+        // Players without colors don't panic.
+        assert!(colors
+            .remap(Player(PLAYERS as u8), Highlight::None)
+            .is_none());
+        assert!(colors.remap(Player(255), Highlight::Damaged).is_none());
+
+        // Without known player colors nothing is recolored.
+        let colors = PlayerColors::new(&palette(), 0);
+        let second = colors.remap(Player(1), Highlight::None).unwrap();
+        assert!(second.iter().enumerate().all(|(i, c)| i == *c as usize));
+    }
+
+    #[test]
+    fn test_highlights_work() {
+        let colors = PlayerColors::with_player_colors(&palette(), 0, COLORS);
+        let selected = colors.remap(Player(0), Highlight::Selected).unwrap();
+        assert!(selected[10] > 10);
+        assert_eq!(selected[255], 255);
+        let damaged = colors.remap(Player(2), Highlight::Damaged).unwrap();
+        assert!((100..108).contains(&damaged[200]));
+
+        let flashes: Vec<_> = (0..8).map(is_flashing).collect();
+        assert_eq!(
+            flashes,
+            vec![true, false, true, false, true, false, false, false]
+        );
+    }
+}
@@ -0,0 +1,527 @@
+//! Input recording and replaying.
+//!
+//! A recording contains the `Input` the game processed in every tick together with the time that
+//! passed since the previous tick. Feeding the recorded input back into the game reproduces the
+//! session exactly, which makes it possible to turn play sessions into regression tests.
+//!
+//! # Recording file format
+//!
+//! All integers are little endian. `varint` is an unsigned LEB128-encoded integer.
+//!
+//! * The header: the `OPRP` magic, a version byte (currently 3), an 8-byte game data
+//!   fingerprint (see `fingerprint()`) and the `varint` pal.dat palette chosen on the command
+//!   line plus one (0 if none was). Replaying with different game data would produce different
+//!   frames, the fingerprint lets us detect that.
+//! * One record per tick:
+//!   * `varint` tick number
+//!   * `varint` milliseconds since the previous tick
+//!   * 8-byte checksum of the frame rendered in the tick (see `frame_checksum()`)
+//!   * a flags byte saying which of the following fields are present
+//!   * (`MOUSE_MOVED`) 2-byte x and 2-byte y mouse position
+//!   * (`KEYS`) `varint` count followed by `varint` scancodes, pressed keys and then released keys
+//!   * (`MOUSE_BUTTONS`) byte count followed by button bytes, pressed buttons and then released
+//!     buttons
+//!   * (`WHEEL`) 4-byte signed wheel movement
+//!   * (`DRAG`) button byte, 2-byte x and y of the start, 2-byte x and y of the end and a phase
+//!     byte
+//!   * (`TEXT`) `varint` length followed by the typed text encoded as UTF-8
+//!   * (`KEYS_TYPED`) `varint` count followed by `varint` scancodes
+//!
+//! Held keys and buttons as well as modifiers are not stored, they're reconstructed from the
+//! presses and releases.
+//!
+//! The user's configuration (settings, key bindings, message catalogs, saved games) isn't stored
+//! either. The game ignores it while recording and replaying and starts from the built-in
+//! defaults instead, so that a recording replays the same on any machine.
+
+use crate::input::{ButtonState, Drag, DragPhase, Input, MousePosition};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"OPRP";
+pub const VERSION: u8 = 3;
+
+const MOUSE_MOVED: u8 = 1;
+const KEYS: u8 = 1 << 1;
+const MOUSE_BUTTONS: u8 = 1 << 2;
+const WHEEL: u8 = 1 << 3;
+const DRAG: u8 = 1 << 4;
+const TEXT: u8 = 1 << 5;
+const KEYS_TYPED: u8 = 1 << 6;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Compute a fingerprint of game data files (their contents, in a fixed order).
+pub fn fingerprint(files: &[&[u8]]) -> u64 {
+    files.iter().fold(FNV_OFFSET_BASIS, |hash, data| {
+        fnv1a(fnv1a(hash, &(data.len() as u64).to_le_bytes()), data)
+    })
+}
+
+/// Compute a checksum of a rendered frame.
+pub fn frame_checksum(buffer: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, buffer)
+}
+
+/// A single recorded tick.
+#[derive(Clone, Debug)]
+pub struct Tick {
+    pub tick: u32,
+    /// Milliseconds since the previous tick.
+    pub dt: u32,
+    pub input: Input,
+    pub frame_checksum: u64,
+}
+
+/// Writes recordings.
+pub struct Recorder<T: Write> {
+    writer: T,
+    tick: u32,
+    mouse_position: MousePosition,
+}
+
+impl<T: Write> Recorder<T> {
+    /// Start a recording by writing the header. `palette` is the palette chosen on the command
+    /// line, if any.
+    pub fn new(mut writer: T, fingerprint: u64, palette: Option<usize>) -> io::Result<Recorder<T>> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&fingerprint.to_le_bytes())?;
+        write_varint(&mut writer, palette.map_or(0, |palette| palette as u32 + 1))?;
+        Ok(Recorder {
+            writer,
+            tick: 0,
+            mouse_position: MousePosition::new(0, 0),
+        })
+    }
+
+    /// Record a tick.
+    pub fn record(&mut self, dt: u32, input: &Input, frame_checksum: u64) -> io::Result<()> {
+        let w = &mut self.writer;
+        write_varint(w, self.tick)?;
+        write_varint(w, dt)?;
+        w.write_all(&frame_checksum.to_le_bytes())?;
+
+        let keys_changed = !input.keys.pressed.is_empty() || !input.keys.released.is_empty();
+        let buttons_changed =
+            !input.mouse_buttons.pressed.is_empty() || !input.mouse_buttons.released.is_empty();
+        let flags = [
+            (input.mouse_position != self.mouse_position, MOUSE_MOVED),
+            (keys_changed, KEYS),
+            (buttons_changed, MOUSE_BUTTONS),
+            (input.wheel != 0, WHEEL),
+            (input.drag.is_some(), DRAG),
+            (!input.text.is_empty(), TEXT),
+            (!input.keys_typed.is_empty(), KEYS_TYPED),
+        ]
+        .iter()
+        .filter(|(present, _)| *present)
+        .fold(0, |flags, (_, flag)| flags | flag);
+        w.write_all(&[flags])?;
+
+        if flags & MOUSE_MOVED != 0 {
+            write_position(w, input.mouse_position)?;
+        }
+        if flags & KEYS != 0 {
+            for keys in [&input.keys.pressed, &input.keys.released] {
+                let codes = sorted(keys.iter().map(|scancode| *scancode as u32));
+                write_varint(w, codes.len() as u32)?;
+                for code in codes {
+                    write_varint(w, code)?;
+                }
+            }
+        }
+        if flags & MOUSE_BUTTONS != 0 {
+            for buttons in [&input.mouse_buttons.pressed, &input.mouse_buttons.released] {
+                let codes = sorted(buttons.iter().map(|button| *button as u8));
+                w.write_all(&[codes.len() as u8])?;
+                w.write_all(&codes)?;
+            }
+        }
+        if flags & WHEEL != 0 {
+            w.write_all(&input.wheel.to_le_bytes())?;
+        }
+        if let Some(drag) = input.drag {
+            w.write_all(&[drag.button as u8])?;
+            write_position(w, drag.start)?;
+            write_position(w, drag.end)?;
+            w.write_all(&[drag_phase_to_byte(drag.phase)])?;
+        }
+        if flags & TEXT != 0 {
+            write_varint(w, input.text.len() as u32)?;
+            w.write_all(input.text.as_bytes())?;
+        }
+        if flags & KEYS_TYPED != 0 {
+            write_varint(w, input.keys_typed.len() as u32)?;
+            for scancode in &input.keys_typed {
+                write_varint(w, *scancode as u32)?;
+            }
+        }
+
+        self.mouse_position = input.mouse_position;
+        self.tick += 1;
+        Ok(())
+    }
+
+    /// Flush the underlying writer and return it.
+    pub fn finish(mut self) -> io::Result<T> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads recordings.
+pub struct Player<T: Read> {
+    reader: T,
+    fingerprint: u64,
+    palette: Option<usize>,
+    next_tick: u32,
+    mouse_position: MousePosition,
+    keys_held: HashSet<Scancode>,
+    mouse_buttons_held: HashSet<MouseButton>,
+}
+
+impl<T: Read> Player<T> {
+    /// Open a recording.
+    ///
+    /// # Errors
+    /// An error is returned if the header can't be read or it's not a header of a recording
+    /// this version of the code understands.
+    pub fn new(mut reader: T) -> Result<Player<T>, String> {
+        let mut header = [0; 13];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| format!("Cannot read the recording header: {e}"))?;
+        if &header[0..4] != MAGIC {
+            return Err("Not an openpol recording".to_string());
+        }
+        if header[4] != VERSION {
+            return Err(format!("Unsupported recording version {}", header[4]));
+        }
+        let mut fingerprint = [0; 8];
+        fingerprint.copy_from_slice(&header[5..13]);
+        let palette = read_varint(&mut reader)
+            .map_err(|e| format!("Cannot read the recording header: {e}"))?;
+        Ok(Player {
+            reader,
+            fingerprint: u64::from_le_bytes(fingerprint),
+            palette: palette.checked_sub(1).map(|palette| palette as usize),
+            next_tick: 0,
+            mouse_position: MousePosition::new(0, 0),
+            keys_held: HashSet::new(),
+            mouse_buttons_held: HashSet::new(),
+        })
+    }
+
+    /// The fingerprint of the game data the recording was made with.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// The palette chosen on the command line when recording, if any.
+    pub fn palette(&self) -> Option<usize> {
+        self.palette
+    }
+
+    /// Read the next tick. `Ok(None)` means the recording has ended.
+    pub fn next_tick(&mut self) -> Result<Option<Tick>, String> {
+        let tick = match read_varint(&mut self.reader) {
+            Ok(tick) => tick,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        self.read_tick(tick)
+            .map_err(|e| format!("Cannot read tick {tick}: {e}"))
+            .map(Some)
+    }
+
+    fn read_tick(&mut self, tick: u32) -> io::Result<Tick> {
+        if tick != self.next_tick {
+            return Err(invalid_data(&format!("expected tick {}", self.next_tick)));
+        }
+        let r = &mut self.reader;
+        let dt = read_varint(r)?;
+        let mut checksum = [0; 8];
+        r.read_exact(&mut checksum)?;
+        let flags = read_u8(r)?;
+
+        let mut input = Input::idle(self.mouse_position);
+        if flags & MOUSE_MOVED != 0 {
+            input.mouse_position = read_position(r)?;
+        }
+        input.keys = ButtonState {
+            held: self.keys_held.clone(),
+            ..ButtonState::default()
+        };
+        if flags & KEYS != 0 {
+            let mut sets = [HashSet::new(), HashSet::new()];
+            for set in &mut sets {
+                for _ in 0..read_varint(r)? {
+                    set.insert(read_scancode(r)?);
+                }
+            }
+            let [pressed, released] = sets;
+            apply(&mut input.keys, pressed, released);
+        }
+        input.mouse_buttons = ButtonState {
+            held: self.mouse_buttons_held.clone(),
+            ..ButtonState::default()
+        };
+        if flags & MOUSE_BUTTONS != 0 {
+            let mut sets = [HashSet::new(), HashSet::new()];
+            for set in &mut sets {
+                for _ in 0..read_u8(r)? {
+                    set.insert(MouseButton::from_ll(read_u8(r)?));
+                }
+            }
+            let [pressed, released] = sets;
+            apply(&mut input.mouse_buttons, pressed, released);
+        }
+        if flags & WHEEL != 0 {
+            let mut wheel = [0; 4];
+            r.read_exact(&mut wheel)?;
+            input.wheel = i32::from_le_bytes(wheel);
+        }
+        if flags & DRAG != 0 {
+            input.drag = Some(Drag {
+                button: MouseButton::from_ll(read_u8(r)?),
+                start: read_position(r)?,
+                end: read_position(r)?,
+                phase: drag_phase_from_byte(read_u8(r)?)
+                    .ok_or_else(|| invalid_data("invalid drag phase"))?,
+            });
+        }
+        if flags & TEXT != 0 {
+            let mut text = vec![0; read_varint(r)? as usize];
+            r.read_exact(&mut text)?;
+            input.text = String::from_utf8(text).map_err(|_| invalid_data("invalid text"))?;
+        }
+        if flags & KEYS_TYPED != 0 {
+            for _ in 0..read_varint(r)? {
+                input.keys_typed.push(read_scancode(r)?);
+            }
+        }
+        input.modifiers = input.keys.modifiers();
+
+        self.mouse_position = input.mouse_position;
+        self.keys_held = input.keys.held.clone();
+        self.mouse_buttons_held = input.mouse_buttons.held.clone();
+        self.next_tick += 1;
+        Ok(Tick {
+            tick,
+            dt,
+            input,
+            frame_checksum: u64::from_le_bytes(checksum),
+        })
+    }
+}
+
+fn apply<T: Copy + Eq + Hash>(
+    state: &mut ButtonState<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+) {
+    // The order matters when a button is both pressed and released within a single tick. The
+    // processor would report such a button as not held, the same is done here.
+    state.held.extend(pressed.iter().copied());
+    for button in &released {
+        state.held.remove(button);
+    }
+    state.pressed = pressed;
+    state.released = released;
+}
+
+fn sorted<T: Ord, I: Iterator<Item = T>>(iterator: I) -> Vec<T> {
+    let mut values = iterator.collect::<Vec<_>>();
+    values.sort();
+    values
+}
+
+fn drag_phase_to_byte(phase: DragPhase) -> u8 {
+    match phase {
+        DragPhase::Pending => 0,
+        DragPhase::Started => 1,
+        DragPhase::InProgress => 2,
+        DragPhase::Ended => 3,
+    }
+}
+
+fn drag_phase_from_byte(byte: u8) -> Option<DragPhase> {
+    match byte {
+        0 => Some(DragPhase::Pending),
+        1 => Some(DragPhase::Started),
+        2 => Some(DragPhase::InProgress),
+        3 => Some(DragPhase::Ended),
+        _ => None,
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn write_varint<T: Write>(writer: &mut T, mut value: u32) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn read_varint<T: Read>(reader: &mut T) -> io::Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = read_u8(reader)?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint too long"))
+}
+
+fn read_scancode<T: Read>(reader: &mut T) -> io::Result<Scancode> {
+    Scancode::from_i32(read_varint(reader)? as i32).ok_or_else(|| invalid_data("invalid scancode"))
+}
+
+pub(crate) fn read_u8<T: Read>(reader: &mut T) -> io::Result<u8> {
+    let mut buffer = [0];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn write_position<T: Write>(writer: &mut T, position: MousePosition) -> io::Result<()> {
+    writer.write_all(&(position.x as u16).to_le_bytes())?;
+    writer.write_all(&(position.y as u16).to_le_bytes())
+}
+
+fn read_position<T: Read>(reader: &mut T) -> io::Result<MousePosition> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(MousePosition::new(
+        u16::from_le_bytes([buffer[0], buffer[1]]) as usize,
+        u16::from_le_bytes([buffer[2], buffer[3]]) as usize,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{Drag, DragPhase, Input, MousePosition};
+    use crate::replay::{fingerprint, frame_checksum, Player, Recorder};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    fn inputs() -> Vec<Input> {
+        let mut first = Input::idle(MousePosition::new(10, 20));
+        first.keys.pressed.insert(Scancode::LShift);
+        first.keys.pressed.insert(Scancode::A);
+        first.keys.held.insert(Scancode::LShift);
+        first.keys.held.insert(Scancode::A);
+        first.modifiers.shift = true;
+        first.mouse_buttons.pressed.insert(MouseButton::Left);
+        first.mouse_buttons.held.insert(MouseButton::Left);
+        first.wheel = -3;
+        first.text = "Żółw".to_string();
+        first.keys_typed = vec![Scancode::A, Scancode::Backspace, Scancode::Backspace];
+
+        let mut second = Input::idle(MousePosition::new(300, 150));
+        second.keys.released.insert(Scancode::A);
+        second.keys.held.insert(Scancode::LShift);
+        second.modifiers.shift = true;
+        second.mouse_buttons.held.insert(MouseButton::Left);
+        second.drag = Some(Drag {
+            button: MouseButton::Left,
+            start: MousePosition::new(10, 20),
+            end: MousePosition::new(300, 150),
+            phase: DragPhase::Started,
+        });
+
+        let mut third = Input::idle(MousePosition::new(300, 150));
+        third.keys.held.insert(Scancode::LShift);
+        third.modifiers.shift = true;
+        third.mouse_buttons.held.insert(MouseButton::Left);
+        vec![first, second, third]
+    }
+
+    #[test]
+    fn test_recording_and_replaying_works() {
+        let mut recorder = Recorder::new(Vec::new(), 1234, Some(7)).unwrap();
+        for (i, input) in inputs().iter().enumerate() {
+            recorder
+                .record(16 + i as u32, input, 100 + i as u64)
+                .unwrap();
+        }
+        let recording = recorder.finish().unwrap();
+
+        let mut player = Player::new(&recording[..]).unwrap();
+        assert_eq!(player.fingerprint(), 1234);
+        assert_eq!(player.palette(), Some(7));
+        for (i, expected) in inputs().iter().enumerate() {
+            let tick = player.next_tick().unwrap().unwrap();
+            assert_eq!(tick.tick, i as u32);
+            assert_eq!(tick.dt, 16 + i as u32);
+            assert_eq!(tick.frame_checksum, 100 + i as u64);
+            let input = tick.input;
+            assert_eq!(input.mouse_position, expected.mouse_position);
+            assert_eq!(input.keys.pressed, expected.keys.pressed);
+            assert_eq!(input.keys.released, expected.keys.released);
+            assert_eq!(input.keys.held, expected.keys.held);
+            assert_eq!(input.mouse_buttons.pressed, expected.mouse_buttons.pressed);
+            assert_eq!(input.mouse_buttons.held, expected.mouse_buttons.held);
+            assert_eq!(input.modifiers, expected.modifiers);
+            assert_eq!(input.wheel, expected.wheel);
+            assert_eq!(input.drag, expected.drag);
+            assert_eq!(input.text, expected.text);
+            assert_eq!(input.keys_typed, expected.keys_typed);
+        }
+        assert!(player.next_tick().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_idle_ticks_are_compact() {
+        let mut recorder = Recorder::new(Vec::new(), 0, None).unwrap();
+        let input = Input::idle(MousePosition::new(0, 0));
+        for _ in 0..100 {
+            recorder.record(16, &input, 0).unwrap();
+        }
+        let recording = recorder.finish().unwrap();
+        // Header + 100 * (tick, dt, checksum, flags)
+        assert_eq!(recording.len(), 14 + 100 * (1 + 1 + 8 + 1));
+    }
+
+    #[test]
+    fn test_invalid_recordings_are_rejected() {
+        assert!(Player::new(&b"OPRP"[..]).is_err());
+        assert!(Player::new(&b"XXXX\x01\0\0\0\0\0\0\0\0"[..]).is_err());
+        assert!(Player::new(&b"OPRP\x09\0\0\0\0\0\0\0\0\0"[..]).is_err());
+        assert!(Player::new(&b"OPRP\x03\0\0\0\0\0\0\0\0"[..]).is_err());
+
+        let mut recorder = Recorder::new(Vec::new(), 0, None).unwrap();
+        recorder
+            .record(16, &Input::idle(MousePosition::new(1, 1)), 0)
+            .unwrap();
+        let recording = recorder.finish().unwrap();
+        // Cut the recording in the middle of the tick.
+        let mut player = Player::new(&recording[..recording.len() - 2]).unwrap();
+        assert!(player.next_tick().is_err());
+    }
+
+    #[test]
+    fn test_fingerprints_differ() {
+        assert_ne!(fingerprint(&[b"ab", b"c"]), fingerprint(&[b"a", b"bc"]));
+        assert_ne!(frame_checksum(&[0, 1]), frame_checksum(&[1, 0]));
+    }
+}
@@ -0,0 +1,306 @@
+//! Selecting units.
+//!
+//! A player selects their units by clicking them or by dragging a rectangle around them in the
+//! viewport (a unit is inside when the center of its cell is). Holding shift adds the units to
+//! the selection instead, a shift-click on a selected unit removes it. Double-clicking a unit
+//! selects all of the player's units of its kind in the viewport.
+//!
+//! The selection can be stored in a control group with ctrl and the group's key (see
+//! `Action::SelectGroup`) and brought back with the key alone. Only the player's own units can
+//! be selected.
+
+use crate::actions::{Action, Bindings, Context, GROUPS};
+use crate::hud::VIEWPORT;
+use crate::image13h::Rect;
+use crate::input::Input;
+use crate::map::{TILE_HEIGHT, TILE_WIDTH};
+use crate::pathfinding::Position;
+use crate::units::{Player, UnitId, Units};
+use crate::viewport::Viewport;
+use sdl2::mouse::MouseButton;
+
+/// How long (in milliseconds) after a click the next one counts as a double-click.
+pub const DOUBLE_CLICK_MS: u32 = 400;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Selection {
+    /// The selected units in the order they were selected.
+    units: Vec<UnitId>,
+    groups: Vec<Vec<UnitId>>,
+    /// The unit clicked last and how long ago, in milliseconds.
+    last_click: Option<(UnitId, u32)>,
+}
+
+impl Default for Selection {
+    fn default() -> Selection {
+        Selection::new()
+    }
+}
+
+impl Selection {
+    pub fn new() -> Selection {
+        Selection {
+            units: Vec::new(),
+            groups: vec![Vec::new(); GROUPS as usize],
+            last_click: None,
+        }
+    }
+
+    pub fn units(&self) -> &[UnitId] {
+        &self.units
+    }
+
+    pub fn contains(&self, id: UnitId) -> bool {
+        self.units.contains(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.units.clear();
+    }
+
+    /// Select `ids` instead of the current selection, or in addition to it with `add`.
+    pub fn select<T: IntoIterator<Item = UnitId>>(&mut self, ids: T, add: bool) {
+        if !add {
+            self.units.clear();
+        }
+        for id in ids {
+            if !self.units.contains(&id) {
+                self.units.push(id);
+            }
+        }
+    }
+
+    /// A click on the cell `cell` (`add` if shift is held). `visible` are the cells shown in the
+    /// viewport, used by double-clicks.
+    pub fn click(
+        &mut self,
+        units: &Units,
+        player: Player,
+        cell: Position,
+        visible: &Rect,
+        add: bool,
+    ) {
+        let clicked = units
+            .owned_by(player)
+            .find(|unit| unit.position == cell)
+            .map(|unit| (unit.id, unit.kind));
+        let (id, kind) = match clicked {
+            Some(clicked) => clicked,
+            None => {
+                self.last_click = None;
+                if !add {
+                    self.clear();
+                }
+                return;
+            }
+        };
+        let double = self.last_click.is_some_and(|(last, _)| last == id);
+        self.last_click = Some((id, 0));
+        if double {
+            let same_kind = units.owned_by(player).filter(|unit| {
+                unit.kind == kind && visible.contains(unit.position.0, unit.position.1)
+            });
+            self.select(same_kind.map(|unit| unit.id), add);
+        } else if add && self.contains(id) {
+            self.units.retain(|selected| *selected != id);
+        } else {
+            self.select(Some(id), add);
+        }
+    }
+
+    /// Select the player's units in the `area` cells.
+    pub fn select_in(&mut self, units: &Units, player: Player, area: &Rect, add: bool) {
+        let inside = units
+            .owned_by(player)
+            .filter(|unit| area.contains(unit.position.0, unit.position.1))
+            .map(|unit| unit.id);
+        self.select(inside, add);
+    }
+
+    /// Store the selection in a control group (0-based).
+    pub fn assign_group(&mut self, group: u8) {
+        self.groups[group as usize] = self.units.clone();
+    }
+
+    /// Select the units of a control group. An empty group leaves the selection alone.
+    pub fn recall_group(&mut self, group: u8) {
+        if !self.groups[group as usize].is_empty() {
+            self.units = self.groups[group as usize].clone();
+        }
+    }
+
+    pub fn group(&self, group: u8) -> &[UnitId] {
+        &self.groups[group as usize]
+    }
+
+    /// Forget the units that don't exist anymore.
+    pub fn prune(&mut self, units: &Units) {
+        self.units.retain(|id| units.get(*id).is_some());
+        for group in &mut self.groups {
+            group.retain(|id| units.get(*id).is_some());
+        }
+    }
+
+    /// Process a frame of input that took `dt` milliseconds: clicks and rectangles in the
+    /// viewport and the control group keys.
+    pub fn update(
+        &mut self,
+        input: &Input,
+        bindings: &Bindings,
+        viewport: &Viewport,
+        units: &Units,
+        player: Player,
+        dt: u32,
+    ) {
+        self.prune(units);
+        self.last_click = self
+            .last_click
+            .map(|(id, elapsed)| (id, elapsed.saturating_add(dt)))
+            .filter(|(_, elapsed)| *elapsed <= DOUBLE_CLICK_MS);
+
+        let add = input.modifiers.shift;
+        match input.drag {
+            Some(drag) if drag.button == MouseButton::Left => {
+                if input.mouse_buttons.is_released(MouseButton::Left)
+                    && VIEWPORT.contains(drag.start.x, drag.start.y)
+                {
+                    let area = drag.rect();
+                    let (left, top) = (area.left.max(VIEWPORT.left), area.top.max(VIEWPORT.top));
+                    let right = area.beyond_right().min(VIEWPORT.beyond_right());
+                    let bottom = area.beyond_bottom().min(VIEWPORT.beyond_bottom());
+                    let (x, y) = viewport.position();
+                    let world = Rect::from_ranges(
+                        left - VIEWPORT.left + x..right - VIEWPORT.left + x,
+                        top - VIEWPORT.top + y..bottom - VIEWPORT.top + y,
+                    );
+                    self.select_in(units, player, &cells_centered_in(&world), add);
+                }
+            }
+            _ => {
+                let position = input.mouse_position;
+                if input.mouse_buttons.is_released(MouseButton::Left) {
+                    if let Some(cell) = viewport.screen_to_cell(position.x, position.y) {
+                        let visible = visible_cells(viewport);
+                        self.click(units, player, cell, &visible, add);
+                    }
+                }
+            }
+        }
+
+        for action in bindings.triggered(input, Context::InGame) {
+            if let Action::SelectGroup(group) = action {
+                if input.modifiers.ctrl {
+                    self.assign_group(group);
+                } else {
+                    self.recall_group(group);
+                }
+            }
+        }
+    }
+}
+
+/// The cells shown in the viewport (the ones with their centers visible).
+pub fn visible_cells(viewport: &Viewport) -> Rect {
+    let (x, y) = viewport.position();
+    cells_centered_in(&Rect::from_ranges(
+        x..x + VIEWPORT.width,
+        y..y + VIEWPORT.height,
+    ))
+}
+
+/// The cells whose centers are inside of a world area (in pixels).
+fn cells_centered_in(area: &Rect) -> Rect {
+    // The first cell with its center at `position` or beyond it (the tile sizes are even).
+    let cell = |position: usize, size: usize| (position + size / 2 - 1) / size;
+    let (left, right) = (
+        cell(area.left, TILE_WIDTH),
+        cell(area.beyond_right(), TILE_WIDTH),
+    );
+    let (top, bottom) = (
+        cell(area.top, TILE_HEIGHT),
+        cell(area.beyond_bottom(), TILE_HEIGHT),
+    );
+    Rect::from_ranges(left..right.max(left), top..bottom.max(top))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image13h::Rect;
+    use crate::selection::{cells_centered_in, Selection};
+    use crate::units::{Player, UnitKind, UnitTable, Units};
+
+    #[test]
+    fn test_clicks_work() {
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let first = units.spawn(&table, UnitKind::Warrior, Player(0), (1, 1));
+        let second = units.spawn(&table, UnitKind::Warrior, Player(0), (3, 1));
+        let peasant = units.spawn(&table, UnitKind::Peasant, Player(0), (2, 2));
+        let far = units.spawn(&table, UnitKind::Warrior, Player(0), (20, 20));
+        units.spawn(&table, UnitKind::Warrior, Player(1), (4, 4));
+        let visible = Rect::from_ranges(0..10, 0..10);
+        let mut selection = Selection::new();
+        let click = |selection: &mut Selection, cell, add| {
+            selection.click(&units, Player(0), cell, &visible, add);
+        };
+
+        click(&mut selection, (1, 1), false);
+        assert_eq!(selection.units(), [first]);
+        click(&mut selection, (2, 2), true);
+        assert_eq!(selection.units(), [first, peasant]);
+        click(&mut selection, (1, 1), true);
+        assert_eq!(selection.units(), [peasant]);
+        // Enemies can't be selected, clicking them deselects.
+        click(&mut selection, (4, 4), false);
+        assert!(selection.is_empty());
+
+        // A double-click selects the units of the kind in view.
+        click(&mut selection, (3, 1), false);
+        click(&mut selection, (3, 1), false);
+        assert_eq!(selection.units(), [first, second]);
+        assert!(!selection.contains(far));
+    }
+
+    #[test]
+    fn test_rectangles_and_groups_work() {
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let first = units.spawn(&table, UnitKind::Archer, Player(0), (1, 1));
+        let second = units.spawn(&table, UnitKind::Warrior, Player(0), (2, 3));
+        units.spawn(&table, UnitKind::Warrior, Player(1), (2, 2));
+        let mut selection = Selection::new();
+        selection.select_in(&units, Player(0), &Rect::from_ranges(0..3, 0..3), false);
+        assert_eq!(selection.units(), [first]);
+        selection.select_in(&units, Player(0), &Rect::from_ranges(2..3, 3..4), true);
+        assert_eq!(selection.units(), [first, second]);
+
+        selection.assign_group(3);
+        selection.clear();
+        selection.recall_group(4);
+        assert!(selection.is_empty());
+        selection.recall_group(3);
+        assert_eq!(selection.units(), [first, second]);
+
+        units.remove(first);
+        selection.prune(&units);
+        assert_eq!(selection.units(), [second]);
+        assert_eq!(selection.group(3), [second]);
+    }
+
+    #[test]
+    fn test_cells_centered_in_work() {
+        // Cell 1 spans 16..32 with its center at 24, cell 2 spans 32..48 with the center at 40.
+        assert_eq!(
+            cells_centered_in(&Rect::from_ranges(20..41, 0..8)),
+            Rect::from_ranges(1..3, 0..1)
+        );
+        assert_eq!(
+            cells_centered_in(&Rect::from_ranges(25..40, 8..20)),
+            Rect::from_ranges(2..2, 1..1)
+        );
+    }
+}
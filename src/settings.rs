@@ -0,0 +1,264 @@
+//! User settings.
+//!
+//! # Settings file format
+//!
+//! The settings are kept in a `key = value` file, one setting per line, in the
+//! [configuration directory](../config/index.html). Empty lines and lines starting with `#` are
+//! ignored. Keys this version of openpol doesn't know about are kept as they are when the file
+//! is saved, so settings written by a newer version aren't lost. Example:
+//!
+//! ```text
+//! scale = 3
+//! music-volume = 80
+//! sound-volume = 100
+//! music = on
+//! skip-intro = off
+//! language = pl
+//! game-dir = /home/user/polanie
+//! ```
+
+use crate::config;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path;
+
+/// The name of the settings file within the configuration directory.
+pub const SETTINGS_FILE_NAME: &str = "settings.cfg";
+
+/// The smallest window scale.
+pub const MIN_SCALE: u32 = 1;
+
+/// The largest window scale.
+pub const MAX_SCALE: u32 = 6;
+
+/// The largest volume, volumes are percentages.
+pub const MAX_VOLUME: u32 = 100;
+
+/// The languages the game can be played in.
+pub const LANGUAGES: [&str; 2] = ["pl", "en"];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settings {
+    /// How many times the window is larger than the game's 320x200 screen.
+    pub scale: u32,
+    /// The music volume, 0 to `MAX_VOLUME`.
+    pub music_volume: u32,
+    /// The sound effect volume, 0 to `MAX_VOLUME`.
+    pub sound_volume: u32,
+    pub music: bool,
+    pub skip_intro: bool,
+    /// One of `LANGUAGES`.
+    pub language: String,
+    /// The directory with the original game's files.
+    pub game_dir: Option<String>,
+    /// Settings we don't know about, in the order they appeared in the file.
+    unknown: Vec<(String, String)>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            scale: 2,
+            music_volume: MAX_VOLUME,
+            sound_volume: MAX_VOLUME,
+            music: true,
+            skip_intro: false,
+            language: LANGUAGES[0].to_string(),
+            game_dir: None,
+            unknown: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings from a reader. The defaults are used for settings the file doesn't
+    /// mention.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read or if the file contains
+    /// invalid values of known settings or lines that can't be parsed.
+    pub fn load<T: io::Read>(reader: T) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        for (number, line) in io::BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {}", number + 1, message);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || error(&format!("invalid {key} value {value:?}"));
+            match key {
+                "scale" => {
+                    settings.scale =
+                        parse_number(value, MIN_SCALE, MAX_SCALE).ok_or_else(invalid)?
+                }
+                "music-volume" => {
+                    settings.music_volume =
+                        parse_number(value, 0, MAX_VOLUME).ok_or_else(invalid)?
+                }
+                "sound-volume" => {
+                    settings.sound_volume =
+                        parse_number(value, 0, MAX_VOLUME).ok_or_else(invalid)?
+                }
+                "music" => settings.music = parse_switch(value).ok_or_else(invalid)?,
+                "skip-intro" => settings.skip_intro = parse_switch(value).ok_or_else(invalid)?,
+                "language" => {
+                    if !LANGUAGES.contains(&value) {
+                        return Err(invalid());
+                    }
+                    settings.language = value.to_string();
+                }
+                "game-dir" => settings.game_dir = Some(value.to_string()),
+                _ => settings.set_unknown(key, value),
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn save<T: io::Write>(&self, mut writer: T) -> io::Result<()> {
+        let switch = |value| if value { "on" } else { "off" };
+        writeln!(writer, "scale = {}", self.scale)?;
+        writeln!(writer, "music-volume = {}", self.music_volume)?;
+        writeln!(writer, "sound-volume = {}", self.sound_volume)?;
+        writeln!(writer, "music = {}", switch(self.music))?;
+        writeln!(writer, "skip-intro = {}", switch(self.skip_intro))?;
+        writeln!(writer, "language = {}", self.language)?;
+        if let Some(game_dir) = &self.game_dir {
+            writeln!(writer, "game-dir = {game_dir}")?;
+        }
+        for (key, value) in &self.unknown {
+            writeln!(writer, "{key} = {value}")?;
+        }
+        Ok(())
+    }
+
+    /// The value of a setting this version of openpol doesn't know about.
+    pub fn unknown(&self, key: &str) -> Option<&str> {
+        self.unknown
+            .iter()
+            .find(|(unknown_key, _)| unknown_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn set_unknown(&mut self, key: &str, value: &str) {
+        match self
+            .unknown
+            .iter_mut()
+            .find(|(unknown_key, _)| unknown_key == key)
+        {
+            Some((_, unknown_value)) => *unknown_value = value.to_string(),
+            None => self.unknown.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Load the settings from the configuration directory. The defaults are used if the file
+    /// doesn't exist.
+    pub fn load_from_config_dir() -> Result<Settings, String> {
+        match path().filter(|path| path.is_file()) {
+            Some(path) => Settings::load(fs::File::open(&path).map_err(|e| e.to_string())?)
+                .map_err(|e| format!("Cannot load settings from {path:?}: {e}")),
+            None => Ok(Settings::default()),
+        }
+    }
+
+    /// Save the settings to the configuration directory, creating the directory if needed.
+    pub fn save_to_config_dir(&self) -> Result<(), String> {
+        let path = path().ok_or("Cannot determine the configuration directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create {dir:?}: {e}"))?;
+        }
+        let file = fs::File::create(&path).map_err(|e| format!("Cannot create {path:?}: {e}"))?;
+        self.save(io::BufWriter::new(file))
+            .map_err(|e| format!("Cannot save settings to {path:?}: {e}"))
+    }
+
+    /// The music volume as a fraction, 0.0 to 1.0, taking the music switch into account.
+    pub fn music_gain(&self) -> f32 {
+        if self.music {
+            self.music_volume as f32 / MAX_VOLUME as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// The sound effect volume as a fraction, 0.0 to 1.0.
+    pub fn sound_gain(&self) -> f32 {
+        self.sound_volume as f32 / MAX_VOLUME as f32
+    }
+}
+
+/// The location of the settings file, `None` if the configuration directory can't be
+/// determined.
+pub fn path() -> Option<path::PathBuf> {
+    config::config_dir().map(|dir| dir.join(SETTINGS_FILE_NAME))
+}
+
+fn parse_number(value: &str, min: u32, max: u32) -> Option<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|number| (min..=max).contains(number))
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" | "yes" | "true" | "1" => Some(true),
+        "off" | "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_loading_works() {
+        let file = "# comment\n\nscale = 3\nmusic = off\nlanguage = en\nvolume-boost = 11\n";
+        let settings = Settings::load(file.as_bytes()).unwrap();
+        assert_eq!(settings.scale, 3);
+        assert!(!settings.music);
+        assert_eq!(settings.language, "en");
+        assert_eq!(settings.music_volume, Settings::default().music_volume);
+        assert_eq!(settings.unknown("volume-boost"), Some("11"));
+        assert_eq!(settings.music_gain(), 0.0);
+    }
+
+    #[test]
+    fn test_loading_errors_are_reported() {
+        assert_eq!(
+            Settings::load("scale = 0".as_bytes()),
+            Err("Line 1: invalid scale value \"0\"".to_string())
+        );
+        assert_eq!(
+            Settings::load("\nmusic = loud".as_bytes()),
+            Err("Line 2: invalid music value \"loud\"".to_string())
+        );
+        assert_eq!(
+            Settings::load("language = xx".as_bytes()),
+            Err("Line 1: invalid language value \"xx\"".to_string())
+        );
+        assert_eq!(
+            Settings::load("scale".as_bytes()),
+            Err("Line 1: expected key = value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_saving_keeps_unknown_settings() {
+        let file = "future-setting = 1\nsound-volume = 40\ngame-dir = /games/polanie\n";
+        let mut settings = Settings::load(file.as_bytes()).unwrap();
+        settings.skip_intro = true;
+        let mut saved = Vec::new();
+        settings.save(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+        assert!(saved.contains("future-setting = 1\n"));
+        assert!(saved.contains("skip-intro = on\n"));
+        assert!(saved.contains("game-dir = /games/polanie\n"));
+        assert_eq!(Settings::load(saved.as_bytes()).unwrap(), settings);
+    }
+}
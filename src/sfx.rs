@@ -0,0 +1,169 @@
+//! Sound effect catalog for [sound.dat](../sounddat/index.html).
+//!
+//! sound.dat doesn't contain any names, the original game refers to the sounds by their indices
+//! only. This module names the sounds whose purpose has been identified: every [`SoundGroup`]
+//! covers a contiguous range of sound.dat entries (a group can have several variants the game
+//! chooses from). The other sounds are [`Sound::Unidentified`], known by their indices alone.
+//!
+//! # Identified sounds
+//!
+//! The catalog describes sound.dat coming from the CD version of Polanie (183 sounds, see the
+//! [sound.dat module documentation](../sounddat/index.html)). So far only `Click` (sound 0, the
+//! one played in the main menu) has been identified. The unit acknowledgements and the attack,
+//! death and building sounds aren't catalogued yet. A group is added here once the purpose of its
+//! sounds has been confirmed in the original game, guessed names don't belong in the catalog.
+//!
+//! # Example
+//!
+//! `openpol-extract-audio` prints the catalog names next to the sound sizes when listing the
+//! contents of sound.dat, `unidentified` for the sounds not in the catalog.
+
+/// The number of sounds in sound.dat coming from the CD version of the game.
+pub const CD_SOUNDS: usize = 183;
+
+/// A group of sound.dat entries having the same, identified, purpose.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SoundGroup {
+    /// The click of the menus.
+    Click,
+}
+
+/// All groups, in sound.dat order.
+pub const GROUPS: [SoundGroup; 1] = [SoundGroup::Click];
+
+impl SoundGroup {
+    /// The name of the group, as used in tools output.
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundGroup::Click => "click",
+        }
+    }
+
+    /// The sound.dat index of the group's first sound.
+    pub fn first(self) -> usize {
+        match self {
+            SoundGroup::Click => 0,
+        }
+    }
+
+    /// The number of sound.dat entries (variants) in the group.
+    pub fn variants(self) -> usize {
+        match self {
+            SoundGroup::Click => 1,
+        }
+    }
+
+    /// The sound.dat index of the group's `variant` (0-based). Panics if the variant is out of
+    /// range.
+    pub fn index(self, variant: usize) -> usize {
+        assert!(variant < self.variants());
+        self.first() + variant
+    }
+}
+
+/// A sound.dat entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Sound {
+    Identified {
+        group: SoundGroup,
+        variant: usize,
+    },
+    /// A sound whose purpose isn't known yet, with its sound.dat index.
+    Unidentified(usize),
+}
+
+/// Find out what a sound.dat `index` is. Returns `None` for indices beyond the catalog.
+pub fn lookup(index: usize) -> Option<Sound> {
+    if index >= CD_SOUNDS {
+        return None;
+    }
+    Some(
+        GROUPS
+            .iter()
+            .find(|group| (group.first()..group.first() + group.variants()).contains(&index))
+            .map_or(Sound::Unidentified(index), |group| Sound::Identified {
+                group: *group,
+                variant: index - group.first(),
+            }),
+    )
+}
+
+/// Get a human readable name of a sound, for example `click` or `unidentified`. Groups with
+/// several variants have the variant appended (`NAME-VARIANT`). Returns `None` for indices
+/// beyond the catalog.
+pub fn name(index: usize) -> Option<String> {
+    lookup(index).map(|sound| match sound {
+        Sound::Identified { group, .. } if group.variants() == 1 => group.name().to_string(),
+        Sound::Identified { group, variant } => format!("{}-{}", group.name(), variant),
+        Sound::Unidentified(_) => "unidentified".to_string(),
+    })
+}
+
+/// The number of sound.dat entries up to the last identified one.
+pub fn identified_sounds() -> usize {
+    GROUPS
+        .iter()
+        .map(|group| group.first() + group.variants())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Check that a sound.dat with `sounds` sounds has all the identified sounds (see
+/// `identified_sounds()`), the game plays only those. Files with fewer sounds than the CD version
+/// are fine as long as they have them.
+pub fn check_sound_count(sounds: usize) -> Result<(), String> {
+    let expected = identified_sounds();
+    if sounds < expected {
+        Err(format!(
+            "sound.dat contains {sounds} sounds, at least {expected} are expected"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sfx::{
+        check_sound_count, identified_sounds, lookup, name, Sound, SoundGroup, CD_SOUNDS, GROUPS,
+    };
+
+    #[test]
+    fn test_groups_fit_cd_sounds() {
+        let mut end = 0;
+        for group in GROUPS.iter() {
+            assert!(group.first() >= end);
+            end = group.first() + group.variants();
+            for variant in 0..group.variants() {
+                assert_eq!(
+                    lookup(group.index(variant)),
+                    Some(Sound::Identified {
+                        group: *group,
+                        variant
+                    })
+                );
+            }
+        }
+        assert!(end <= CD_SOUNDS);
+        assert_eq!(identified_sounds(), end);
+        assert_eq!(lookup(CD_SOUNDS), None);
+    }
+
+    #[test]
+    fn test_names_work() {
+        assert_eq!(SoundGroup::Click.index(0), 0);
+        assert_eq!(name(0).unwrap(), "click");
+        assert_eq!(lookup(6), Some(Sound::Unidentified(6)));
+        assert_eq!(name(6).unwrap(), "unidentified");
+        assert_eq!(name(CD_SOUNDS), None);
+    }
+
+    #[test]
+    fn test_check_sound_count_works() {
+        assert!(check_sound_count(CD_SOUNDS).is_ok());
+        assert!(check_sound_count(CD_SOUNDS + 1).is_ok());
+        // Only the click is needed so far.
+        assert!(check_sound_count(1).is_ok());
+        assert!(check_sound_count(0).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::ui::{activated_with_keyboard, Response, Skin, Widget};
+use sdl2::mouse::MouseButton;
+
+/// A rectangular clickable area of the screen. The button doesn't draw anything by itself, the
+/// graphics usually come from a background image, but it can highlight itself when hovered.
+pub struct Button {
+    rect: Rect,
+    hovered: bool,
+}
+
+impl Button {
+    pub fn new(rect: Rect) -> Button {
+        Button {
+            rect,
+            hovered: false,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Is the mouse cursor over the button?
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    /// Process a frame of input. Returns true if the button has been clicked.
+    pub fn update(&mut self, input: &Input) -> bool {
+        self.hovered = self
+            .rect
+            .contains(input.mouse_position.x, input.mouse_position.y);
+        self.hovered && input.mouse_button_pressed(MouseButton::Left)
+    }
+
+    /// Draw an outline around the button if it's hovered.
+    pub fn draw_highlight(&self, image: &mut Image13h, color: u8) {
+        if self.hovered {
+            image.draw_rect(&self.rect, color);
+        }
+    }
+}
+
+impl Widget for Button {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        input: &Input,
+        _dt: u32,
+        _focused: bool,
+    ) -> Option<Response> {
+        if Button::update(self, input) {
+            Some(Response::Clicked)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, _focused: bool) {
+        self.draw_highlight(image, skin.colors.highlight);
+    }
+}
+
+/// A list of buttons, each one associated with a value (usually an action) that's reported when
+/// the button is clicked.
+pub struct Menu<T: Copy> {
+    items: Vec<(Button, T)>,
+}
+
+impl<T: Copy> Menu<T> {
+    pub fn new(items: &[(Rect, T)]) -> Menu<T> {
+        Menu {
+            items: items
+                .iter()
+                .map(|(rect, value)| (Button::new(*rect), *value))
+                .collect(),
+        }
+    }
+
+    /// Process a frame of input. Returns the value of the item clicked, if any.
+    pub fn update(&mut self, input: &Input) -> Option<T> {
+        let mut clicked = None;
+        for (button, value) in &mut self.items {
+            if button.update(input) {
+                clicked = Some(*value);
+            }
+        }
+        clicked
+    }
+
+    /// The value of the item under the mouse cursor.
+    pub fn hovered(&self) -> Option<T> {
+        self.items
+            .iter()
+            .find(|(button, _)| button.is_hovered())
+            .map(|(_, value)| *value)
+    }
+
+    /// Highlight the hovered item.
+    pub fn draw(&self, image: &mut Image13h, highlight_color: u8) {
+        for (button, _) in &self.items {
+            button.draw_highlight(image, highlight_color);
+        }
+    }
+}
+
+/// Draw `text` centered in `rect`.
+fn draw_centered_text(skin: &Skin, image: &mut Image13h, rect: &Rect, text: &str) {
+    let width = skin.fontdat.text_width(text);
+    let x = rect.left as isize + (rect.width as isize - width as isize) / 2;
+    let y = rect.top as isize + (rect.height as isize - fontdat::CHARACTER_HEIGHT as isize) / 2;
+    skin.fontdat.draw_text(image, text, x, y);
+}
+
+/// A button drawn with one of the graf.dat `second_button` sprites, with an optional label on
+/// top. It can be activated with the mouse or, when focused, with Return or Space.
+pub struct ImageButton {
+    button: Button,
+    sprite: usize,
+    label: String,
+}
+
+impl ImageButton {
+    /// Create a button occupying `rect`, drawn with the `sprite`-th second button sprite. The
+    /// sprite is clipped to the rect.
+    pub fn new(rect: Rect, sprite: usize, label: &str) -> ImageButton {
+        ImageButton {
+            button: Button::new(rect),
+            sprite,
+            label: label.to_string(),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label = label.to_string();
+    }
+}
+
+impl Widget for ImageButton {
+    fn rect(&self) -> Rect {
+        self.button.rect()
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        input: &Input,
+        _dt: u32,
+        focused: bool,
+    ) -> Option<Response> {
+        let clicked = self.button.update(input);
+        if clicked || activated_with_keyboard(input, focused) {
+            Some(Response::Clicked)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, focused: bool) {
+        let rect = self.button.rect();
+        image.blit_within(
+            skin.grafdat.second_button(self.sprite),
+            rect.left as isize,
+            rect.top as isize,
+            &rect,
+        );
+        draw_centered_text(skin, image, &rect, &self.label);
+        if focused {
+            image.draw_rect(&rect, skin.colors.focus);
+        } else {
+            self.button.draw_highlight(image, skin.colors.highlight);
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+/// The second button sprites used to draw unchecked and checked toggles.
+const TOGGLE_SPRITES: [usize; 2] = [2, 3];
+
+/// The space between a toggle's box and its label, in pixels.
+const TOGGLE_LABEL_SPACING: usize = 4;
+
+/// An on/off switch: a box followed by a label. Clicking anywhere in its rect (or pressing
+/// Return or Space when it's focused) flips it.
+pub struct Toggle {
+    button: Button,
+    label: String,
+    checked: bool,
+}
+
+impl Toggle {
+    pub fn new(rect: Rect, label: &str) -> Toggle {
+        Toggle {
+            button: Button::new(rect),
+            label: label.to_string(),
+            checked: false,
+        }
+    }
+
+    pub fn is_checked(&self) -> bool {
+        self.checked
+    }
+
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = checked;
+    }
+}
+
+impl Widget for Toggle {
+    fn rect(&self) -> Rect {
+        self.button.rect()
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        input: &Input,
+        _dt: u32,
+        focused: bool,
+    ) -> Option<Response> {
+        let clicked = self.button.update(input);
+        if clicked || activated_with_keyboard(input, focused) {
+            self.checked = !self.checked;
+            Some(Response::Changed)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, focused: bool) {
+        let rect = self.button.rect();
+        let sprite = skin
+            .grafdat
+            .second_button(TOGGLE_SPRITES[self.checked as usize]);
+        image.blit_within(sprite, rect.left as isize, rect.top as isize, &rect);
+        skin.fontdat.draw_text(
+            image,
+            &self.label,
+            (rect.left + sprite.width() + TOGGLE_LABEL_SPACING) as isize,
+            rect.top as isize,
+        );
+        if focused {
+            image.draw_rect(&rect, skin.colors.focus);
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::Fontdat;
+    use crate::image13h::{Image13h, Rect};
+    use crate::input::{Input, MousePosition};
+    use crate::ui::button::{ImageButton, Menu, Toggle};
+    use crate::ui::{Response, Widget};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    #[test]
+    fn test_menu_works() {
+        let mut menu = Menu::new(&[
+            (Rect::from_ranges(0..10, 0..10), 'a'),
+            (Rect::from_ranges(0..10, 20..30), 'b'),
+        ]);
+        let mut input = Input::idle(MousePosition::new(5, 25));
+        assert_eq!(menu.update(&input), None);
+        assert_eq!(menu.hovered(), Some('b'));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        assert_eq!(menu.update(&input), Some('b'));
+
+        let mut image = Image13h::empty(12, 32);
+        menu.draw(&mut image, 4);
+        assert_eq!(image.line(20)[0..11], [4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 0]);
+        assert_eq!(image.line(0)[0], 0);
+
+        let mut input = Input::idle(MousePosition::new(15, 15));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        assert_eq!(menu.update(&input), None);
+        assert_eq!(menu.hovered(), None);
+    }
+
+    #[test]
+    fn test_keyboard_activation_works() {
+        let fontdat = Fontdat::empty();
+        let mut button = ImageButton::new(Rect::from_ranges(0..10, 0..10), 0, "ok");
+        let mut toggle = Toggle::new(Rect::from_ranges(0..10, 20..30), "sound");
+        let mut input = Input::idle(MousePosition::new(50, 50));
+        input.keys_typed.push(Scancode::Return);
+        assert_eq!(
+            Widget::update(&mut button, &fontdat, &input, 0, false),
+            None
+        );
+        assert_eq!(
+            Widget::update(&mut button, &fontdat, &input, 0, true),
+            Some(Response::Clicked)
+        );
+        assert_eq!(
+            Widget::update(&mut toggle, &fontdat, &input, 0, true),
+            Some(Response::Changed)
+        );
+        assert!(toggle.is_checked());
+        assert_eq!(
+            Widget::update(&mut toggle, &fontdat, &input, 0, false),
+            None
+        );
+        assert!(toggle.is_checked());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::ui::{activated_with_keyboard, Response, Scrollbar, Skin, Widget};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use std::cmp;
+
+/// The width of a list box's scrollbar in pixels.
+pub const SCROLLBAR_WIDTH: usize = 6;
+
+/// A scrollable list of lines of text, at most one of them selected. Clicking an item selects
+/// it, clicking the selected item again (or pressing Return or Space when the list box is
+/// focused) activates it. Up, Down, Home, End, Page Up and Page Down move the selection when
+/// the list box is focused.
+pub struct ListBox {
+    rect: Rect,
+    items: Vec<String>,
+    selected: Option<usize>,
+    scrollbar: Scrollbar,
+}
+
+impl ListBox {
+    pub fn new(rect: Rect, items: Vec<String>) -> ListBox {
+        assert!(rect.width > SCROLLBAR_WIDTH);
+        let mut scrollbar = Scrollbar::new(
+            Rect::from_ranges(
+                rect.beyond_right() - SCROLLBAR_WIDTH..rect.beyond_right(),
+                rect.top..rect.beyond_bottom(),
+            ),
+            items.len(),
+            rect.height / fontdat::CHARACTER_HEIGHT,
+        );
+        scrollbar.set_wheel_area(rect);
+        ListBox {
+            rect,
+            items,
+            selected: None,
+            scrollbar,
+        }
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Replace the items, clearing the selection.
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.scrollbar.set_content_length(items.len());
+        self.items = items;
+        self.selected = None;
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Select an item (clamped to the valid indices) and scroll to make it visible.
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index
+            .filter(|_| !self.items.is_empty())
+            .map(|index| cmp::min(index, self.items.len() - 1));
+        if let Some(selected) = self.selected {
+            let offset = self.scrollbar.offset();
+            let rows = self.rows();
+            if selected < offset {
+                self.scrollbar.set_offset(selected);
+            } else if selected >= offset + rows {
+                self.scrollbar.set_offset(selected + 1 - rows);
+            }
+        }
+    }
+
+    /// The number of items visible at a time.
+    fn rows(&self) -> usize {
+        cmp::max(1, self.rect.height / fontdat::CHARACTER_HEIGHT)
+    }
+
+    /// The area taken by the items (everything except for the scrollbar).
+    fn items_rect(&self) -> Rect {
+        Rect::from_ranges(
+            self.rect.left..self.rect.beyond_right() - SCROLLBAR_WIDTH,
+            self.rect.top..self.rect.beyond_bottom(),
+        )
+    }
+}
+
+impl Widget for ListBox {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        fontdat: &Fontdat,
+        input: &Input,
+        dt: u32,
+        focused: bool,
+    ) -> Option<Response> {
+        let previous = self.selected;
+        let mut response = None;
+        self.scrollbar.update(fontdat, input, dt, false);
+
+        let position = input.mouse_position;
+        if input.mouse_button_pressed(MouseButton::Left)
+            && self.items_rect().contains(position.x, position.y)
+        {
+            let index =
+                self.scrollbar.offset() + (position.y - self.rect.top) / fontdat::CHARACTER_HEIGHT;
+            if index < self.items.len() {
+                if self.selected == Some(index) {
+                    response = Some(Response::Clicked);
+                }
+                self.selected = Some(index);
+            }
+        }
+        if focused && !self.items.is_empty() {
+            let last = self.items.len() - 1;
+            let rows = self.rows();
+            for scancode in &input.keys_typed {
+                let selected = match (scancode, self.selected) {
+                    (Scancode::Up, Some(selected)) => selected.saturating_sub(1),
+                    (Scancode::Down, Some(selected)) => selected + 1,
+                    (Scancode::PageUp, Some(selected)) => selected.saturating_sub(rows),
+                    (Scancode::PageDown, Some(selected)) => selected + rows,
+                    (Scancode::Up, None) | (Scancode::Down, None) | (Scancode::Home, _) => 0,
+                    (Scancode::End, _) => last,
+                    _ => continue,
+                };
+                self.select(Some(selected));
+            }
+            if self.selected.is_some() && activated_with_keyboard(input, focused) {
+                response = Some(Response::Clicked);
+            }
+        }
+        if self.selected != previous {
+            Some(Response::Changed)
+        } else {
+            response
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, focused: bool) {
+        let items_rect = self.items_rect();
+        let offset = self.scrollbar.offset();
+        for (row, item) in self.items[offset..].iter().take(self.rows()).enumerate() {
+            let y = self.rect.top + row * fontdat::CHARACTER_HEIGHT;
+            if self.selected == Some(offset + row) {
+                image.fill_rect(
+                    &Rect::from_ranges(
+                        items_rect.left..items_rect.beyond_right(),
+                        y..y + fontdat::CHARACTER_HEIGHT,
+                    ),
+                    skin.colors.selection,
+                );
+            }
+            skin.fontdat
+                .draw_text(image, item, self.rect.left as isize, y as isize);
+        }
+        self.scrollbar.draw(skin, image, false);
+        if focused {
+            image.draw_rect(&items_rect, skin.colors.focus);
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::Fontdat;
+    use crate::fontdat::CHARACTER_HEIGHT;
+    use crate::image13h::Rect;
+    use crate::input::{Input, MousePosition};
+    use crate::ui::listbox::ListBox;
+    use crate::ui::{Response, Widget};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    fn list_box() -> ListBox {
+        let items = (0..10).map(|i| format!("item {}", i)).collect();
+        ListBox::new(Rect::from_ranges(0..100, 0..3 * CHARACTER_HEIGHT), items)
+    }
+
+    fn keys(keys: &[Scancode]) -> Input {
+        let mut input = Input::idle(MousePosition::new(200, 0));
+        input.keys_typed = keys.to_vec();
+        input
+    }
+
+    #[test]
+    fn test_mouse_selection_works() {
+        let fontdat = Fontdat::empty();
+        let mut list = list_box();
+        let mut input = Input::idle(MousePosition::new(10, CHARACTER_HEIGHT + 1));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        assert_eq!(
+            list.update(&fontdat, &input, 0, false),
+            Some(Response::Changed)
+        );
+        assert_eq!(list.selected(), Some(1));
+        assert_eq!(
+            list.update(&fontdat, &input, 0, false),
+            Some(Response::Clicked)
+        );
+
+        // Scroll with the wheel and click the same row.
+        let mut wheel = Input::idle(MousePosition::new(10, 0));
+        wheel.wheel = -2;
+        list.update(&fontdat, &wheel, 0, false);
+        assert_eq!(
+            list.update(&fontdat, &input, 0, false),
+            Some(Response::Changed)
+        );
+        assert_eq!(list.selected(), Some(3));
+    }
+
+    #[test]
+    fn test_keyboard_selection_works() {
+        let fontdat = Fontdat::empty();
+        let mut list = list_box();
+        assert_eq!(
+            list.update(&fontdat, &keys(&[Scancode::Down]), 0, false),
+            None
+        );
+        assert_eq!(
+            list.update(&fontdat, &keys(&[Scancode::Down, Scancode::Down]), 0, true),
+            Some(Response::Changed)
+        );
+        assert_eq!(list.selected(), Some(1));
+        list.update(&fontdat, &keys(&[Scancode::End]), 0, true);
+        assert_eq!(list.selected(), Some(9));
+        assert_eq!(list.scrollbar.offset(), 7);
+        list.update(&fontdat, &keys(&[Scancode::PageUp, Scancode::Up]), 0, true);
+        assert_eq!(list.selected(), Some(5));
+        assert_eq!(list.scrollbar.offset(), 5);
+        assert_eq!(
+            list.update(&fontdat, &keys(&[Scancode::Return]), 0, true),
+            Some(Response::Clicked)
+        );
+
+        list.set_items(vec![]);
+        assert_eq!(
+            list.update(&fontdat, &keys(&[Scancode::Down]), 0, true),
+            None
+        );
+        assert_eq!(list.selected(), None);
+    }
+}
@@ -0,0 +1,301 @@
+//! User interface widgets.
+//!
+//! Widgets are retained: they're created once, kept in a [`Ui`](struct.Ui.html) and fed every
+//! frame of input. The `Ui` routes the input, keeps track of the keyboard focus (Tab and
+//! Shift+Tab move it between the focusable widgets, clicking a widget focuses it) and draws the
+//! widgets on an `Image13h` using graf.dat sprites and `Fontdat` text, see
+//! [`Skin`](struct.Skin.html).
+
+pub mod button;
+pub mod listbox;
+pub mod panel;
+pub mod slider;
+pub mod textfield;
+
+pub use crate::ui::button::{Button, ImageButton, Menu, Toggle};
+pub use crate::ui::listbox::ListBox;
+pub use crate::ui::panel::{Label, Panel};
+pub use crate::ui::slider::{Scrollbar, Slider};
+pub use crate::ui::textfield::TextField;
+
+use crate::fontdat::Fontdat;
+use crate::grafdat::Grafdat;
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use std::any::Any;
+
+/// What happened to a widget during a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Response {
+    /// The widget has been activated (clicked or, when focused, Return or Space was pressed).
+    Clicked,
+    /// The widget's value has been changed.
+    Changed,
+}
+
+/// The colors widgets use on top of the sprites.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Colors {
+    /// Outlines of hovered widgets.
+    pub highlight: u8,
+    /// Outlines of the focused widget.
+    pub focus: u8,
+    /// Selected list box items.
+    pub selection: u8,
+    /// Panel outlines.
+    pub shadow: u8,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            highlight: 15,
+            focus: 14,
+            selection: 1,
+            shadow: 8,
+        }
+    }
+}
+
+/// Everything widgets need to draw themselves.
+pub struct Skin<'a> {
+    pub grafdat: &'a Grafdat,
+    pub fontdat: &'a Fontdat,
+    pub colors: Colors,
+}
+
+impl<'a> Skin<'a> {
+    pub fn new(grafdat: &'a Grafdat, fontdat: &'a Fontdat) -> Skin<'a> {
+        Skin {
+            grafdat,
+            fontdat,
+            colors: Colors::default(),
+        }
+    }
+}
+
+/// Access to a widget as `Any`, so that `Ui` can hand out widgets of concrete types. Every widget
+/// gets it from the blanket implementation.
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Widget: AsAny {
+    /// The area of the screen occupied by the widget.
+    fn rect(&self) -> Rect;
+
+    /// Process a frame of input. `fontdat` is the font the widget's text is drawn with, `dt` is
+    /// the time since the previous frame in milliseconds, `focused` tells if the widget has the
+    /// keyboard focus.
+    fn update(
+        &mut self,
+        fontdat: &Fontdat,
+        input: &Input,
+        dt: u32,
+        focused: bool,
+    ) -> Option<Response>;
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, focused: bool);
+
+    /// Can the widget receive the keyboard focus?
+    fn focusable(&self) -> bool {
+        false
+    }
+}
+
+/// Identifies a widget added to a `Ui`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct WidgetId(usize);
+
+/// A collection of widgets. Widgets are drawn in the order they were added, so later widgets
+/// end up on top.
+#[derive(Default)]
+pub struct Ui {
+    widgets: Vec<Box<dyn Widget>>,
+    focus: Option<usize>,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui::default()
+    }
+
+    pub fn add<W: Widget>(&mut self, widget: W) -> WidgetId {
+        self.widgets.push(Box::new(widget));
+        WidgetId(self.widgets.len() - 1)
+    }
+
+    /// Get a widget. Returns `None` if the widget is not a `W`.
+    pub fn get<W: Widget>(&self, id: WidgetId) -> Option<&W> {
+        // Dereferenced, as the box itself is `Any` too.
+        (*self.widgets[id.0]).as_any().downcast_ref()
+    }
+
+    /// Get a widget. Returns `None` if the widget is not a `W`.
+    pub fn get_mut<W: Widget>(&mut self, id: WidgetId) -> Option<&mut W> {
+        (*self.widgets[id.0]).as_any_mut().downcast_mut()
+    }
+
+    /// The widget with the keyboard focus.
+    pub fn focus(&self) -> Option<WidgetId> {
+        self.focus.map(WidgetId)
+    }
+
+    /// Move the keyboard focus. Widgets that can't be focused are ignored.
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        self.focus = id
+            .map(|id| id.0)
+            .filter(|index| self.widgets[*index].focusable());
+    }
+
+    /// Process a frame of input. Returns the responses of all the widgets that reported one, in
+    /// the order the widgets were added.
+    pub fn update(
+        &mut self,
+        fontdat: &Fontdat,
+        input: &Input,
+        dt: u32,
+    ) -> Vec<(WidgetId, Response)> {
+        if input.mouse_button_pressed(MouseButton::Left) {
+            let position = input.mouse_position;
+            self.focus = self.widgets.iter().rposition(|widget| {
+                widget.focusable() && widget.rect().contains(position.x, position.y)
+            });
+        }
+        for scancode in &input.keys_typed {
+            if *scancode == Scancode::Tab {
+                self.cycle_focus(input.modifiers.shift);
+            }
+        }
+
+        let focus = self.focus;
+        self.widgets
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, widget)| {
+                widget
+                    .update(fontdat, input, dt, focus == Some(index))
+                    .map(|response| (WidgetId(index), response))
+            })
+            .collect()
+    }
+
+    pub fn draw(&self, skin: &Skin, image: &mut Image13h) {
+        for (index, widget) in self.widgets.iter().enumerate() {
+            widget.draw(skin, image, self.focus == Some(index));
+        }
+    }
+
+    /// Move the focus to the next (or previous) focusable widget, wrapping around.
+    fn cycle_focus(&mut self, backwards: bool) {
+        let len = self.widgets.len();
+        if len == 0 {
+            return;
+        }
+        let start = match (self.focus, backwards) {
+            (Some(focus), _) => focus,
+            (None, false) => len - 1,
+            (None, true) => 0,
+        };
+        self.focus = (1..=len)
+            .map(|step| {
+                if backwards {
+                    (start + len - step) % len
+                } else {
+                    (start + step) % len
+                }
+            })
+            .find(|index| self.widgets[*index].focusable());
+    }
+}
+
+/// Is the widget being activated with the keyboard?
+fn activated_with_keyboard(input: &Input, focused: bool) -> bool {
+    focused
+        && input
+            .keys_typed
+            .iter()
+            .any(|scancode| *scancode == Scancode::Return || *scancode == Scancode::Space)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::Fontdat;
+    use crate::image13h::Rect;
+    use crate::input::{Input, MousePosition};
+    use crate::ui::{Label, Response, TextField, Toggle, Ui};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    fn tab(shift: bool) -> Input {
+        let mut input = Input::idle(MousePosition::new(0, 0));
+        input.keys_typed.push(Scancode::Tab);
+        input.modifiers.shift = shift;
+        input
+    }
+
+    #[test]
+    fn test_focus_works() {
+        let fontdat = Fontdat::empty();
+        let mut ui = Ui::new();
+        let first = ui.add(Toggle::new(Rect::from_ranges(0..10, 0..10), "a"));
+        ui.add(Label::new(&fontdat, 0, 20, "label"));
+        let third = ui.add(TextField::new(0, 40, 50));
+        assert_eq!(ui.focus(), None);
+
+        ui.update(&fontdat, &tab(false), 0);
+        assert_eq!(ui.focus(), Some(first));
+        ui.update(&fontdat, &tab(false), 0);
+        assert_eq!(ui.focus(), Some(third));
+        ui.update(&fontdat, &tab(false), 0);
+        assert_eq!(ui.focus(), Some(first));
+        ui.update(&fontdat, &tab(true), 0);
+        assert_eq!(ui.focus(), Some(third));
+        assert!(ui.get::<TextField>(third).unwrap().is_focused());
+
+        let mut input = Input::idle(MousePosition::new(5, 5));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        assert_eq!(
+            ui.update(&fontdat, &input, 0),
+            vec![(first, Response::Changed)]
+        );
+        assert_eq!(ui.focus(), Some(first));
+        assert!(ui.get::<Toggle>(first).unwrap().is_checked());
+        assert!(!ui.get::<TextField>(third).unwrap().is_focused());
+        assert!(ui.get::<Label>(first).is_none());
+
+        let mut input = Input::idle(MousePosition::new(5, 25));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        ui.update(&fontdat, &input, 0);
+        assert_eq!(ui.focus(), None);
+    }
+
+    #[test]
+    fn test_typing_into_focused_field_works() {
+        let fontdat = Fontdat::empty();
+        let mut ui = Ui::new();
+        let field = ui.add(TextField::new(0, 0, 100));
+        ui.set_focus(Some(field));
+        let mut input = Input::idle(MousePosition::new(0, 50));
+        input.text = "abc".to_string();
+        assert_eq!(
+            ui.update(&fontdat, &input, 0),
+            vec![(field, Response::Changed)]
+        );
+        assert_eq!(ui.get::<TextField>(field).unwrap().text(), "abc");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::ui::{Response, Skin, Widget};
+
+/// The wood sprite panels are tiled with.
+const PANEL_WOOD: usize = 1;
+
+/// A wooden background for other widgets. Panels don't react to input.
+pub struct Panel {
+    rect: Rect,
+}
+
+impl Panel {
+    pub fn new(rect: Rect) -> Panel {
+        Panel { rect }
+    }
+}
+
+impl Widget for Panel {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        _input: &Input,
+        _dt: u32,
+        _focused: bool,
+    ) -> Option<Response> {
+        None
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, _focused: bool) {
+        image.tile(skin.grafdat.wood(PANEL_WOOD), &self.rect);
+        image.draw_rect(&self.rect, skin.colors.shadow);
+    }
+}
+
+/// A line of text.
+pub struct Label {
+    x: usize,
+    y: usize,
+    text: String,
+    /// The width of the text drawn with the font the label was given.
+    width: usize,
+}
+
+impl Label {
+    /// Create a label drawn with `fontdat`.
+    pub fn new(fontdat: &Fontdat, x: usize, y: usize, text: &str) -> Label {
+        Label {
+            x,
+            y,
+            text: text.to_string(),
+            width: fontdat.text_width(text),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, fontdat: &Fontdat, text: &str) {
+        self.text = text.to_string();
+        self.width = fontdat.text_width(text);
+    }
+}
+
+impl Widget for Label {
+    fn rect(&self) -> Rect {
+        Rect::from_ranges(
+            self.x..self.x + self.width,
+            self.y..self.y + fontdat::CHARACTER_HEIGHT,
+        )
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        _input: &Input,
+        _dt: u32,
+        _focused: bool,
+    ) -> Option<Response> {
+        None
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, _focused: bool) {
+        skin.fontdat
+            .draw_text(image, &self.text, self.x as isize, self.y as isize);
+    }
+}
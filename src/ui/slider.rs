@@ -0,0 +1,358 @@
+use crate::fontdat::Fontdat;
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::ui::{Response, Skin, Widget};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use std::cmp;
+
+/// The width of a slider's knob in pixels.
+pub const KNOB_WIDTH: usize = 5;
+
+/// The wood sprite slider and scrollbar tracks are tiled with.
+const TRACK_WOOD: usize = 0;
+
+/// The second button sprite slider knobs and scrollbar thumbs are cut from.
+const KNOB_SPRITE: usize = 2;
+
+/// A horizontal slider choosing an integer from `min..=max`. The value can be changed by
+/// clicking or dragging along the slider or, when it's focused, with the Left and Right keys.
+pub struct Slider {
+    rect: Rect,
+    min: i32,
+    max: i32,
+    value: i32,
+    dragging: bool,
+}
+
+impl Slider {
+    pub fn new(rect: Rect, min: i32, max: i32, value: i32) -> Slider {
+        assert!(min <= max);
+        assert!(rect.width >= KNOB_WIDTH);
+        Slider {
+            rect,
+            min,
+            max,
+            value: value.clamp(min, max),
+            dragging: false,
+        }
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// Set the value, clamped to the slider's range.
+    pub fn set_value(&mut self, value: i32) {
+        self.value = value.clamp(self.min, self.max);
+    }
+
+    /// The number of pixels the knob can travel.
+    fn travel(&self) -> usize {
+        self.rect.width - KNOB_WIDTH
+    }
+
+    /// The knob's offset from the left edge of the slider.
+    fn knob_offset(&self) -> usize {
+        if self.max == self.min {
+            return 0;
+        }
+        ((self.value - self.min) as i64 * self.travel() as i64 / (self.max - self.min) as i64)
+            as usize
+    }
+
+    /// The value corresponding to the mouse cursor being at `x`.
+    fn value_at(&self, x: usize) -> i32 {
+        let travel = self.travel() as i64;
+        if travel == 0 {
+            return self.min;
+        }
+        let offset = (x as i64 - (self.rect.left + KNOB_WIDTH / 2) as i64).clamp(0, travel);
+        // Round to the nearest value.
+        self.min + ((offset * (self.max - self.min) as i64 + travel / 2) / travel) as i32
+    }
+}
+
+impl Widget for Slider {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        input: &Input,
+        _dt: u32,
+        focused: bool,
+    ) -> Option<Response> {
+        let previous = self.value;
+        let position = input.mouse_position;
+        if input.mouse_button_pressed(MouseButton::Left) {
+            self.dragging = self.rect.contains(position.x, position.y);
+        } else if !input.mouse_buttons.is_held(MouseButton::Left) {
+            self.dragging = false;
+        }
+        if self.dragging {
+            self.value = self.value_at(position.x);
+        }
+        if focused {
+            for scancode in &input.keys_typed {
+                match scancode {
+                    Scancode::Left => self.set_value(self.value - 1),
+                    Scancode::Right => self.set_value(self.value + 1),
+                    Scancode::Home => self.value = self.min,
+                    Scancode::End => self.value = self.max,
+                    _ => (),
+                }
+            }
+        }
+        if self.value != previous {
+            Some(Response::Changed)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, focused: bool) {
+        image.tile(skin.grafdat.wood(TRACK_WOOD), &self.rect);
+        let knob = self.rect.left + self.knob_offset();
+        draw_knob(
+            skin,
+            image,
+            &Rect::from_ranges(
+                knob..knob + KNOB_WIDTH,
+                self.rect.top..self.rect.beyond_bottom(),
+            ),
+        );
+        if focused {
+            image.draw_rect(&self.rect, skin.colors.focus);
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+/// The smallest height of a scrollbar's thumb in pixels.
+pub const MINIMUM_THUMB_HEIGHT: usize = 4;
+
+/// A vertical scrollbar for content `content_length` units long, of which `visible_length`
+/// units are visible at a time. The offset changes with the mouse wheel (when the mouse cursor
+/// is over `wheel_area`), by dragging the thumb and by clicking the track above or below the
+/// thumb (scrolling by a page).
+pub struct Scrollbar {
+    rect: Rect,
+    wheel_area: Rect,
+    content_length: usize,
+    visible_length: usize,
+    offset: usize,
+    /// Where the thumb was grabbed, relative to its top, while it's being dragged.
+    grab: Option<usize>,
+}
+
+impl Scrollbar {
+    pub fn new(rect: Rect, content_length: usize, visible_length: usize) -> Scrollbar {
+        Scrollbar {
+            rect,
+            wheel_area: rect,
+            content_length,
+            visible_length,
+            offset: 0,
+            grab: None,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Set the offset, clamped so that the visible part doesn't go past the end of the content.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = cmp::min(offset, self.max_offset());
+    }
+
+    pub fn set_content_length(&mut self, content_length: usize) {
+        self.content_length = content_length;
+        self.set_offset(self.offset);
+    }
+
+    /// Scroll with the mouse wheel when the mouse cursor is anywhere in `area`, not only over
+    /// the scrollbar itself.
+    pub fn set_wheel_area(&mut self, area: Rect) {
+        self.wheel_area = area;
+    }
+
+    fn max_offset(&self) -> usize {
+        self.content_length.saturating_sub(self.visible_length)
+    }
+
+    /// The thumb's (top, height), relative to the top of the scrollbar.
+    fn thumb(&self) -> (usize, usize) {
+        let track = self.rect.height;
+        if self.content_length <= self.visible_length {
+            return (0, track);
+        }
+        let height = cmp::min(
+            track,
+            cmp::max(
+                MINIMUM_THUMB_HEIGHT,
+                track * self.visible_length / self.content_length,
+            ),
+        );
+        let top = (track - height) * self.offset / self.max_offset();
+        (top, height)
+    }
+}
+
+impl Widget for Scrollbar {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        _fontdat: &Fontdat,
+        input: &Input,
+        _dt: u32,
+        _focused: bool,
+    ) -> Option<Response> {
+        let previous = self.offset;
+        let position = input.mouse_position;
+        let (thumb_top, thumb_height) = self.thumb();
+        if input.mouse_button_pressed(MouseButton::Left)
+            && self.rect.contains(position.x, position.y)
+        {
+            let y = position.y - self.rect.top;
+            if y < thumb_top {
+                self.set_offset(self.offset.saturating_sub(self.visible_length));
+            } else if y >= thumb_top + thumb_height {
+                self.set_offset(self.offset + self.visible_length);
+            } else {
+                self.grab = Some(y - thumb_top);
+            }
+        } else if !input.mouse_buttons.is_held(MouseButton::Left) {
+            self.grab = None;
+        }
+        if let Some(grab) = self.grab {
+            let free = self.rect.height - thumb_height;
+            if free > 0 {
+                let top = (position.y as isize - self.rect.top as isize - grab as isize)
+                    .clamp(0, free as isize) as usize;
+                self.set_offset((top * self.max_offset() + free / 2) / free);
+            }
+        }
+        if input.wheel != 0 && self.wheel_area.contains(position.x, position.y) {
+            // Wheel up (positive values) scrolls towards the beginning.
+            let offset = self.offset as i64 - input.wheel as i64;
+            self.set_offset(cmp::max(0, offset) as usize);
+        }
+        if self.offset != previous {
+            Some(Response::Changed)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, _focused: bool) {
+        image.tile(skin.grafdat.wood(TRACK_WOOD), &self.rect);
+        let (top, height) = self.thumb();
+        let top = self.rect.top + top;
+        draw_knob(
+            skin,
+            image,
+            &Rect::from_ranges(self.rect.left..self.rect.beyond_right(), top..top + height),
+        );
+    }
+}
+
+/// Fill a slider knob or a scrollbar thumb with the knob sprite.
+fn draw_knob(skin: &Skin, image: &mut Image13h, rect: &Rect) {
+    image.tile(skin.grafdat.second_button(KNOB_SPRITE), rect);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::Fontdat;
+    use crate::image13h::Rect;
+    use crate::input::{Input, MousePosition};
+    use crate::ui::slider::{Scrollbar, Slider};
+    use crate::ui::{Response, Widget};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    fn click(x: usize, y: usize) -> Input {
+        let mut input = Input::idle(MousePosition::new(x, y));
+        input.mouse_buttons.pressed.insert(MouseButton::Left);
+        input.mouse_buttons.held.insert(MouseButton::Left);
+        input
+    }
+
+    #[test]
+    fn test_slider_works() {
+        let fontdat = Fontdat::empty();
+        // 105 pixels wide, so the knob travels 100 pixels, 10 pixels per value.
+        let mut slider = Slider::new(Rect::from_ranges(10..115, 0..8), 0, 10, 3);
+        assert_eq!(slider.value(), 3);
+
+        assert_eq!(
+            slider.update(&fontdat, &click(12 + 70, 4), 0, false),
+            Some(Response::Changed)
+        );
+        assert_eq!(slider.value(), 7);
+
+        // Dragging outside of the slider still works.
+        let mut input = Input::idle(MousePosition::new(500, 50));
+        input.mouse_buttons.held.insert(MouseButton::Left);
+        slider.update(&fontdat, &input, 0, false);
+        assert_eq!(slider.value(), 10);
+
+        // Releasing the button ends dragging.
+        let input = Input::idle(MousePosition::new(0, 4));
+        assert_eq!(slider.update(&fontdat, &input, 0, false), None);
+        assert_eq!(slider.value(), 10);
+
+        let mut input = Input::idle(MousePosition::new(0, 50));
+        input.keys_typed = vec![Scancode::Left, Scancode::Left];
+        slider.update(&fontdat, &input, 0, false);
+        assert_eq!(slider.value(), 10);
+        slider.update(&fontdat, &input, 0, true);
+        assert_eq!(slider.value(), 8);
+        input.keys_typed = vec![Scancode::Home, Scancode::Left];
+        slider.update(&fontdat, &input, 0, true);
+        assert_eq!(slider.value(), 0);
+    }
+
+    #[test]
+    fn test_scrollbar_works() {
+        let fontdat = Fontdat::empty();
+        let mut scrollbar = Scrollbar::new(Rect::from_ranges(0..8, 0..100), 40, 10);
+        assert_eq!(scrollbar.thumb(), (0, 25));
+
+        // Clicking below the thumb scrolls by a page.
+        scrollbar.update(&fontdat, &click(4, 50), 0, false);
+        assert_eq!(scrollbar.offset(), 10);
+        assert_eq!(scrollbar.thumb(), (25, 25));
+        scrollbar.update(&fontdat, &click(4, 10), 0, false);
+        assert_eq!(scrollbar.offset(), 0);
+
+        // Dragging the thumb.
+        scrollbar.update(&fontdat, &click(4, 10), 0, false);
+        let mut input = Input::idle(MousePosition::new(4, 10 + 75));
+        input.mouse_buttons.held.insert(MouseButton::Left);
+        scrollbar.update(&fontdat, &input, 0, false);
+        assert_eq!(scrollbar.offset(), 30);
+
+        let mut input = Input::idle(MousePosition::new(4, 10));
+        input.wheel = 2;
+        scrollbar.update(&fontdat, &input, 0, false);
+        assert_eq!(scrollbar.offset(), 28);
+        input.wheel = -100;
+        scrollbar.update(&fontdat, &input, 0, false);
+        assert_eq!(scrollbar.offset(), 30);
+
+        scrollbar.set_content_length(5);
+        assert_eq!(scrollbar.offset(), 0);
+        assert_eq!(scrollbar.thumb(), (0, 100));
+    }
+}
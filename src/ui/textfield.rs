@@ -0,0 +1,416 @@
+use crate::fontdat::{self, Fontdat};
+use crate::image13h::{Image13h, Rect};
+use crate::input::Input;
+use crate::ui::{Response, Skin, Widget};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use std::cmp;
+use std::ops;
+
+/// How long the caret stays visible (and then invisible) when blinking, in milliseconds.
+pub const CARET_BLINK_MS: u32 = 500;
+
+/// A single-line text field. Only characters `Fontdat` can draw are accepted and the text can't
+/// get wider than the field.
+pub struct TextField {
+    rect: Rect,
+    text: Vec<char>,
+    caret: usize,
+    /// Where the selection started, the selection spans from here to the caret.
+    anchor: Option<usize>,
+    focused: bool,
+    blink: u32,
+    pub caret_color: u8,
+    pub selection_color: u8,
+}
+
+impl TextField {
+    /// Create an empty, unfocused text field with its top left corner at (`x`, `y`) capable of
+    /// holding text up to `max_width` pixels wide.
+    pub fn new(x: usize, y: usize, max_width: usize) -> TextField {
+        TextField {
+            // One extra column is needed for the caret after the last character.
+            rect: Rect::from_ranges(x..x + max_width + 1, y..y + fontdat::CHARACTER_HEIGHT),
+            text: Vec::new(),
+            caret: 0,
+            anchor: None,
+            focused: false,
+            blink: 0,
+            caret_color: 15,
+            selection_color: 1,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// The maximum width of the text in pixels.
+    pub fn max_width(&self) -> usize {
+        self.rect.width - 1
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    /// Replace the text. Characters that can't be drawn with `fontdat` are dropped and the text
+    /// is truncated to fit in the field. The caret is placed at the end.
+    pub fn set_text(&mut self, fontdat: &Fontdat, text: &str) {
+        self.text.clear();
+        self.caret = 0;
+        self.anchor = None;
+        self.insert(fontdat, text);
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.anchor = None;
+        self.blink = 0;
+    }
+
+    /// The caret position (the number of characters before it).
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// The selected characters' range, `None` if nothing is selected.
+    pub fn selection(&self) -> Option<ops::Range<usize>> {
+        match self.anchor {
+            Some(anchor) if anchor != self.caret => {
+                Some(cmp::min(anchor, self.caret)..cmp::max(anchor, self.caret))
+            }
+            _ => None,
+        }
+    }
+
+    /// Is the caret visible at the moment (the field is focused and the caret isn't blinked
+    /// out)?
+    pub fn caret_visible(&self) -> bool {
+        self.focused && (self.blink / CARET_BLINK_MS).is_multiple_of(2)
+    }
+
+    /// Process a frame of input. `dt` is the time since the previous frame in milliseconds.
+    pub fn update(&mut self, fontdat: &Fontdat, input: &Input, dt: u32) {
+        self.blink = self.blink.wrapping_add(dt);
+        if input.mouse_button_pressed(MouseButton::Left) {
+            let position = input.mouse_position;
+            if self.rect.contains(position.x, position.y) {
+                self.set_focused(true);
+                self.caret = self.index_at(fontdat, position.x - self.rect.left);
+            } else {
+                self.set_focused(false);
+            }
+        }
+        if !self.focused {
+            return;
+        }
+
+        let shift = input.modifiers.shift;
+        for scancode in &input.keys_typed {
+            match scancode {
+                Scancode::Left => match self.selection() {
+                    Some(selection) if !shift => self.move_caret(selection.start, false),
+                    _ => self.move_caret(self.caret.saturating_sub(1), shift),
+                },
+                Scancode::Right => match self.selection() {
+                    Some(selection) if !shift => self.move_caret(selection.end, false),
+                    _ => self.move_caret(cmp::min(self.caret + 1, self.text.len()), shift),
+                },
+                Scancode::Home => self.move_caret(0, shift),
+                Scancode::End => self.move_caret(self.text.len(), shift),
+                Scancode::Backspace => {
+                    if !self.delete_selection() && self.caret > 0 {
+                        self.caret -= 1;
+                        self.text.remove(self.caret);
+                    }
+                }
+                Scancode::Delete => {
+                    if !self.delete_selection() && self.caret < self.text.len() {
+                        self.text.remove(self.caret);
+                    }
+                }
+                Scancode::A if input.modifiers.ctrl => {
+                    self.anchor = Some(0);
+                    self.caret = self.text.len();
+                }
+                _ => continue,
+            }
+            self.blink = 0;
+        }
+        if !input.text.is_empty() {
+            self.delete_selection();
+            self.insert(fontdat, &input.text);
+            self.blink = 0;
+        }
+    }
+
+    /// Draw the field with its text, selection and caret.
+    pub fn draw(&self, fontdat: &Fontdat, image: &mut Image13h) {
+        let (x, y) = (self.rect.left, self.rect.top);
+        let offset =
+            |index: usize| fontdat.text_width(&self.text[..index].iter().collect::<String>());
+        if let Some(selection) = self.selection() {
+            let (start, end) = (offset(selection.start), offset(selection.end));
+            image.fill_rect(
+                &Rect::from_ranges(x + start..x + end, y..self.rect.beyond_bottom()),
+                self.selection_color,
+            );
+        }
+        fontdat.draw_text(image, &self.text(), x as isize, y as isize);
+        if self.caret_visible() {
+            let caret_x = x + offset(self.caret);
+            image.fill_rect(
+                &Rect::from_ranges(caret_x..caret_x + 1, y..self.rect.beyond_bottom()),
+                self.caret_color,
+            );
+        }
+    }
+
+    fn move_caret(&mut self, caret: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.caret);
+        } else {
+            self.anchor = None;
+        }
+        self.caret = caret;
+    }
+
+    /// Delete the selected characters, returns false if nothing was selected.
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some(selection) => {
+                self.text.drain(selection.clone());
+                self.caret = selection.start;
+                self.anchor = None;
+                true
+            }
+            None => {
+                self.anchor = None;
+                false
+            }
+        }
+    }
+
+    /// Insert text at the caret, skipping characters that can't be drawn or don't fit.
+    fn insert(&mut self, fontdat: &Fontdat, text: &str) {
+        let mut width = fontdat.text_width(&self.text());
+        for c in text.chars() {
+            if let Some(character_width) = fontdat.character_width(c) {
+                if width + character_width <= self.max_width() {
+                    self.text.insert(self.caret, c);
+                    self.caret += 1;
+                    width += character_width;
+                }
+            }
+        }
+    }
+
+    /// The caret position closest to `x` pixels from the left edge of the field.
+    fn index_at(&self, fontdat: &Fontdat, x: usize) -> usize {
+        let mut offset = 0;
+        for (index, c) in self.text.iter().enumerate() {
+            let width = fontdat.character_width(*c).unwrap_or(0);
+            if x < offset + width / 2 {
+                return index;
+            }
+            offset += width;
+        }
+        self.text.len()
+    }
+}
+
+impl Widget for TextField {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn update(
+        &mut self,
+        fontdat: &Fontdat,
+        input: &Input,
+        dt: u32,
+        focused: bool,
+    ) -> Option<Response> {
+        if focused != self.focused {
+            self.set_focused(focused);
+        }
+        let text = self.text.clone();
+        TextField::update(self, fontdat, input, dt);
+        if self.text != text {
+            Some(Response::Changed)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, skin: &Skin, image: &mut Image13h, _focused: bool) {
+        TextField::draw(self, skin.fontdat, image);
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fontdat::{self, Fontdat};
+    use crate::image13h::Image13h;
+
+    use crate::input::{Input, MousePosition};
+    use crate::ui::textfield::{TextField, CARET_BLINK_MS};
+    use sdl2::keyboard::Scancode;
+    use sdl2::mouse::MouseButton;
+
+    fn typed(text: &str) -> Input {
+        let mut input = Input::idle(MousePosition::new(0, 0));
+        input.text = text.to_string();
+        input
+    }
+
+    fn keys(keys: &[Scancode], shift: bool) -> Input {
+        let mut input = Input::idle(MousePosition::new(0, 0));
+        input.keys_typed = keys.to_vec();
+        input.modifiers.shift = shift;
+        input
+    }
+
+    fn focused_field(fontdat: &Fontdat, text: &str) -> TextField {
+        let mut field = TextField::new(10, 10, 100);
+        field.set_focused(true);
+        field.set_text(fontdat, text);
+        field
+    }
+
+    #[test]
+    fn test_typing_works() {
+        let fontdat = Fontdat::empty();
+        let mut field = focused_field(&fontdat, "");
+        field.update(&fontdat, &typed("Zażółć"), 0);
+        field.update(&fontdat, &typed("{}"), 0);
+        assert_eq!(field.text(), "Zażółć");
+        assert_eq!(field.caret(), 6);
+
+        let mut unfocused = TextField::new(10, 10, 100);
+        unfocused.update(&fontdat, &typed("abc"), 0);
+        assert_eq!(unfocused.text(), "");
+    }
+
+    #[test]
+    fn test_text_is_limited_by_width() {
+        let fontdat = Fontdat::empty();
+        // "W" is 11 pixels wide.
+        let mut field = TextField::new(0, 0, 25);
+        field.set_text(&fontdat, "WWWi");
+        assert_eq!(field.text(), "WWi");
+        assert!(fontdat.text_width(&field.text()) <= field.max_width());
+    }
+
+    #[test]
+    fn test_extra_glyphs_are_measured() {
+        let mut fontdat = Fontdat::empty();
+        fontdat.set_extra_glyph('€', Image13h::empty(15, fontdat::CHARACTER_HEIGHT));
+        let mut field = TextField::new(0, 0, 25);
+        field.set_text(&fontdat, "€€a");
+        assert_eq!(field.text(), "€a");
+        let mut click = Input::idle(MousePosition::new(10, 2));
+        click.mouse_buttons.pressed.insert(MouseButton::Left);
+        field.update(&fontdat, &click, 0);
+        assert_eq!(field.caret(), 1);
+    }
+
+    #[test]
+    fn test_editing_keys_work() {
+        let fontdat = Fontdat::empty();
+        let mut field = focused_field(&fontdat, "abcd");
+        field.update(&fontdat, &keys(&[Scancode::Backspace], false), 0);
+        assert_eq!(field.text(), "abc");
+        field.update(
+            &fontdat,
+            &keys(&[Scancode::Home, Scancode::Delete], false),
+            0,
+        );
+        assert_eq!(field.text(), "bc");
+        assert_eq!(field.caret(), 0);
+        field.update(&fontdat, &keys(&[Scancode::End, Scancode::Left], false), 0);
+        assert_eq!(field.caret(), 1);
+        field.update(&fontdat, &typed("x"), 0);
+        assert_eq!(field.text(), "bxc");
+    }
+
+    #[test]
+    fn test_selection_works() {
+        let fontdat = Fontdat::empty();
+        let mut field = focused_field(&fontdat, "abcd");
+        field.update(&fontdat, &keys(&[Scancode::Left, Scancode::Left], true), 0);
+        assert_eq!(field.selection(), Some(2..4));
+        field.update(&fontdat, &typed("X"), 0);
+        assert_eq!(field.text(), "abX");
+        assert_eq!(field.selection(), None);
+
+        field.update(&fontdat, &keys(&[Scancode::Home], true), 0);
+        assert_eq!(field.selection(), Some(0..3));
+        field.update(&fontdat, &keys(&[Scancode::Right], false), 0);
+        assert_eq!(field.selection(), None);
+        assert_eq!(field.caret(), 3);
+
+        let mut select_all = keys(&[Scancode::A], false);
+        select_all.modifiers.ctrl = true;
+        field.update(&fontdat, &select_all, 0);
+        field.update(&fontdat, &keys(&[Scancode::Delete], false), 0);
+        assert_eq!(field.text(), "");
+    }
+
+    #[test]
+    fn test_clicking_focuses_and_places_caret() {
+        let fontdat = Fontdat::empty();
+        let mut field = TextField::new(10, 10, 100);
+        field.set_text(&fontdat, "abc");
+        let mut click = Input::idle(MousePosition::new(17, 12));
+        click.mouse_buttons.pressed.insert(MouseButton::Left);
+        field.update(&fontdat, &click, 0);
+        assert!(field.is_focused());
+        // "a" is 6 pixels wide, so 7 pixels from the left edge is closest to the boundary
+        // between "a" and "b".
+        assert_eq!(field.caret(), 1);
+
+        let mut click = Input::idle(MousePosition::new(0, 0));
+        click.mouse_buttons.pressed.insert(MouseButton::Left);
+        field.update(&fontdat, &click, 0);
+        assert!(!field.is_focused());
+    }
+
+    #[test]
+    fn test_caret_blinks() {
+        let fontdat = Fontdat::empty();
+        let mut field = focused_field(&fontdat, "");
+        assert!(field.caret_visible());
+        field.update(&fontdat, &typed(""), CARET_BLINK_MS);
+        assert!(!field.caret_visible());
+        field.update(&fontdat, &typed("a"), 0);
+        assert!(field.caret_visible());
+    }
+
+    #[test]
+    fn test_drawing_works() {
+        let mut fontdat = Fontdat::empty();
+        for i in 0..fontdat::CHARACTERS {
+            fontdat.glyph_mut(i).fill(7);
+        }
+        let mut field = focused_field(&fontdat, "ab");
+        field.caret_color = 3;
+        field.update(&fontdat, &keys(&[Scancode::Left], true), 0);
+        let mut image = Image13h::empty(120, 30);
+        field.draw(&fontdat, &mut image);
+        let line = image.line(15);
+        assert_eq!(line[10], 7);
+        // The selected "b" is drawn over the selection background, the caret is right before it.
+        assert_eq!(line[16], 3);
+        assert_eq!(line[22], 0);
+    }
+}
@@ -0,0 +1,323 @@
+//! Units: the kinds of units, their stats and the storage of the units in a game.
+//!
+//! This is a part of the game simulation, which doesn't depend on SDL or on the wall clock: it
+//! advances in ticks of `TICK_MS` milliseconds and, given the same input, always ends up in the
+//! same state. Units are kept ordered by their IDs, so iterating over them is deterministic too.
+//!
+//! # Status
+//!
+//! Only the storage and the table format are done: units with stable IDs, owners, positions and
+//! orders, and stats loaded from a table. The unit kinds and the built-in stats are placeholders,
+//! not the ones of the original game: which kinds the original has and what their stats are
+//! hasn't been worked out yet. `UnitKind` and `tables/units.cfg` are to be replaced once they
+//! have.
+//!
+//! # Unit table format
+//!
+//! The per-kind stats come from a table, the built-in one is `tables/units.cfg`. Every line
+//! holds the stats of a kind as whitespace-separated columns: the kind (see `UnitKind::name()`),
+//! hit points, speed (in thousandths of a cell per tick), damage per hit, range (in cells),
+//! sight (in cells) and cost (in wood). Empty lines and lines starting with `#` are ignored.
+//! Every kind has to be listed exactly once. Example:
+//!
+//! ```text
+//! # kind     hit-points  speed  damage  range  sight  cost
+//! peasant    30          100    2       1      4      50
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+
+/// How much game time a simulation tick stands for, in milliseconds.
+pub const TICK_MS: u32 = 50;
+
+/// The built-in unit table.
+const BUILTIN_TABLE: &str = include_str!("../tables/units.cfg");
+
+/// The kinds of units. Placeholders, see the module documentation.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum UnitKind {
+    Peasant,
+    Warrior,
+    Archer,
+    Horseman,
+}
+
+pub const UNIT_KINDS: [UnitKind; 4] = [
+    UnitKind::Peasant,
+    UnitKind::Warrior,
+    UnitKind::Archer,
+    UnitKind::Horseman,
+];
+
+impl UnitKind {
+    /// The name used in the unit table.
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitKind::Peasant => "peasant",
+            UnitKind::Warrior => "warrior",
+            UnitKind::Archer => "archer",
+            UnitKind::Horseman => "horseman",
+        }
+    }
+
+    /// The inverse of `name()`.
+    pub fn from_name(name: &str) -> Option<UnitKind> {
+        UNIT_KINDS.iter().copied().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stats {
+    pub hit_points: u32,
+    /// How far the unit moves in a tick, in thousandths of a cell.
+    pub speed: u32,
+    /// The hit points taken from the target with every hit.
+    pub damage: u32,
+    /// How far (in cells) the unit can hit from.
+    pub range: u32,
+    /// How far (in cells) the unit can see.
+    pub sight: u32,
+    /// How much wood training the unit costs.
+    pub cost: u32,
+}
+
+/// The stats of all unit kinds.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnitTable {
+    stats: BTreeMap<UnitKind, Stats>,
+}
+
+impl UnitTable {
+    /// Load a unit table from a reader.
+    ///
+    /// # Errors
+    /// An error message is returned if the reader can't be read, if a line can't be parsed or if
+    /// a kind is missing or listed more than once.
+    pub fn load<T: io::Read>(reader: T) -> Result<UnitTable, String> {
+        let mut stats = BTreeMap::new();
+        for (number, line) in io::BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {}", number + 1, message);
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            if columns.len() != 7 {
+                return Err(error("expected a kind and 6 numbers"));
+            }
+            let kind = UnitKind::from_name(columns[0])
+                .ok_or_else(|| error(&format!("unknown unit kind {:?}", columns[0])))?;
+            let numbers = columns[1..]
+                .iter()
+                .map(|column| column.parse::<u32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| error(&e.to_string()))?;
+            let kind_stats = Stats {
+                hit_points: numbers[0],
+                speed: numbers[1],
+                damage: numbers[2],
+                range: numbers[3],
+                sight: numbers[4],
+                cost: numbers[5],
+            };
+            if stats.insert(kind, kind_stats).is_some() {
+                return Err(error(&format!("{} listed again", kind.name())));
+            }
+        }
+        if let Some(kind) = UNIT_KINDS.iter().find(|kind| !stats.contains_key(kind)) {
+            return Err(format!("{} is missing", kind.name()));
+        }
+        Ok(UnitTable { stats })
+    }
+
+    pub fn builtin() -> UnitTable {
+        UnitTable::load(BUILTIN_TABLE.as_bytes()).expect("Invalid built-in unit table")
+    }
+
+    pub fn stats(&self, kind: UnitKind) -> &Stats {
+        &self.stats[&kind]
+    }
+}
+
+/// A unit's ID, unique for the whole game: the IDs of removed units aren't reused.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UnitId(pub u32);
+
+/// A player, 0-based.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Player(pub u8);
+
+/// What a unit has been told to do.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Order {
+    Idle,
+    /// Go to a cell.
+    Move {
+        target: (usize, usize),
+    },
+    Attack {
+        target: UnitId,
+    },
+    /// Gather the resource in a cell.
+    Gather {
+        target: (usize, usize),
+    },
+    /// Put up a building with its top left corner in a cell. There are no building kinds yet,
+    /// `building` is the column of the building in the graf.dat building pictures.
+    Build {
+        building: u8,
+        target: (usize, usize),
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unit {
+    pub id: UnitId,
+    pub kind: UnitKind,
+    pub owner: Player,
+    /// The cell the unit is in.
+    pub position: (usize, usize),
+    pub hit_points: u32,
+    pub order: Order,
+}
+
+/// The units of a game.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Units {
+    units: BTreeMap<UnitId, Unit>,
+    next_id: u32,
+}
+
+impl Units {
+    pub fn new() -> Units {
+        Units::default()
+    }
+
+    /// Add an idle unit with full hit points. Returns the new unit's ID.
+    pub fn spawn(
+        &mut self,
+        table: &UnitTable,
+        kind: UnitKind,
+        owner: Player,
+        position: (usize, usize),
+    ) -> UnitId {
+        let id = UnitId(self.next_id);
+        self.next_id += 1;
+        self.units.insert(
+            id,
+            Unit {
+                id,
+                kind,
+                owner,
+                position,
+                hit_points: table.stats(kind).hit_points,
+                order: Order::Idle,
+            },
+        );
+        id
+    }
+
+    /// Remove a unit, returning it (`None` if there's no unit with the ID).
+    pub fn remove(&mut self, id: UnitId) -> Option<Unit> {
+        self.units.remove(&id)
+    }
+
+    pub fn get(&self, id: UnitId) -> Option<&Unit> {
+        self.units.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: UnitId) -> Option<&mut Unit> {
+        self.units.get_mut(&id)
+    }
+
+    /// All units, ordered by their IDs.
+    pub fn iter(&self) -> impl Iterator<Item = &Unit> {
+        self.units.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Unit> {
+        self.units.values_mut()
+    }
+
+    /// The units of a player, ordered by their IDs.
+    pub fn owned_by(&self, owner: Player) -> impl Iterator<Item = &Unit> {
+        self.iter().filter(move |unit| unit.owner == owner)
+    }
+
+    /// The unit in a cell, the one with the lowest ID if there are more of them.
+    pub fn at(&self, position: (usize, usize)) -> Option<&Unit> {
+        self.iter().find(|unit| unit.position == position)
+    }
+
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::units::{Order, Player, UnitKind, UnitTable, Units, UNIT_KINDS};
+
+    #[test]
+    fn test_builtin_table_is_complete() {
+        let table = UnitTable::builtin();
+        for kind in UNIT_KINDS.iter().copied() {
+            assert!(table.stats(kind).hit_points > 0);
+            assert_eq!(UnitKind::from_name(kind.name()), Some(kind));
+        }
+        assert!(table.stats(UnitKind::Archer).range > table.stats(UnitKind::Warrior).range);
+    }
+
+    #[test]
+    fn test_table_errors_are_reported() {
+        assert_eq!(
+            UnitTable::load("peasant 1 2 3".as_bytes()),
+            Err("Line 1: expected a kind and 6 numbers".to_string())
+        );
+        assert_eq!(
+            UnitTable::load("\nknight 1 2 3 4 5 6".as_bytes()),
+            Err("Line 2: unknown unit kind \"knight\"".to_string())
+        );
+        assert_eq!(
+            UnitTable::load("peasant 1 2 3 4 5 x".as_bytes()),
+            Err("Line 1: invalid digit found in string".to_string())
+        );
+        assert_eq!(
+            UnitTable::load("peasant 1 2 3 4 5 6".as_bytes()),
+            Err("warrior is missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_units_work() {
+        let table = UnitTable::builtin();
+        let mut units = Units::new();
+        let peasant = units.spawn(&table, UnitKind::Peasant, Player(0), (1, 2));
+        let archer = units.spawn(&table, UnitKind::Archer, Player(1), (3, 2));
+        assert_eq!(units.len(), 2);
+        assert_eq!(
+            units.get(archer).unwrap().hit_points,
+            table.stats(UnitKind::Archer).hit_points
+        );
+        assert_eq!(units.at((1, 2)).unwrap().id, peasant);
+        assert_eq!(units.owned_by(Player(1)).count(), 1);
+
+        units.get_mut(peasant).unwrap().order = Order::Move { target: (5, 5) };
+        assert_eq!(
+            units.remove(peasant).unwrap().order,
+            Order::Move { target: (5, 5) }
+        );
+        assert_eq!(units.get(peasant), None);
+        // IDs aren't reused.
+        let warrior = units.spawn(&table, UnitKind::Warrior, Player(0), (1, 2));
+        assert!(warrior > archer);
+        let ids: Vec<_> = units.iter().map(|unit| unit.id).collect();
+        assert_eq!(ids, vec![archer, warrior]);
+    }
+}
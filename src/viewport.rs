@@ -0,0 +1,357 @@
+//! The map viewport: the part of the map shown in `hud::VIEWPORT`, and the minimap.
+//!
+//! Positions on the map ("world" positions) are measured in pixels from the top left corner of
+//! the map, a cell is `map::TILE_WIDTH`x`map::TILE_HEIGHT` pixels. The viewport scrolls by
+//! pixels, so the tiles on its edges are often only partially visible.
+//!
+//! The map scrolls with the scroll actions (the arrow keys by default), with the mouse cursor at
+//! the screen edge (see `cursor::scroll_direction()`) and by clicking (or dragging) in the
+//! minimap, which centers the viewport on the clicked spot.
+
+use crate::actions::{Action, Bindings};
+use crate::cursor::{self, ScrollDirection};
+use crate::grafdat::Grafdat;
+use crate::hud::{MINIMAP, VIEWPORT};
+use crate::image13h::{Image13h, Rect};
+use crate::input::{Input, MousePosition};
+use crate::map::{Map, TILE_HEIGHT, TILE_WIDTH};
+use sdl2::mouse::MouseButton;
+use std::cmp;
+
+/// How fast the map scrolls, in pixels per second.
+pub const SCROLL_SPEED: u32 = 160;
+
+/// The color the parts of the viewport and the minimap without map are filled with.
+const BACKGROUND: u8 = 0;
+
+/// The color of the outline marking the viewport on the minimap.
+// TODO Check what the original game uses, 15 is white in the in-game palette.
+const MINIMAP_VIEW_COLOR: u8 = 15;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Viewport {
+    /// The world position of the top left corner of the viewport.
+    x: usize,
+    y: usize,
+    /// The map size in pixels.
+    world_width: usize,
+    world_height: usize,
+    /// Scrolling progress not large enough to move by a pixel yet, in thousandths of a pixel.
+    scroll_remainder: u32,
+}
+
+impl Viewport {
+    /// A viewport showing the top left corner of `map`.
+    pub fn new(map: &Map) -> Viewport {
+        Viewport {
+            x: 0,
+            y: 0,
+            world_width: map.width() * TILE_WIDTH,
+            world_height: map.height() * TILE_HEIGHT,
+            scroll_remainder: 0,
+        }
+    }
+
+    /// The world position of the top left corner of the viewport.
+    pub fn position(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// Move the top left corner of the viewport to a world position. The viewport stays within
+    /// the map, positions beyond it are clamped.
+    pub fn scroll_to(&mut self, x: isize, y: isize) {
+        let max_x = self.world_width.saturating_sub(VIEWPORT.width);
+        let max_y = self.world_height.saturating_sub(VIEWPORT.height);
+        self.x = cmp::min(cmp::max(x, 0) as usize, max_x);
+        self.y = cmp::min(cmp::max(y, 0) as usize, max_y);
+    }
+
+    pub fn scroll_by(&mut self, dx: isize, dy: isize) {
+        self.scroll_to(self.x as isize + dx, self.y as isize + dy);
+    }
+
+    /// Scroll so that the world position is in the center of the viewport (or as close to it as
+    /// the map edges allow).
+    pub fn center_on(&mut self, x: usize, y: usize) {
+        self.scroll_to(
+            x as isize - (VIEWPORT.width / 2) as isize,
+            y as isize - (VIEWPORT.height / 2) as isize,
+        );
+    }
+
+    /// The screen position of a world position. The result is outside of `hud::VIEWPORT` (and
+    /// possibly negative) if the position isn't visible.
+    pub fn world_to_screen(&self, x: usize, y: usize) -> (isize, isize) {
+        (
+            x as isize - self.x as isize + VIEWPORT.left as isize,
+            y as isize - self.y as isize + VIEWPORT.top as isize,
+        )
+    }
+
+    /// The world position shown at a screen position, `None` if the screen position is outside
+    /// of the viewport or there's no map there.
+    pub fn screen_to_world(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if !VIEWPORT.contains(x, y) {
+            return None;
+        }
+        let world = (x - VIEWPORT.left + self.x, y - VIEWPORT.top + self.y);
+        if world.0 < self.world_width && world.1 < self.world_height {
+            Some(world)
+        } else {
+            None
+        }
+    }
+
+    /// The map cell shown at a screen position, `None` if there's none.
+    pub fn screen_to_cell(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        self.screen_to_world(x, y)
+            .map(|(x, y)| (x / TILE_WIDTH, y / TILE_HEIGHT))
+    }
+
+    /// Scroll according to the input of a frame that took `dt` milliseconds.
+    pub fn update(&mut self, map: &Map, input: &Input, bindings: &Bindings, dt: u32) {
+        let position = input.mouse_position;
+        if input.mouse_buttons.is_held(MouseButton::Left)
+            && MINIMAP.contains(position.x, position.y)
+        {
+            if let Some((x, y)) = minimap_to_world(map, position) {
+                self.center_on(x, y);
+            }
+        }
+
+        let (mut dx, mut dy) = match cursor::scroll_direction(position) {
+            Some(direction) => direction_vector(direction),
+            None => (0, 0),
+        };
+        for (action, (x, y)) in [
+            (Action::ScrollUp, (0, -1)),
+            (Action::ScrollDown, (0, 1)),
+            (Action::ScrollLeft, (-1, 0)),
+            (Action::ScrollRight, (1, 0)),
+        ]
+        .iter()
+        .copied()
+        {
+            if bindings.is_active(input, action) {
+                dx += x;
+                dy += y;
+            }
+        }
+        let (dx, dy) = (dx.signum(), dy.signum());
+        if dx == 0 && dy == 0 {
+            self.scroll_remainder = 0;
+            return;
+        }
+        let distance = self.scroll_remainder + SCROLL_SPEED * dt;
+        self.scroll_remainder = distance % 1000;
+        let pixels = (distance / 1000) as isize;
+        self.scroll_by(dx * pixels, dy * pixels);
+    }
+
+    /// Draw the visible part of the map into `hud::VIEWPORT` of `image`.
+    pub fn draw(&self, grafdat: &Grafdat, map: &Map, image: &mut Image13h) {
+        image.fill_rect(&VIEWPORT, BACKGROUND);
+        let columns = self.x / TILE_WIDTH
+            ..cmp::min((self.x + VIEWPORT.width).div_ceil(TILE_WIDTH), map.width());
+        let rows = self.y / TILE_HEIGHT
+            ..cmp::min(
+                (self.y + VIEWPORT.height).div_ceil(TILE_HEIGHT),
+                map.height(),
+            );
+        for row in rows {
+            for column in columns.clone() {
+                let tile = map.get(column, row).unwrap().tile;
+                let (x, y) = self.world_to_screen(column * TILE_WIDTH, row * TILE_HEIGHT);
+                let (offset_x, offset_y) = tile.offset();
+                image.blit_within(
+                    grafdat.picture(tile.picture()),
+                    x - offset_x as isize,
+                    y - offset_y as isize,
+                    &visible_part(x, y),
+                );
+            }
+        }
+    }
+
+    /// Draw the minimap into `hud::MINIMAP` of `image`, with the part of the map shown in the
+    /// viewport outlined.
+    pub fn draw_minimap(&self, grafdat: &Grafdat, map: &Map, image: &mut Image13h) {
+        image.fill_rect(&MINIMAP, BACKGROUND);
+        let area = minimap_area(map);
+        for y in 0..area.height {
+            for x in 0..area.width {
+                let cell = map
+                    .get(x * map.width() / area.width, y * map.height() / area.height)
+                    .unwrap();
+                // Every cell is represented by the color in the middle of its tile.
+                let (offset_x, offset_y) = cell.tile.offset();
+                let picture = grafdat.picture(cell.tile.picture());
+                let color = picture.line(offset_y + TILE_HEIGHT / 2)[offset_x + TILE_WIDTH / 2];
+                image.set_pixel_clipped((area.left + x) as isize, (area.top + y) as isize, color);
+            }
+        }
+        if self.world_width == 0 || self.world_height == 0 {
+            return;
+        }
+        let to_minimap_x = |x: usize| area.left + x * area.width / self.world_width;
+        let to_minimap_y = |y: usize| area.top + y * area.height / self.world_height;
+        let view = Rect::from_ranges(
+            to_minimap_x(self.x)..to_minimap_x(cmp::min(self.x + VIEWPORT.width, self.world_width)),
+            to_minimap_y(self.y)
+                ..to_minimap_y(cmp::min(self.y + VIEWPORT.height, self.world_height)),
+        );
+        image.draw_rect(&view, MINIMAP_VIEW_COLOR);
+    }
+}
+
+/// The part of `hud::MINIMAP` the map occupies: as much of it as possible with the map's aspect
+/// ratio kept (measured in cells, not pixels), in the top left corner.
+pub fn minimap_area(map: &Map) -> Rect {
+    let (width, height) = (map.width(), map.height());
+    if width == 0 || height == 0 {
+        return Rect::from_ranges(MINIMAP.left..MINIMAP.left, MINIMAP.top..MINIMAP.top);
+    }
+    let (area_width, area_height) = if width * MINIMAP.height >= height * MINIMAP.width {
+        (MINIMAP.width, cmp::max(1, MINIMAP.width * height / width))
+    } else {
+        (cmp::max(1, MINIMAP.height * width / height), MINIMAP.height)
+    };
+    Rect {
+        left: MINIMAP.left,
+        top: MINIMAP.top,
+        width: area_width,
+        height: area_height,
+    }
+}
+
+/// The world position shown at a screen position within the minimap, `None` if the position
+/// isn't within the part of the minimap the map occupies.
+pub fn minimap_to_world(map: &Map, position: MousePosition) -> Option<(usize, usize)> {
+    let area = minimap_area(map);
+    if !area.contains(position.x, position.y) {
+        return None;
+    }
+    Some((
+        (position.x - area.left) * map.width() * TILE_WIDTH / area.width,
+        (position.y - area.top) * map.height() * TILE_HEIGHT / area.height,
+    ))
+}
+
+fn direction_vector(direction: ScrollDirection) -> (isize, isize) {
+    match direction {
+        ScrollDirection::Up => (0, -1),
+        ScrollDirection::UpRight => (1, -1),
+        ScrollDirection::Right => (1, 0),
+        ScrollDirection::DownRight => (1, 1),
+        ScrollDirection::Down => (0, 1),
+        ScrollDirection::DownLeft => (-1, 1),
+        ScrollDirection::Left => (-1, 0),
+        ScrollDirection::UpLeft => (-1, -1),
+    }
+}
+
+/// The part of the viewport covered by the tile drawn at the screen position `x`, `y`.
+fn visible_part(x: isize, y: isize) -> Rect {
+    let clamp = |value: isize, lower: usize, upper: usize| {
+        cmp::min(cmp::max(value, lower as isize) as usize, upper)
+    };
+    let (left, right) = (VIEWPORT.left, VIEWPORT.beyond_right());
+    let (top, bottom) = (VIEWPORT.top, VIEWPORT.beyond_bottom());
+    Rect::from_ranges(
+        clamp(x, left, right)..clamp(x + TILE_WIDTH as isize, left, right),
+        clamp(y, top, bottom)..clamp(y + TILE_HEIGHT as isize, top, bottom),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, Bindings};
+    use crate::grafdat::Grafdat;
+    use crate::hud::{MINIMAP, VIEWPORT};
+    use crate::image13h::Image13h;
+    use crate::input::{Input, MousePosition};
+    use crate::map::{Map, Terrain, Tile, TILE_HEIGHT, TILE_WIDTH};
+    use crate::viewport::{minimap_area, minimap_to_world, Viewport, SCROLL_SPEED};
+    use sdl2::mouse::MouseButton;
+
+    fn map() -> Map {
+        Map::new(40, 20, Tile::new(Terrain::Grass, 0))
+    }
+
+    #[test]
+    fn test_coordinate_conversions_work() {
+        let mut viewport = Viewport::new(&map());
+        viewport.scroll_to(20, 30);
+        assert_eq!(viewport.position(), (20, 30));
+        assert_eq!(viewport.world_to_screen(20, 30), (11, 8));
+        assert_eq!(viewport.world_to_screen(0, 0), (-9, -22));
+        assert_eq!(viewport.screen_to_world(11, 8), Some((20, 30)));
+        assert_eq!(viewport.screen_to_world(10, 8), None);
+        assert_eq!(viewport.screen_to_cell(11 + 12, 8), Some((2, 2)));
+
+        // The viewport doesn't leave the map.
+        viewport.scroll_to(-5, 1000);
+        assert_eq!(viewport.position(), (0, 20 * TILE_HEIGHT - VIEWPORT.height));
+        viewport.center_on(40 * TILE_WIDTH, 0);
+        assert_eq!(viewport.position().0, 40 * TILE_WIDTH - VIEWPORT.width);
+    }
+
+    #[test]
+    fn test_scrolling_works() {
+        let map = map();
+        let mut viewport = Viewport::new(&map);
+        let bindings = Bindings::defaults();
+        let mut input = Input::idle(MousePosition::new(100, 100));
+        let right = bindings.keys(Action::ScrollRight)[0];
+        input.keys.held.insert(right);
+        // Scrolling by less than a pixel per frame adds up.
+        for _ in 0..3 {
+            viewport.update(&map, &input, &bindings, 1000 / SCROLL_SPEED / 2);
+        }
+        assert_eq!(viewport.position(), (1, 0));
+
+        // The mouse at the bottom edge of the screen scrolls down.
+        let input = Input::idle(MousePosition::new(100, 199));
+        viewport.update(&map, &input, &bindings, 100);
+        assert_eq!(viewport.position(), (1, 16));
+
+        // Clicking the minimap centers the viewport.
+        let area = minimap_area(&map);
+        let mut input = Input::idle(MousePosition::new(area.left, area.top));
+        input.mouse_buttons.held.insert(MouseButton::Left);
+        viewport.update(&map, &input, &bindings, 0);
+        assert_eq!(viewport.position(), (0, 0));
+        assert_eq!(
+            minimap_to_world(&map, MousePosition::new(MINIMAP.left + 21, MINIMAP.top)),
+            Some((20 * TILE_WIDTH, 0))
+        );
+        assert_eq!(area.width, MINIMAP.width);
+        assert_eq!(area.height, MINIMAP.height / 2);
+    }
+
+    #[test]
+    fn test_drawing_clips_to_the_viewport() {
+        let map = Map::new(2, 2, Tile::new(Terrain::Trees, 1));
+        let mut grafdat = Grafdat::empty();
+        let mut images = grafdat.to_images();
+        // Paint the tree picture the tile is taken from (its right half).
+        images[3].fill_rect(
+            &crate::image13h::Rect::from_ranges(11..43, 8 + 8 * 14..8 + 9 * 14),
+            7,
+        );
+        let mut data = Vec::new();
+        Grafdat::save_images(&images, &mut data);
+        grafdat = Grafdat::load(&data[..]).unwrap();
+
+        let mut viewport = Viewport::new(&map);
+        viewport.scroll_to(5, 5);
+        let mut screen = Image13h::filled_with_color(320, 200, 1);
+        viewport.draw(&grafdat, &map, &mut screen);
+        assert_eq!(screen.line(VIEWPORT.top)[VIEWPORT.left], 7);
+        // The map is smaller than the viewport, the rest of it is cleared.
+        assert_eq!(screen.line(VIEWPORT.top)[VIEWPORT.left + 2 * TILE_WIDTH], 0);
+        // Nothing is drawn outside of the viewport.
+        assert_eq!(screen.line(VIEWPORT.top - 1)[VIEWPORT.left], 1);
+        assert_eq!(screen.line(VIEWPORT.top)[VIEWPORT.left - 1], 1);
+    }
+}